@@ -0,0 +1,227 @@
+//! Benchmarks for evaluating conditions and running link actions, on games built directly from
+//! in-memory types rather than parsed from story files.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use storygamer::app::Game;
+use storygamer::parser::Metadata;
+use storygamer::types::{
+    ComparisonOp, Condition, InventoryLimit, InventoryOverflow, ItemDef, LimitMode, Link,
+    LinkAction, LinkDest, Operation, Page, Variable, VariableDef,
+};
+
+/// The number of links followed in each iteration of a benchmark.
+const FOLLOWS: usize = 100;
+
+fn metadata() -> Metadata {
+    Metadata {
+        title: "Benchmark".to_owned(),
+        author: None,
+        version: None,
+    }
+}
+
+/// Creates two pages, `a` and `b`, that link to each other with `link`, whose destination is
+/// replaced with the other page.
+fn page_pair(link: Link) -> Rc<RefCell<Page>> {
+    let page = |id: &str| {
+        Rc::new(RefCell::new(Page::new(
+            id.to_owned(),
+            None,
+            "",
+            None,
+            vec![],
+        )))
+    };
+    let (a, b) = (page("a"), page("b"));
+    for (from, to) in [(&a, &b), (&b, &a)].iter() {
+        from.borrow_mut().links.push(Link {
+            dest: LinkDest::to_page(to),
+            ..link.clone()
+        });
+    }
+    a
+}
+
+/// Creates a game starting at `starting_page`, which keeps a short history so that following
+/// links doesn't use more memory as the benchmark goes on.
+fn new_game(
+    starting_page: &Rc<RefCell<Page>>,
+    variable_defs: &HashMap<String, VariableDef>,
+    item_defs: &HashMap<String, ItemDef>,
+) -> Game {
+    let mut game = Game::with_seed(
+        metadata(),
+        starting_page,
+        variable_defs,
+        item_defs,
+        &HashMap::new(),
+        &[],
+        0,
+    );
+    game.history_limit = Some(10);
+    game
+}
+
+fn follow_links(game: &mut Game) {
+    for _ in 0..FOLLOWS {
+        game.follow_link(0).unwrap();
+    }
+}
+
+fn op(name: &str, op: ComparisonOp, value: i32) -> Condition {
+    Condition::Op(Operation {
+        name: name.to_owned(),
+        op,
+        value: Variable::Num(value),
+        value_ref: None,
+        modulo: None,
+    })
+}
+
+/// Returns a true condition nested `depth` levels deep, alternating between `and` and `or`, with
+/// `width` conditions at each level, only the last of which is true in an `or`.
+fn nested_condition(depth: usize, width: usize) -> Condition {
+    if depth == 0 {
+        return op("gold", ComparisonOp::GTE, 10);
+    }
+    let mut children: Vec<_> = (1..width)
+        .map(|_| match depth % 2 {
+            0 => nested_condition(depth - 1, width),
+            _ => Condition::Not(Box::new(nested_condition(depth - 1, width))),
+        })
+        .collect();
+    children.push(nested_condition(depth - 1, width));
+    match depth % 2 {
+        0 => Condition::And(children),
+        _ => Condition::Or(children),
+    }
+}
+
+fn bench_nested_conditions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nested_conditions");
+    let variable_defs: HashMap<_, _> = vec![("gold".to_owned(), Variable::Num(50).into())]
+        .into_iter()
+        .collect();
+    for depth in [1, 4, 8].iter() {
+        let cond = nested_condition(*depth, 2);
+        let link = Link {
+            requires: Some(cond.clone()),
+            ..Link::new("Next", LinkDest::default())
+        };
+        let page = page_pair(link);
+        let mut game = new_game(&page, &variable_defs, &HashMap::new());
+        assert!(game.eval_condition(&cond).unwrap());
+
+        group.bench_with_input(BenchmarkId::new("eval", depth), &cond, |b, cond| {
+            b.iter(|| game.eval_condition(cond).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("follow_link", depth), |b| {
+            b.iter(|| follow_links(&mut game))
+        });
+    }
+    group.finish();
+}
+
+fn item_defs(count: usize) -> HashMap<String, ItemDef> {
+    (0..count)
+        .map(|i| {
+            let name = format!("item{}", i);
+            let def = ItemDef {
+                name: name.clone(),
+                description: None,
+                max_uses: None,
+                effect: LinkAction::ModNum {
+                    name: "gold".to_owned(),
+                    value: 1,
+                },
+            };
+            (name, def)
+        })
+        .collect()
+}
+
+/// Returns a link that acquires every item in `item_defs`.
+fn acquire_all(item_defs: &HashMap<String, ItemDef>) -> Link {
+    Link {
+        actions: item_defs
+            .keys()
+            .map(|name| LinkAction::AcquireItem(name.clone()))
+            .collect(),
+        ..Link::new("Acquire", LinkDest::default())
+    }
+}
+
+fn bench_large_inventories(c: &mut Criterion) {
+    let mut group = c.benchmark_group("large_inventories");
+    let variable_defs: HashMap<_, _> = vec![("gold".to_owned(), Variable::Num(0).into())]
+        .into_iter()
+        .collect();
+    for count in [10, 100, 1000].iter() {
+        let item_defs = item_defs(*count);
+
+        // Conditions on every item held.
+        let cond = Condition::And(
+            item_defs
+                .keys()
+                .map(|name| {
+                    Condition::ItemUses(Operation {
+                        name: name.clone(),
+                        op: ComparisonOp::GT,
+                        value: Variable::Num(0),
+                        value_ref: None,
+                        modulo: None,
+                    })
+                })
+                .collect(),
+        );
+        let link = Link {
+            requires: Some(cond),
+            ..Link::new("Next", LinkDest::default())
+        };
+        let mut game = new_game(&page_pair(link), &variable_defs, &item_defs);
+        let acquire = page_pair(acquire_all(&item_defs));
+        game.current_page = Rc::clone(&acquire);
+        game.follow_link(0).unwrap();
+        game.current_page = Rc::clone(&game.starting_page);
+        group.bench_function(BenchmarkId::new("item_conditions", count), |b| {
+            b.iter(|| follow_links(&mut game))
+        });
+
+        // Acquiring and using items with a full inventory, which drops the oldest item to make
+        // room for each new one.
+        let link = Link {
+            actions: item_defs
+                .keys()
+                .take(10)
+                .flat_map(|name| {
+                    vec![
+                        LinkAction::AcquireItem(name.clone()),
+                        LinkAction::UseItem(name.clone()),
+                    ]
+                })
+                .collect(),
+            ..Link::new("Next", LinkDest::default())
+        };
+        let mut game = new_game(&page_pair(link), &variable_defs, &item_defs);
+        game.inventory_limit = Some(InventoryLimit {
+            max: *count,
+            mode: LimitMode::Items,
+            overflow: InventoryOverflow::DropOldest,
+        });
+        game.current_page = Rc::clone(&acquire);
+        game.follow_link(0).unwrap();
+        game.current_page = Rc::clone(&game.starting_page);
+        group.bench_function(BenchmarkId::new("acquire_and_use", count), |b| {
+            b.iter(|| follow_links(&mut game))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_nested_conditions, bench_large_inventories);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+//! Benchmarks for parsing story files, serially and in parallel, on synthetic stories with many
+//! files.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use storygamer::parser::{bench, Settings};
+
+/// The number of pages in each story file.
+const PAGES_PER_FILE: usize = 10;
+
+/// Writes a story with `files` story files to a new temporary directory, returning its settings.
+fn write_story(files: usize) -> Settings {
+    let dir = env::temp_dir().join(format!("storygamer-bench-{}-{}", process::id(), files));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut page_ids = Vec::new();
+    for file in 0..files {
+        let mut content = String::from("author: \"Anonymous\"\ntags: [bench]\n");
+        for page in 0..PAGES_PER_FILE {
+            let page_id = format!("page-{}-{}", file, page);
+            let next_id = format!("page-{}-{}", file, (page + 1) % PAGES_PER_FILE);
+            content.push_str(&format!(
+                r#"---
+id: {id}
+content: |
+  You stand at a crossroads in **{id}**. The road north leads to {next}, and the road south
+  leads back the way you came. A signpost, weathered by years of rain, points in both
+  directions at once. {{{{name}}}} considers the choice carefully.
+links:
+  - text: "Go north"
+    dest: {{ page: {next} }}
+    requires: {{ and: ["gold >= 1", {{ not: {{ has-item: key }} }}] }}
+    actions:
+      - mod-num: {{ name: gold, value: -1 }}
+      - add-note: "Went north from {id}."
+  - text: "Go south"
+    dest: previous
+"#,
+                id = page_id,
+                next = next_id,
+            ));
+            page_ids.push(page_id);
+        }
+        fs::write(dir.join(format!("file{:04}.yaml", file)), content).unwrap();
+    }
+
+    let settings = format!(
+        r#"title: Bench
+base_dir: {:?}
+entrypoint: file0000.yaml
+pages: [{}]
+variables:
+  name: Traveler
+  gold: 10
+items:
+  key:
+    effect: {{ mod-num: {{ name: gold, value: 1 }} }}
+logger: {{ enabled: false }}
+"#,
+        dir,
+        page_ids.join(", ")
+    );
+    let path: PathBuf = dir.join("Storygame.yaml");
+    fs::write(&path, settings).unwrap();
+    Settings::read(path).unwrap()
+}
+
+/// Compares parsing serially and in parallel for stories with more and more files, to show where
+/// parsing in parallel starts to pay off.
+fn bench_read_pages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_pages");
+    for files in [1, 4, 8, 16, 32, 128].iter() {
+        let settings = write_story(*files);
+        let sources = bench::read_sources(&settings).unwrap();
+        for (name, parallel_min_files) in [("serial", usize::MAX), ("parallel", 1)].iter() {
+            let pages = bench::read_pages(&settings, &sources, *parallel_min_files).unwrap();
+            assert_eq!(pages.len(), files * PAGES_PER_FILE);
+            group.bench_function(BenchmarkId::new(*name, files), |b| {
+                b.iter(|| bench::read_pages(&settings, &sources, *parallel_min_files).unwrap())
+            });
+        }
+        let _ = fs::remove_dir_all(settings.base_dir());
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_read_pages);
+criterion_main!(benches);
@@ -1,44 +1,541 @@
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::iter;
+use std::path::Path;
 use std::rc::{Rc, Weak};
+use std::sync::Arc;
 
+use either::Either::*;
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, JsonValue, Output, RenderContext,
+    RenderError, ScopedJson,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Doctype, Error, Result};
+use crate::parser::{self, LazyPages, Metadata, Settings};
 use crate::types::{
-    ComparisonOp, Condition, Item, ItemDef, Link, LinkAction, LinkDest, LinkTrigger, Operation,
-    Page, Prompt, Variable,
+    ComparisonOp, Condition, CreationStep, Expr, InitialValue, InventoryLimit, InventoryOverflow,
+    Item, ItemDef, LimitMode, Link, LinkAction, LinkDest, LinkTrigger, Note, Operation, Page,
+    PageID, Prompt, TimerDef, VarType, Variable, VariableDef, Version, TURN_COUNT,
 };
-use crate::utils::ConvertBounded;
+use crate::utils::{ConvertBounded, Rng};
 
 pub struct Game {
+    pub metadata: Metadata,
     pub starting_page: Rc<RefCell<Page>>,
     pub current_page: Rc<RefCell<Page>>,
     pub current_link_idx: Option<usize>,
     pub history: Vec<HistoryItem>,
     pub prompt_queue: VecDeque<Prompt>,
+    /// The number of times the player has entered each page they've been to, including the
+    /// current page.
+    pub visit_counts: HashMap<PageID, u32>,
 
     pub variables: HashMap<String, Variable>,
+    pub variable_defs: HashMap<String, VariableDef>,
     pub item_defs: HashMap<String, Rc<ItemDef>>,
+    /// Template helpers, each computing a number from the game's variables.
+    pub helpers: Arc<HashMap<String, Expr>>,
     pub items: HashMap<String, VecDeque<Item>>,
+    /// The limit on how much the player can carry, if any.
+    pub inventory_limit: Option<InventoryLimit>,
+    /// The most entries to keep in `history`, if there's a limit. The oldest entries are dropped
+    /// first.
+    pub history_limit: Option<usize>,
+    /// Timers that count down number variables as the player moves between pages, by variable
+    /// name.
+    pub timers: HashMap<String, TimerDef>,
+    /// Notes added by the story for the player to review, in the order they were added.
+    pub journal: Vec<String>,
+
+    /// The state of the game when the player last entered a checkpoint page.
+    pub last_checkpoint: Option<Snapshot>,
+    /// The character creation sequence, while it is in progress.
+    pub character_creation: Option<CharacterCreation>,
+    /// Every page in the story by ID, if the story was parsed up front, so that pages which no link
+    /// leads to can still be found, e.g. where a timer sends the player.
+    pub pages: HashMap<PageID, Rc<RefCell<Page>>>,
+    /// The source of pages that haven't been parsed yet, if the story is parsed lazily.
+    pub lazy_pages: Option<Rc<RefCell<LazyPages>>>,
+    /// The source of random numbers for actions such as `roll`.
+    rng: Rng,
+    /// The Handlebars registry used to render templates, with the story's helpers registered.
+    registry: Rc<Handlebars<'static>>,
+    /// The number of items acquired so far, used to order [`Item::acquired`].
+    acquisitions: u64,
+    /// The number of prompts popped since the queue was last empty, for showing the player how far
+    /// along a series of prompts they are.
+    prompts_popped: usize,
+    /// The values of the variables when the game started, including random starting values as
+    /// they were rolled, for [`reset_variables`](Self::reset_variables).
+    pub initial_variables: HashMap<String, Variable>,
+    /// The character creation steps to go through when the game starts.
+    creation_steps: Vec<CreationStep>,
 }
 
 impl Game {
+    /// Creates a game starting at `starting_page`.
+    ///
+    /// The pages and definitions don't need to come from the parser, so a game can also be built
+    /// directly from in-memory types, e.g. for benchmarking.
     pub fn new(
+        metadata: Metadata,
         starting_page: &Rc<RefCell<Page>>,
-        variables: &HashMap<String, Variable>,
+        variable_defs: &HashMap<String, VariableDef>,
         item_defs: &HashMap<String, ItemDef>,
+        helpers: &HashMap<String, Expr>,
+        character_creation: &[CreationStep],
     ) -> Self {
-        Game {
+        Self::with_rng(
+            metadata,
+            starting_page,
+            variable_defs,
+            item_defs,
+            helpers,
+            character_creation,
+            Rng::from_entropy(),
+        )
+    }
+
+    /// Creates a game like [`new`](Self::new), but with random numbers generated from `seed`, so
+    /// that the same story and seed always produce the same random outcomes.
+    pub fn with_seed(
+        metadata: Metadata,
+        starting_page: &Rc<RefCell<Page>>,
+        variable_defs: &HashMap<String, VariableDef>,
+        item_defs: &HashMap<String, ItemDef>,
+        helpers: &HashMap<String, Expr>,
+        character_creation: &[CreationStep],
+        seed: u64,
+    ) -> Self {
+        Self::with_rng(
+            metadata,
+            starting_page,
+            variable_defs,
+            item_defs,
+            helpers,
+            character_creation,
+            Rng::new(seed),
+        )
+    }
+
+    /// Creates a game starting at `starting_page`, with the definitions, character creation, and
+    /// random seed (if any) from `settings`.
+    pub fn from_settings(settings: &Settings, starting_page: &Rc<RefCell<Page>>) -> Self {
+        let rng = match settings.seed() {
+            Some(seed) => Rng::new(seed),
+            None => Rng::from_entropy(),
+        };
+        let mut game = Self::with_rng(
+            settings.metadata(),
+            starting_page,
+            settings.variables(),
+            settings.items(),
+            settings.helpers(),
+            settings.character_creation(),
+            rng,
+        );
+        game.inventory_limit = settings.inventory_limit().map(|max| InventoryLimit {
+            max,
+            mode: settings.limit_mode(),
+            overflow: settings.inventory_overflow(),
+        });
+        game.history_limit = settings.history_limit();
+        game.timers = settings.timers().clone();
+        game
+    }
+
+    fn with_rng(
+        metadata: Metadata,
+        starting_page: &Rc<RefCell<Page>>,
+        variable_defs: &HashMap<String, VariableDef>,
+        item_defs: &HashMap<String, ItemDef>,
+        helpers: &HashMap<String, Expr>,
+        character_creation: &[CreationStep],
+        mut rng: Rng,
+    ) -> Self {
+        let variables: HashMap<_, _> = variable_defs
+            .iter()
+            .map(|(name, def)| {
+                let value = match def.initial {
+                    InitialValue::Value(ref value) => value.clone(),
+                    InitialValue::Random { random: (min, max) } => {
+                        let value = rng.gen_range(min..=max);
+                        debug!("init: random({}, {}..={}) = {}", name, min, max, value);
+                        Variable::Num(value)
+                    }
+                };
+                (name.clone(), value)
+            })
+            .collect();
+
+        let mut game = Game {
+            metadata,
             starting_page: Rc::clone(starting_page),
             current_page: Rc::clone(starting_page),
             current_link_idx: None,
             history: Vec::new(),
             prompt_queue: VecDeque::new(),
+            visit_counts: iter::once((starting_page.borrow().id.clone(), 1)).collect(),
             variables: variables.clone(),
+            variable_defs: variable_defs.clone(),
             item_defs: item_defs
                 .clone()
                 .into_iter()
                 .map(|(name, def)| (name, Rc::new(def)))
                 .collect(),
+            helpers: Arc::new(helpers.clone()),
             items: HashMap::new(),
+            inventory_limit: None,
+            history_limit: None,
+            timers: HashMap::new(),
+            journal: Vec::new(),
+            last_checkpoint: None,
+            character_creation: CharacterCreation::new(character_creation),
+            pages: HashMap::new(),
+            lazy_pages: None,
+            rng,
+            registry: Rc::new(template_registry(helpers)),
+            acquisitions: 0,
+            prompts_popped: 0,
+            initial_variables: variables,
+            creation_steps: character_creation.to_vec(),
+        };
+        if game.character_creation.is_none() && starting_page.borrow().checkpoint {
+            game.last_checkpoint = Some(game.snapshot());
+        }
+        game
+    }
+
+    /// Render a Handlebars `template` with the game's variables and helpers.
+    ///
+    /// Besides the story's own helpers, templates can use:
+    ///
+    /// - `{{default <value> <fallback>}}`, which renders `fallback` if `value` is undeclared or
+    ///   empty. Empty string variables with a `render_default` in their declaration render as that
+    ///   instead.
+    /// - `{{item_count <item>}}`, the number of the named item the player holds.
+    /// - `{{has_item <item>}}`, whether the player holds the named item, e.g. in
+    ///   `{{#if (has_item sword)}}You grip your blade.{{/if}}`.
+    /// - `{{visits <page>}}`, the number of times the player has entered the page with the given
+    ///   ID, e.g. in `You've been here {{visits "cave"}} times.`
+    ///
+    /// Item helpers render nothing and log a warning if the item is undeclared.
+    ///
+    /// If rendering fails, the error is logged and `template` is returned as-is.
+    pub fn render_template(&self, template: &str) -> String {
+        #[derive(Serialize)]
+        struct TemplateData<'a> {
+            #[serde(flatten)]
+            variables: BTreeMap<&'a String, Cow<'a, Variable>>,
+            /// The number of each declared item held, under [`TEMPLATE_ITEMS_KEY`].
+            #[serde(rename = "__items")]
+            items: BTreeMap<&'a String, usize>,
+            /// The visit count of each visited page, under [`TEMPLATE_VISITS_KEY`].
+            #[serde(rename = "__visits")]
+            visits: BTreeMap<&'a PageID, u32>,
+        }
+
+        // Sort variables by name so that iterating over them in a template (e.g. with `#each`)
+        // gives the same output on every render.
+        let variables = self
+            .variables
+            .iter()
+            .map(|(name, value)| {
+                let render_default = self
+                    .variable_defs
+                    .get(name)
+                    .and_then(|def| def.render_default.as_ref());
+                match (value, render_default) {
+                    (Variable::Str(s), Some(default)) if s.is_empty() => {
+                        (name, Cow::Owned(Variable::Str(default.clone())))
+                    }
+                    _ => (name, Cow::Borrowed(value)),
+                }
+            })
+            .collect();
+        let items = self
+            .item_defs
+            .keys()
+            .map(|name| (name, self.items.get(name).map_or(0, VecDeque::len)))
+            .collect();
+        let visits = self
+            .visit_counts
+            .iter()
+            .map(|(page_id, count)| (page_id, *count))
+            .collect();
+        let data = TemplateData {
+            variables,
+            items,
+            visits,
+        };
+        match self.registry.render_template(template, &data) {
+            Ok(content) => content,
+            Err(err) => {
+                error!("error rendering template: {}", err);
+                template.to_owned()
+            }
+        }
+    }
+
+    /// Reseed the game's random number generator, so that the same actions produce the same random
+    /// numbers every time, e.g. for testing.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Take a [`Snapshot`] of the current state of the game.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            current_page: Rc::clone(&self.current_page),
+            current_link_idx: self.current_link_idx,
+            history: self.history.clone(),
+            visit_counts: self.visit_counts.clone(),
+            variables: self.variables.clone(),
+            items: self.items.clone(),
+            journal: self.journal.clone(),
+        }
+    }
+
+    /// Restore the game to the state captured by `snapshot`.
+    ///
+    /// Any queued prompts are discarded.
+    pub fn restore_snapshot(&mut self, snapshot: Snapshot) {
+        self.current_page = snapshot.current_page;
+        self.current_link_idx = snapshot.current_link_idx;
+        self.history = snapshot.history;
+        self.visit_counts = snapshot.visit_counts;
+        self.variables = snapshot.variables;
+        self.items = snapshot.items;
+        self.journal = snapshot.journal;
+        self.prompt_queue.clear();
+    }
+
+    /// Capture the state of the game as a [`GameSave`], which can be written to a save file.
+    ///
+    /// History entries for pages that no longer exist are left out.
+    pub fn to_save(&self) -> GameSave {
+        GameSave {
+            version: self.metadata.version,
+            current_page: self.current_page.borrow().id.clone(),
+            current_link_idx: self.current_link_idx,
+            history: self
+                .history
+                .iter()
+                .filter_map(|item| {
+                    Some(SavedHistoryItem {
+                        page: item.page.upgrade()?.borrow().id.clone(),
+                        link_idx: item.link_idx,
+                    })
+                })
+                .collect(),
+            visit_counts: self
+                .visit_counts
+                .iter()
+                .map(|(page_id, count)| (page_id.clone(), *count))
+                .collect(),
+            variables: self
+                .variables
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            items: self
+                .items
+                .iter()
+                .map(|(name, stack)| (name.clone(), stack.iter().map(Item::used).collect()))
+                .collect(),
+            journal: self.journal.clone(),
+            prompt_queue: self.prompt_queue.iter().cloned().collect(),
+        }
+    }
+
+    /// Returns `true` if `save` can be loaded into this version of the story, by
+    /// [`Version::is_compatible_with`]. Saves from stories without a version, and any save if this
+    /// story has no version, are assumed to be compatible.
+    ///
+    /// [`restore`](Self::restore) doesn't check this, so the player can choose to load an
+    /// incompatible save anyway.
+    pub fn is_compatible_save(&self, save: &GameSave) -> bool {
+        match (self.metadata.version, save.version) {
+            (Some(version), Some(save_version)) => version.is_compatible_with(&save_version),
+            _ => true,
+        }
+    }
+
+    /// Restore the game to the state captured by `save`, looking up the pages it refers to by ID.
+    ///
+    /// Fails without changing the game state if the save refers to a page, variable, or item that
+    /// no longer exists, which can happen if the story changed since the game was saved. Variables
+    /// added to the story since then keep their current values.
+    pub fn restore(&mut self, save: GameSave) -> Result<()> {
+        let pages = parser::reachable_pages(&self.starting_page);
+        let find_page = |page_id: &str| match self.pages.get(page_id).or(pages.get(page_id)) {
+            Some(page) => Ok(Rc::clone(page)),
+            None => self.load_page(page_id),
+        };
+
+        let current_page = find_page(&save.current_page)?;
+        let history = save
+            .history
+            .iter()
+            .map(|item| Ok(HistoryItem::new(&find_page(&item.page)?, item.link_idx)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut variables = self.variables.clone();
+        for (name, value) in save.variables {
+            let def = self
+                .variable_defs
+                .get(&name)
+                .ok_or_else(|| Error::undeclared_variable(&name))?;
+            if value.type_() != def.type_() {
+                return Err(Error::bad_variable_type(&name, value.type_(), def.type_()));
+            }
+            variables.insert(name, value);
+        }
+
+        // Saves don't record the order items were acquired in, so restored items are ordered by
+        // name instead.
+        let mut items = HashMap::with_capacity(save.items.len());
+        let mut acquisitions = self.acquisitions;
+        for (name, uses) in save.items {
+            let def = self
+                .item_defs
+                .get(&name)
+                .ok_or_else(|| Error::undeclared_item(&name))?;
+            let stack = uses
+                .into_iter()
+                .map(|used| {
+                    let mut item = Item::new(def);
+                    item.mod_uses(used);
+                    item.set_acquired(acquisitions);
+                    acquisitions += 1;
+                    item
+                })
+                .collect();
+            items.insert(name, stack);
+        }
+
+        debug!("restoring save: '{}'", save.current_page);
+        self.current_page = current_page;
+        self.current_link_idx = save.current_link_idx;
+        self.history = history;
+        self.visit_counts = save.visit_counts.into_iter().collect();
+        self.variables = variables;
+        self.items = items;
+        self.acquisitions = acquisitions;
+        self.journal = save.journal;
+        self.prompt_queue = save.prompt_queue.into();
+        self.prompts_popped = 0;
+        self.character_creation = None;
+        self.last_checkpoint = None;
+        // The saved visit counts already include the visit to the current page, so only save the
+        // checkpoint that entering it would.
+        if self.current_page.borrow().checkpoint {
+            self.last_checkpoint = Some(self.snapshot());
+        }
+        Ok(())
+    }
+
+    /// Start the story over from the beginning, as if the game had just been created: character
+    /// creation (if any) runs again, variables get their starting values back, and the history,
+    /// visit counts, items, notes, queued prompts, and checkpoint are cleared.
+    ///
+    /// Random starting values aren't rolled again, so variables are the same as when the game
+    /// started.
+    pub fn restart(&mut self) {
+        debug!("restart");
+        self.current_page = Rc::clone(&self.starting_page);
+        self.current_link_idx = None;
+        self.history.clear();
+        self.prompt_queue.clear();
+        self.prompts_popped = 0;
+        self.visit_counts = iter::once((self.starting_page.borrow().id.clone(), 1)).collect();
+        self.reset_variables();
+        self.items.clear();
+        self.acquisitions = 0;
+        self.journal.clear();
+        self.character_creation = CharacterCreation::new(&self.creation_steps);
+        self.last_checkpoint = None;
+        if self.character_creation.is_none() && self.starting_page.borrow().checkpoint {
+            self.last_checkpoint = Some(self.snapshot());
+        }
+    }
+
+    /// Set every variable back to the value it had when the game started.
+    pub fn reset_variables(&mut self) {
+        debug!("reset variables");
+        self.variables = self.initial_variables.clone();
+    }
+
+    /// Return to the last checkpoint, if any. Returns `false` if no checkpoint has been reached.
+    ///
+    /// Checkpoints are saved by entering a checkpoint page or by a `set-checkpoint` action. Queued
+    /// prompts aren't part of a checkpoint, so any that haven't been answered are discarded.
+    pub fn restore_checkpoint(&mut self) -> bool {
+        match self.last_checkpoint.clone() {
+            Some(snapshot) => {
+                debug!(
+                    "restoring checkpoint: '{}'",
+                    snapshot.current_page.borrow().id
+                );
+                self.restore_snapshot(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the turn count, which conditions can refer to as [`TURN_COUNT`]: the total of the
+    /// [`visit_counts`](Self::visit_counts), so 1 on the starting page and one more for each page
+    /// entered after that.
+    pub fn turn(&self) -> i32 {
+        turn_count(&self.visit_counts)
+    }
+
+    /// Returns the current character creation step and its index, if character creation is in
+    /// progress.
+    pub fn creation_step(&self) -> Option<(usize, &CreationStep)> {
+        self.character_creation
+            .as_ref()
+            .map(|cc| (cc.current, &cc.steps[cc.current]))
+    }
+
+    /// Choose the item named `name` for the current character creation step, replacing any item
+    /// previously chosen for the step.
+    pub fn choose_creation_item(&mut self, name: &str) {
+        let prev = match self.character_creation.as_mut() {
+            Some(cc) => cc.chosen_items[cc.current].replace(name.to_owned()),
+            None => return,
+        };
+        let mut actions = Vec::with_capacity(2);
+        if let Some(prev) = prev {
+            actions.push(LinkAction::DropItem(prev));
+        }
+        actions.push(LinkAction::AcquireItem(name.to_owned()));
+        self.run_link_actions(actions);
+    }
+
+    /// Advance to the next character creation step, finishing character creation after the last.
+    pub fn next_creation_step(&mut self) {
+        if let Some(cc) = self.character_creation.as_mut() {
+            cc.current += 1;
+            if cc.current >= cc.steps.len() {
+                debug!("character creation: complete");
+                self.character_creation = None;
+                if self.current_page.borrow().checkpoint {
+                    self.last_checkpoint = Some(self.snapshot());
+                }
+            }
+        }
+    }
+
+    /// Go back to the previous character creation step, if any.
+    pub fn prev_creation_step(&mut self) {
+        if let Some(cc) = self.character_creation.as_mut() {
+            cc.current = cc.current.saturating_sub(1);
         }
     }
 
@@ -46,28 +543,146 @@ impl Game {
     ///
     /// The returned iterator yields `(i, link)` pairs, where `i` is the link's index in the
     /// original `links` vector, such that "missing" indices correspond with links that were
-    /// filtered out.
+    /// filtered out. Links whose condition can't be evaluated are excluded as well.
     pub fn filter_active_links<'a>(
         &'a self,
         links: &'a Vec<Link>,
     ) -> impl Iterator<Item = (usize, &'a Link)> {
         links.iter().enumerate().filter(move |(_, link)| {
             if let Some(cond) = &link.requires {
-                if !self.eval_condition(&cond) {
-                    return false;
+                match self.eval_condition(&cond) {
+                    Ok(true) => {}
+                    Ok(false) => return false,
+                    Err(err) => {
+                        error!(
+                            "error evaluating condition for link '{}' on page '{}': {}",
+                            link.text,
+                            self.current_page.borrow().id,
+                            err
+                        );
+                        return false;
+                    }
                 }
             }
             true
         })
     }
 
+    /// Returns whether the current page is an ending, i.e. every link on it that the player can
+    /// follow ends the game, or there are no such links.
+    ///
+    /// Only each link's `dest` is considered, not destinations set by its actions or triggers.
+    pub fn is_ending(&self) -> bool {
+        let page = self.current_page.borrow();
+        let is_ending = self
+            .filter_active_links(&page.links)
+            .all(|(_, link)| matches!(link.dest, LinkDest::EndGame(_)));
+        is_ending
+    }
+
     /// Advance the Game by selecting the Link with the given `link_idx`.
-    pub fn follow_link(&mut self, link_idx: usize) -> Option<String> {
+    ///
+    /// Fails without changing the game state with [`Error::NoSuchLink`] if the current page has no
+    /// such link, or with [`Error::LinkLocked`] if the link's `requires` condition is not met.
+    /// Other errors are wrapped in [`Error::InLink`], to show where they occurred.
+    pub fn follow_link(&mut self, link_idx: usize) -> Result<Option<String>> {
         trace!("next(idx={})", link_idx);
 
+        self.check_link_idx(link_idx)?;
+        let page_id = self.current_page.borrow().id.clone();
+        let in_link = |err| Error::in_link(&page_id, link_idx, err);
+
+        if self.is_link_locked(link_idx).map_err(in_link)? {
+            debug!("locked: link({})", link_idx);
+            let page = self.current_page.borrow();
+            return Err(Error::link_locked(&page.id, &page.links[link_idx].text));
+        }
+        let (link_dest, _) = self.run_link(link_idx).map_err(in_link)?;
+        self.eval_link_dest(link_dest, Some(link_idx))
+            .map_err(in_link)
+    }
+
+    /// Preview what following the Link with the given `link_idx` would do, without changing the
+    /// game state.
+    pub fn peek_link(&self, link_idx: usize) -> Result<LinkPreview> {
+        trace!("peek(idx={})", link_idx);
+
+        self.check_link_idx(link_idx)?;
+        let page_id = self.current_page.borrow().id.clone();
+        let in_link = |err| Error::in_link(&page_id, link_idx, err);
+
+        // Run the link on a copy of the game, and compare the result with the current state.
+        let mut game = self.scratch_copy();
+        let (dest, triggered) = game.run_link(link_idx).map_err(in_link)?;
+
+        let mut changed_variables: Vec<_> = game
+            .variables
+            .iter()
+            .filter_map(|(name, value)| {
+                let prev = self.variables.get(name)?;
+                if prev == value {
+                    return None;
+                }
+                Some((name.clone(), prev.clone(), value.clone()))
+            })
+            .collect();
+        changed_variables.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+        let count = |items: &HashMap<String, VecDeque<Item>>, name: &str| {
+            items.get(name).map_or(0, VecDeque::len)
+        };
+        let mut changed_items: Vec<_> = self
+            .items
+            .keys()
+            .chain(game.items.keys())
+            .filter_map(|name| {
+                let (prev, next) = (count(&self.items, name), count(&game.items, name));
+                if prev == next {
+                    return None;
+                }
+                Some((name.clone(), prev, next))
+            })
+            .collect();
+        changed_items.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+        changed_items.dedup_by(|(a, ..), (b, ..)| a == b);
+
+        Ok(LinkPreview {
+            locked: self.is_link_locked(link_idx).map_err(in_link)?,
+            dest,
+            triggered,
+            changed_variables,
+            changed_items,
+            new_notes: game.journal.split_off(self.journal.len()),
+            prompts: game.prompt_queue.len(),
+        })
+    }
+
+    /// Fails with [`Error::NoSuchLink`] if the current page has no Link with the given `link_idx`.
+    fn check_link_idx(&self, link_idx: usize) -> Result<()> {
+        let page = self.current_page.borrow();
+        if link_idx >= page.links.len() {
+            return Err(Error::no_such_link(&page.id, link_idx));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the `requires` condition of the Link with the given `link_idx` is not met.
+    fn is_link_locked(&self, link_idx: usize) -> Result<bool> {
+        let page = self.current_page.borrow();
+        match &page.links[link_idx].requires {
+            Some(cond) => Ok(!self.eval_condition(cond)?),
+            None => Ok(false),
+        }
+    }
+
+    /// Run the actions and triggers of the Link with the given `link_idx`.
+    ///
+    /// Returns the link's final destination and the indices of the triggers whose condition was
+    /// met.
+    fn run_link(&mut self, link_idx: usize) -> Result<(LinkDest, Vec<usize>)> {
         let (mut link_dest, actions, triggers) = {
-            let links = &self.current_page.borrow().links;
-            let to_link = links.get(link_idx).unwrap();
+            let page = self.current_page.borrow();
+            let to_link = &page.links[link_idx];
             (
                 to_link.dest.clone(),
                 to_link.actions.clone(),
@@ -78,10 +693,44 @@ impl Game {
         if let Some(dest) = self.run_link_actions(actions) {
             link_dest = dest;
         }
-        if let Some(dest) = self.eval_link_triggers(triggers) {
+        let (dest, triggered) = self.eval_link_triggers(triggers)?;
+        if let Some(dest) = dest {
             link_dest = dest;
         }
-        self.eval_link_dest(link_dest, link_idx)
+        Ok((link_dest, triggered))
+    }
+
+    /// Returns a copy of the game's state that can be changed without affecting the game, e.g. to
+    /// preview the outcome of a link.
+    fn scratch_copy(&self) -> Game {
+        Game {
+            metadata: self.metadata.clone(),
+            starting_page: Rc::clone(&self.starting_page),
+            current_page: Rc::clone(&self.current_page),
+            current_link_idx: self.current_link_idx,
+            history: self.history.clone(),
+            prompt_queue: VecDeque::new(),
+            visit_counts: self.visit_counts.clone(),
+            variables: self.variables.clone(),
+            variable_defs: self.variable_defs.clone(),
+            item_defs: self.item_defs.clone(),
+            helpers: Arc::clone(&self.helpers),
+            items: self.items.clone(),
+            inventory_limit: self.inventory_limit,
+            history_limit: self.history_limit,
+            timers: self.timers.clone(),
+            journal: self.journal.clone(),
+            last_checkpoint: None,
+            character_creation: None,
+            pages: self.pages.clone(),
+            lazy_pages: None,
+            rng: self.rng.clone(),
+            registry: Rc::clone(&self.registry),
+            acquisitions: self.acquisitions,
+            prompts_popped: 0,
+            initial_variables: self.initial_variables.clone(),
+            creation_steps: Vec::new(),
+        }
     }
 
     /// Execute a series of [`LinkAction`](crate::types::LinkAction) in order for a given
@@ -98,12 +747,63 @@ impl Game {
                         debug!("action: set-var({}, {})", name, value);
                     }
                 }
+                LinkAction::SetVarFrom { name, from } => {
+                    if let Some(value) = self.variables.get(&from).cloned() {
+                        if let Some(var) = self.variables.get_mut(&name) {
+                            debug!("action: set-var-from({}, {}) = {}", name, from, value);
+                            *var = value;
+                        }
+                    }
+                }
+                LinkAction::AppendStr { name, value } => {
+                    if let Some(Variable::Str(var)) = self.variables.get_mut(&name) {
+                        var.push_str(&value);
+                        debug!("action: append-str({}, {:?})", name, value);
+                    }
+                }
                 LinkAction::ModNum { name, value } => {
                     if let Some(Variable::Num(var)) = self.variables.get_mut(&name) {
-                        *var = i32::convert_bounded(*var as i32 + value as i32);
+                        *var = match self.variable_defs.get(&name) {
+                            Some(def) if def.wrap.is_some() => {
+                                def.wrapped(*var as i64 + value as i64) as i32
+                            }
+                            _ => i32::convert_bounded(*var as i32 + value as i32),
+                        };
                         debug!("action: mod-num({}, {})", name, value);
                     }
                 }
+                LinkAction::ModNumByVar { name, by, negate } => {
+                    if let Some(Variable::Num(by_value)) = self.variables.get(&by) {
+                        let value = match negate {
+                            true => -(*by_value as i64),
+                            false => *by_value as i64,
+                        };
+                        debug!(
+                            "action: mod-num-by-var({}, {}, negate={})",
+                            name, by, negate
+                        );
+                        self.run_link_actions(vec![LinkAction::ModNum {
+                            name,
+                            value: i32::convert_bounded(value),
+                        }]);
+                    }
+                }
+                LinkAction::ModFloat { name, value } => {
+                    if let Some(Variable::Float(var)) = self.variables.get_mut(&name) {
+                        let result = *var + value;
+                        if result.is_finite() {
+                            *var = result;
+                        }
+                        debug!("action: mod-float({}, {})", name, value);
+                    }
+                }
+                LinkAction::Roll { name, min, max } => {
+                    let roll = self.rng.gen_range(min..=max);
+                    if let Some(Variable::Num(var)) = self.variables.get_mut(&name) {
+                        *var = roll;
+                        debug!("action: roll({}, {}..={}) = {}", name, min, max, roll);
+                    }
+                }
                 LinkAction::ToggleBool(name) => {
                     if let Some(Variable::Bool(var)) = self.variables.get_mut(&name) {
                         *var = !*var;
@@ -118,11 +818,37 @@ impl Game {
                     debug!("action: prompt({:?})", prompt.variable);
                     self.prompt_queue.push_back(prompt.clone());
                 }
+                LinkAction::AddNote(Note { text, once }) => {
+                    let note = self.render_template(&text);
+                    debug!("action: add-note({:?}, once={})", note, once);
+                    if !(once && self.journal.contains(&note)) {
+                        self.journal.push(note);
+                    }
+                }
+                LinkAction::SetCheckpoint => {
+                    debug!(
+                        "action: set-checkpoint('{}')",
+                        self.current_page.borrow().id
+                    );
+                    self.last_checkpoint = Some(self.snapshot());
+                }
                 LinkAction::AcquireItem(name) => {
                     debug!("action: acquire-item({})", name);
+                    if !self.make_room_for(&name) {
+                        debug!("action: acquire-item({}): inventory is full", name);
+                        self.prompt_queue.push_back(Prompt::info(format!(
+                            "You can't carry any more, so you leave the {} behind.",
+                            name
+                        )));
+                        continue;
+                    }
                     let def = &self.item_defs[&name];
                     let mut item = Item::new(def);
+                    item.set_acquired(self.acquisitions);
+                    self.acquisitions += 1;
                     match self.items.get_mut(&name) {
+                        // The newest item in a stack carries the stack's used count, so that
+                        // partially used charges are always consumed first.
                         Some(stack) => {
                             if let Some(prev) = stack.back_mut() {
                                 let uses_added = item.mod_uses(prev.used());
@@ -148,21 +874,19 @@ impl Game {
                 }
                 LinkAction::UseItem(name) => {
                     debug!("action: use-item({})", name);
-                    if let Some(stack) = self.items.get_mut(&name) {
-                        if let Some(item) = stack.get_mut(0) {
-                            if let Some(effect) = item.use_once().cloned() {
-                                // If item has reached its `max_uses`, remove it from the game.
-                                if let Some(0) = item.uses_left() {
-                                    stack.pop_front();
-                                    if stack.is_empty() {
-                                        self.items.remove(&name);
-                                    }
-                                }
-
-                                if let Some(dest) = self.run_link_actions(vec![effect]) {
-                                    final_dest = Some(dest);
-                                }
-                            }
+                    let effect = self
+                        .items
+                        .get_mut(&name)
+                        .and_then(|stack| stack.back_mut())
+                        .and_then(|item| item.use_once().cloned());
+
+                    // If the item has reached its `max_uses`, remove it from the game before
+                    // running its effect, so the effect sees the updated inventory.
+                    self.remove_depleted_items(&name);
+
+                    if let Some(effect) = effect {
+                        if let Some(dest) = self.run_link_actions(vec![effect]) {
+                            final_dest = Some(dest);
                         }
                     }
                 }
@@ -172,104 +896,875 @@ impl Game {
         final_dest
     }
 
-    fn eval_link_triggers(&mut self, triggers: Vec<LinkTrigger>) -> Option<LinkDest> {
+    /// Check that there's room in the inventory to acquire another item named `name`, dropping the
+    /// oldest items to make room if the [`InventoryLimit`] allows it.
+    ///
+    /// Returns `false` if the item can't be acquired.
+    fn make_room_for(&mut self, name: &str) -> bool {
+        let limit = match self.inventory_limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let is_full = |items: &HashMap<String, VecDeque<Item>>| match limit.mode {
+            LimitMode::Items => items.values().map(VecDeque::len).sum::<usize>() >= limit.max,
+            LimitMode::Stacks => !items.contains_key(name) && items.len() >= limit.max,
+        };
+        if !is_full(&self.items) {
+            return true;
+        }
+        if limit.overflow == InventoryOverflow::Reject {
+            return false;
+        }
+
+        // The oldest copy of each item is at the front of its stack.
+        let oldest = self
+            .items
+            .iter()
+            .filter_map(|(name, stack)| Some((stack.front()?.acquired(), name)))
+            .min()
+            .map(|(_, name)| name.clone());
+        if let Some(oldest) = oldest {
+            debug!("inventory full: dropping oldest item '{}'", oldest);
+            match limit.mode {
+                LimitMode::Items => {
+                    self.run_link_actions(vec![LinkAction::DropItem(oldest)]);
+                }
+                LimitMode::Stacks => {
+                    self.items.remove(&oldest);
+                }
+            }
+        }
+        !is_full(&self.items)
+    }
+
+    /// Remove any depleted items from the stack named `name`, removing the stack itself if it
+    /// becomes empty.
+    ///
+    /// This upholds the invariant that every [`Item`] in `items` has at least one use left, such
+    /// that [`Condition::HasItem`] holding means the item can be used.
+    fn remove_depleted_items(&mut self, name: &str) {
+        if let Some(stack) = self.items.get_mut(name) {
+            stack.retain(|item| !item.is_depleted());
+            if stack.is_empty() {
+                self.items.remove(name);
+            }
+        }
+    }
+
+    /// Run the actions of each [`LinkTrigger`] whose condition is met, or its `else_actions` if
+    /// the condition is not met, in order. Later triggers override the outcomes of earlier ones,
+    /// unless a met trigger has `stop` set, in which case the remaining triggers are skipped.
+    ///
+    /// Also returns the indices of the triggers whose condition was met.
+    fn eval_link_triggers(
+        &mut self,
+        triggers: Vec<LinkTrigger>,
+    ) -> Result<(Option<LinkDest>, Vec<usize>)> {
         let mut final_dest = None;
+        let mut triggered = Vec::new();
 
-        for trigger in triggers {
-            if self.eval_condition(&trigger.condition) {
+        for (i, trigger) in triggers.into_iter().enumerate() {
+            if self.eval_condition(&trigger.condition)? {
+                triggered.push(i);
                 if let Some(dest) = self.run_link_actions(trigger.actions) {
                     final_dest = Some(dest);
                 }
+                if trigger.stop {
+                    trace!("trigger: stop");
+                    break;
+                }
+            } else if let Some(dest) = self.run_link_actions(trigger.else_actions) {
+                final_dest = Some(dest);
             }
         }
 
-        final_dest
+        Ok((final_dest, triggered))
     }
 
-    fn eval_link_dest(&mut self, link_dest: LinkDest, link_idx: usize) -> Option<String> {
+    /// Go to `link_dest`, having followed the link at `link_idx` on the current page, if any, and
+    /// count down timers if the page changed.
+    fn eval_link_dest(
+        &mut self,
+        link_dest: LinkDest,
+        link_idx: Option<usize>,
+    ) -> Result<Option<String>> {
+        // Returning to a checkpoint restores timers as they were, so it doesn't count down.
+        let counts_down = !matches!(link_dest, LinkDest::Checkpoint);
+        let is_back = matches!(link_dest, LinkDest::PrevPage | LinkDest::PrevPages(_));
+        let prev_page = Rc::clone(&self.current_page);
+        let history_len = self.history.len();
+
+        let ending = self.goto_link_dest(link_dest, link_idx)?;
+        if ending.is_some() || !counts_down || Rc::ptr_eq(&prev_page, &self.current_page) {
+            return Ok(ending);
+        }
+        let pages_back = if is_back {
+            history_len.saturating_sub(self.history.len())
+        } else {
+            0
+        };
+        match self.count_down_timers(pages_back) {
+            // Going where a timer sends the player doesn't count down timers again.
+            Some(dest) => self.goto_link_dest(dest, None),
+            None => Ok(None),
+        }
+    }
+
+    /// Go to `link_dest`, having followed the link at `link_idx` on the current page, if any.
+    fn goto_link_dest(
+        &mut self,
+        link_dest: LinkDest,
+        link_idx: Option<usize>,
+    ) -> Result<Option<String>> {
         match link_dest {
-            dest @ LinkDest::Page(_) => {
-                let page = dest.get_page().unwrap();
+            LinkDest::Page(to_page) => {
+                let page = match to_page {
+                    Right(page) => page,
+                    Left(page_id) => self.find_page(&page_id)?,
+                };
                 trace!("dest: page('{}')", page.borrow().id);
-
-                if page.borrow().id != self.current_page.borrow().id {
-                    self.history
-                        .push(HistoryItem::new(&self.current_page, self.current_link_idx));
-                    self.current_page = page;
-                    self.current_link_idx = Some(link_idx);
+                self.goto_page(page, link_idx);
+            }
+            LinkDest::Random(to_pages) if to_pages.is_empty() => {
+                error!(
+                    "no pages to choose from at random; staying on page '{}'",
+                    self.current_page.borrow().id
+                );
+            }
+            LinkDest::Random(to_pages) => {
+                let i = self.rng.gen_range(0..=to_pages.len() as i32 - 1) as usize;
+                let page = match &to_pages[i] {
+                    Right(page) => Rc::clone(page),
+                    Left(page_id) => self.find_page(page_id)?,
+                };
+                trace!("dest: random('{}')", page.borrow().id);
+                self.goto_page(page, link_idx);
+            }
+            LinkDest::PageFromVar(name) => {
+                let value = match self.variables.get(&name) {
+                    Some(Variable::Str(value)) => value.clone(),
+                    Some(var) => {
+                        return Err(Error::bad_variable_type(&name, var.type_(), VarType::Str))
+                    }
+                    None => return Err(Error::undeclared_variable(name)),
+                };
+                let page_id = self.current_page.borrow().id.clone();
+                let page = parser::resolve_page_id(&page_id, &value)
+                    .and_then(|page_id| self.find_page(&page_id).ok());
+                match page {
+                    Some(page) => {
+                        trace!("dest: page-from-var({}) = '{}'", name, page.borrow().id);
+                        self.goto_page(page, link_idx);
+                    }
+                    None => error!(
+                        "variable '{}' holds '{}', which is not a page ID; staying on page '{}'",
+                        name, value, page_id
+                    ),
                 }
             }
+            LinkDest::StartPage => {
+                trace!("dest: start");
+                self.goto_page(Rc::clone(&self.starting_page), link_idx);
+            }
+            LinkDest::StartOver => {
+                trace!("dest: start-over");
+                self.history.clear();
+                self.current_page = Rc::clone(&self.starting_page);
+                self.current_link_idx = None;
+                self.enter_page();
+            }
             LinkDest::CurrentPage => {
                 trace!("dest: current");
             }
+            // Going back by a link counts as another visit, unlike going back with `go_back`.
             LinkDest::PrevPage => {
                 trace!("dest: previous");
-                if let Some(item) = self.history.pop() {
-                    self.current_page = item.page.upgrade().unwrap();
-                    self.current_link_idx = item.link_idx;
+                self.follow_back(1);
+            }
+            LinkDest::PrevPages(n) => {
+                trace!("dest: back({})", n);
+                self.follow_back(n);
+            }
+            LinkDest::Checkpoint => {
+                trace!("dest: checkpoint");
+                if !self.restore_checkpoint() {
+                    self.prompt_queue
+                        .push_back(Prompt::info("There's no checkpoint to return to."));
                 }
             }
             LinkDest::EndGame(msg) => {
                 trace!("dest: end");
                 self.current_link_idx = None;
-                return Some(msg);
+                return Ok(Some(msg));
             }
         }
-        None
+        Ok(None)
     }
 
-    fn eval_condition(&self, cond: &Condition) -> bool {
-        match cond {
-            Condition::And(children) => children.iter().all(|child| self.eval_condition(child)),
-            Condition::Or(children) => children.iter().any(|child| self.eval_condition(child)),
-            Condition::Not(condition) => !self.eval_condition(condition),
-            Condition::Op(Operation { name, op, value }) => self.eval_operation(name, *op, value),
-            Condition::HasItem(name) => self.items.contains_key(name),
+    /// Count down each timer by one after moving to another page, or wind it back by `pages_back`
+    /// if the player went back that many pages and the timer rewinds. Runs the `on_zero` actions of
+    /// the timers that reach zero, returning the destination they set, if any.
+    fn count_down_timers(&mut self, pages_back: usize) -> Option<LinkDest> {
+        // Sort so timers that run out together act in the same order every time.
+        let mut names: Vec<_> = self.timers.keys().cloned().collect();
+        names.sort();
+
+        let mut actions = Vec::new();
+        for name in names {
+            let timer = &self.timers[&name];
+            let value = match self.variables.get_mut(&name) {
+                Some(Variable::Num(value)) => value,
+                _ => continue,
+            };
+            if pages_back > 0 && timer.rewind_on_back {
+                *value = value.saturating_add(pages_back.min(i32::MAX as usize) as i32);
+                debug!("timer: rewind({}) = {}", name, value);
+            } else if *value > 0 {
+                *value -= 1;
+                debug!("timer: count-down({}) = {}", name, value);
+                if *value == 0 {
+                    actions.extend(timer.on_zero.iter().cloned());
+                }
+            }
         }
+        self.run_link_actions(actions)
     }
 
-    fn eval_operation(&self, name: &String, op: ComparisonOp, value: &Variable) -> bool {
-        use ComparisonOp::*;
-        use Variable::*;
-        let var = &self.variables[name];
+    /// Go to `page` by following the link at `link_idx`, if any, recording the current page in the
+    /// history. Does nothing if `page` is the current page.
+    fn goto_page(&mut self, page: Rc<RefCell<Page>>, link_idx: Option<usize>) {
+        if page.borrow().id != self.current_page.borrow().id {
+            self.push_history();
+            self.current_page = page;
+            self.current_link_idx = link_idx;
+            self.enter_page();
+        }
+    }
 
-        match op {
-            EQ => match (var, value) {
-                (Num(x), Num(y)) => x == y,
-                (Bool(x), Bool(y)) => x == y,
-                (Str(x), Str(y)) => x == y,
-                _ => unreachable!(),
-            },
-            NEQ => match (var, value) {
-                (Num(x), Num(y)) => x != y,
-                (Bool(x), Bool(y)) => x != y,
-                (Str(x), Str(y)) => x != y,
-                _ => unreachable!(),
-            },
-            GT => match (var, value) {
-                (Num(x), Num(y)) => x > y,
-                _ => unreachable!(),
-            },
-            GTE => match (var, value) {
-                (Num(x), Num(y)) => x >= y,
-                _ => unreachable!(),
-            },
-            LT => match (var, value) {
-                (Num(x), Num(y)) => x < y,
-                _ => unreachable!(),
-            },
-            LTE => match (var, value) {
-                (Num(x), Num(y)) => x <= y,
-                _ => unreachable!(),
+    /// Record the current page in the history, dropping the oldest entries if there are more than
+    /// the `history_limit`.
+    fn push_history(&mut self) {
+        self.history
+            .push(HistoryItem::new(&self.current_page, self.current_link_idx));
+        if let Some(limit) = self.history_limit {
+            if self.history.len() > limit {
+                let excess = self.history.len() - limit;
+                self.history.drain(..excess);
+            }
+        }
+    }
+
+    /// Go back `n` pages by following a link, telling the player if there's no page to go back
+    /// to, e.g. because older pages were dropped from the history.
+    fn follow_back(&mut self, n: usize) {
+        if self.goto_prev_pages(n) {
+            self.count_visit();
+        } else {
+            self.prompt_queue
+                .push_back(Prompt::info("You can't go back any further."));
+        }
+    }
+
+    /// Count a visit to the newly entered current page, and save a checkpoint if it's a
+    /// checkpoint.
+    fn enter_page(&mut self) {
+        self.count_visit();
+        if self.current_page.borrow().checkpoint {
+            trace!("checkpoint: '{}'", self.current_page.borrow().id);
+            self.last_checkpoint = Some(self.snapshot());
+        }
+    }
+
+    /// Count a visit to the current page.
+    fn count_visit(&mut self) {
+        *self
+            .visit_counts
+            .entry(self.current_page.borrow().id.clone())
+            .or_insert(0) += 1;
+    }
+
+    /// Use the item named `name` from the player's inventory, as if by a link with a `use-item`
+    /// action, going wherever its effect leads.
+    ///
+    /// Returns the ending message if the effect ends the game. Fails if the player doesn't hold
+    /// the item or character creation is in progress.
+    pub fn use_item(&mut self, name: &str) -> Result<Option<String>> {
+        if self.character_creation.is_some() {
+            return Err(Error::message(
+                "items can't be used during character creation",
+            ));
+        }
+        if !self.items.contains_key(name) {
+            return Err(Error::message(format!("no '{}' in inventory", name)));
+        }
+        match self.run_link_actions(vec![LinkAction::UseItem(name.to_owned())]) {
+            Some(dest) => self.eval_link_dest(dest, None),
+            None => Ok(None),
+        }
+    }
+
+    /// Go back to the previous page in the history, as if following a link to
+    /// [`LinkDest::PrevPage`]. Variables, items, and notes are left as they are.
+    ///
+    /// Returns `false` without changing the game state if there is no previous page.
+    pub fn go_back(&mut self) -> bool {
+        if self.character_creation.is_some() {
+            return false;
+        }
+        debug!("go back");
+        self.goto_prev_pages(1)
+    }
+
+    /// Go back to the page at `history_idx` in the history, discarding the history after it, as if
+    /// the player had never left that page. Variables, items, and notes are left as they are.
+    ///
+    /// Returns `false` without changing the game state if there is no such entry, its page no
+    /// longer exists, or character creation is in progress.
+    pub fn go_back_to(&mut self, history_idx: usize) -> bool {
+        if self.character_creation.is_some() {
+            return false;
+        }
+        let (page, link_idx) = match self.history.get(history_idx) {
+            Some(item) => match item.page.upgrade() {
+                Some(page) => (page, item.link_idx),
+                None => return false,
             },
+            None => return false,
+        };
+        debug!("go back to: '{}'", page.borrow().id);
+        self.history.truncate(history_idx);
+        self.current_page = page;
+        self.current_link_idx = link_idx;
+        true
+    }
+
+    /// Go directly to the page `page_id`, recording the current page in the history, so authors
+    /// can check a page without playing through the story to reach it.
+    ///
+    /// Returns `false` without changing the game state if no page with that ID can be reached
+    /// from the starting page, or if character creation is in progress.
+    pub fn jump_to_page(&mut self, page_id: &str) -> bool {
+        if self.character_creation.is_some() {
+            return false;
+        }
+        let page = match self.find_page(page_id) {
+            Ok(page) => page,
+            Err(_) => return false,
+        };
+        debug!("jump to page: '{}'", page_id);
+        if !Rc::ptr_eq(&page, &self.current_page) {
+            self.push_history();
+            self.current_page = page;
+            self.current_link_idx = None;
+            self.enter_page();
+        }
+        true
+    }
+
+    /// Go back `n` pages in the history, stopping early if the history runs out. Returns whether
+    /// the current page changed.
+    fn goto_prev_pages(&mut self, n: usize) -> bool {
+        let mut moved = false;
+        for _ in 0..n {
+            // Skip history entries for pages that no longer exist.
+            let prev = iter::from_fn(|| self.history.pop())
+                .find_map(|item| Some((item.page.upgrade()?, item.link_idx)));
+            match prev {
+                Some((page, link_idx)) => {
+                    self.current_page = page;
+                    self.current_link_idx = link_idx;
+                    moved = true;
+                }
+                None => break,
+            }
+        }
+        moved
+    }
+
+    /// Get a page by ID from the story's pages, or from the pages reachable from the starting page
+    /// if the game doesn't have them, or by loading it if the story is parsed lazily.
+    fn find_page(&self, page_id: &str) -> Result<Rc<RefCell<Page>>> {
+        if let Some(page) = self.pages.get(page_id) {
+            return Ok(Rc::clone(page));
+        }
+        match parser::reachable_pages(&self.starting_page).remove(page_id) {
+            Some(page) => Ok(page),
+            None => self.load_page(page_id),
         }
     }
 
+    /// Get a page that a link refers to by ID, which happens when the story is parsed lazily.
+    fn load_page(&self, page_id: &str) -> Result<Rc<RefCell<Page>>> {
+        match &self.lazy_pages {
+            Some(pages) => pages.borrow_mut().get(page_id),
+            None => Err(Error::undeclared_page_id(page_id)),
+        }
+    }
+
+    /// Evaluate `cond` against the current state of the game.
+    ///
+    /// Fails with [`Error::UndeclaredVariable`] if `cond` refers to a variable that doesn't exist,
+    /// which can happen if the story changed since the game state was saved.
+    pub fn eval_condition(&self, cond: &Condition) -> Result<bool> {
+        let current_page = self.current_page.borrow();
+        let state = ConditionState {
+            variables: &self.variables,
+            items: &self.items,
+            visit_counts: &self.visit_counts,
+            current_page: &current_page,
+        };
+        state.eval(cond)
+    }
+
     pub fn pop_prompt(&mut self) -> Option<Prompt> {
-        self.prompt_queue.pop_front()
+        if self.prompt_queue.is_empty() {
+            self.prompts_popped = 0;
+        }
+        // Skip prompts whose `requires` condition isn't met at the time they would be shown.
+        while let Some(prompt) = self.prompt_queue.pop_front() {
+            match prompt
+                .requires
+                .as_ref()
+                .map(|cond| self.eval_condition(cond))
+            {
+                None | Some(Ok(true)) => {
+                    self.prompts_popped += 1;
+                    return Some(prompt);
+                }
+                Some(Ok(false)) => debug!("skipped prompt: {:?}", prompt.text),
+                Some(Err(err)) => error!("error evaluating condition for prompt: {}", err),
+            }
+        }
+        None
+    }
+
+    /// Put a popped `prompt` back at the front of the queue, to be popped again.
+    pub fn unpop_prompt(&mut self, prompt: Prompt) {
+        self.prompt_queue.push_front(prompt);
+        self.prompts_popped = self.prompts_popped.saturating_sub(1);
+    }
+
+    /// Returns the position of the last popped prompt in the series of prompts it belongs to, and
+    /// the number of prompts in the series, e.g. `(2, 3)` for the second of three prompts queued by
+    /// a link. Prompts that are skipped later on are still counted.
+    pub fn prompt_progress(&self) -> (usize, usize) {
+        (
+            self.prompts_popped,
+            self.prompts_popped + self.prompt_queue.len(),
+        )
+    }
+
+    /// Pop the prompts at the front of the queue that ask for a variable, up to the first prompt
+    /// that doesn't, skipping prompts as [`pop_prompt`](Self::pop_prompt) does.
+    pub fn pop_variable_prompts(&mut self) -> Vec<Prompt> {
+        let mut prompts = Vec::new();
+        while let Some(prompt) = self.pop_prompt() {
+            if prompt.variable.is_none() {
+                self.unpop_prompt(prompt);
+                break;
+            }
+            prompts.push(prompt);
+        }
+        prompts
+    }
+}
+
+/// Creates the Handlebars registry for rendering templates, with the `default` helper and each of
+/// the story's `helpers` registered.
+fn template_registry(helpers: &HashMap<String, Expr>) -> Handlebars<'static> {
+    let helpers = Arc::new(helpers.clone());
+    let mut reg = Handlebars::new();
+    reg.register_helper("default", Box::new(default_helper));
+    reg.register_helper("item_count", Box::new(ItemHelper { count: true }));
+    reg.register_helper("has_item", Box::new(ItemHelper { count: false }));
+    reg.register_helper("visits", Box::new(VisitsHelper));
+    for (name, expr) in helpers.iter() {
+        let (name, expr, helpers) = (name.clone(), expr.clone(), Arc::clone(&helpers));
+        reg.register_helper(
+            &name.clone(),
+            Box::new(
+                move |_: &Helper,
+                      _: &Handlebars,
+                      ctx: &Context,
+                      _: &mut RenderContext,
+                      out: &mut dyn Output|
+                      -> HelperResult {
+                    let value = eval_helper(&expr, &helpers, ctx.data()).ok_or_else(|| {
+                        RenderError::new(format!("could not compute helper '{}'", name))
+                    })?;
+                    out.write(&value.to_string())?;
+                    Ok(())
+                },
+            ),
+        );
+    }
+    reg
+}
+
+/// The `default` template helper, which renders its first parameter, or its second parameter if
+/// the first is missing, null, or an empty string.
+fn default_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = match h.param(0).map(|param| param.value()) {
+        None | Some(JsonValue::Null) => None,
+        Some(JsonValue::String(s)) if s.is_empty() => None,
+        Some(value) => Some(value),
+    };
+    match value.or_else(|| h.param(1).map(|param| param.value())) {
+        Some(JsonValue::String(s)) => out.write(s)?,
+        None | Some(JsonValue::Null) => {}
+        Some(value) => out.write(&value.to_string())?,
+    }
+    Ok(())
+}
+
+/// The key of the item counts in the data that templates are rendered with.
+const TEMPLATE_ITEMS_KEY: &str = "__items";
+
+/// The `item_count` and `has_item` template helpers, which look up how many of the item named by
+/// their parameter the player holds, given either as a string or a bare word.
+struct ItemHelper {
+    /// Whether to return the number of items held, rather than whether any are held.
+    count: bool,
+}
+
+impl HelperDef for ItemHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<Option<ScopedJson<'reg, 'rc>>, RenderError> {
+        let name = h
+            .param(0)
+            .and_then(|param| match param.value() {
+                JsonValue::String(name) => Some(name.clone()),
+                _ => param.relative_path().cloned(),
+            })
+            .ok_or_else(|| RenderError::new(format!("{}: expected an item name", h.name())))?;
+        let count = ctx
+            .data()
+            .get(TEMPLATE_ITEMS_KEY)
+            .and_then(|items| items.get(&name))
+            .and_then(JsonValue::as_u64);
+        let value = match count {
+            Some(count) if self.count => JsonValue::from(count),
+            Some(count) => JsonValue::Bool(count > 0),
+            None => {
+                warn!("{}: undeclared item '{}'", h.name(), name);
+                JsonValue::Null
+            }
+        };
+        Ok(Some(ScopedJson::Derived(value)))
+    }
+}
+
+/// The key of the page visit counts in the data that templates are rendered with.
+const TEMPLATE_VISITS_KEY: &str = "__visits";
+
+/// The `visits` template helper, which looks up how many times the player has entered the page
+/// whose ID is its parameter. Pages that haven't been visited, or don't exist, have 0 visits.
+struct VisitsHelper;
+
+impl HelperDef for VisitsHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> std::result::Result<Option<ScopedJson<'reg, 'rc>>, RenderError> {
+        let page_id = h
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or_else(|| RenderError::new("visits: expected a page ID"))?;
+        let count = ctx
+            .data()
+            .get(TEMPLATE_VISITS_KEY)
+            .and_then(|visits| visits.get(page_id))
+            .and_then(JsonValue::as_u64)
+            .unwrap_or(0);
+        Ok(Some(ScopedJson::Derived(JsonValue::from(count))))
     }
 }
 
+/// Evaluate a template helper's `expr`, looking up names in `helpers` or else the template
+/// context `data`.
+fn eval_helper(expr: &Expr, helpers: &HashMap<String, Expr>, data: &JsonValue) -> Option<f64> {
+    expr.eval(&|name| match helpers.get(name) {
+        Some(expr) => eval_helper(expr, helpers, data),
+        None => data.get(name)?.as_f64(),
+    })
+}
+
+/// The parts of the game state that conditions depend on, borrowed once for the evaluation of a
+/// whole condition tree.
+struct ConditionState<'a> {
+    variables: &'a HashMap<String, Variable>,
+    items: &'a HashMap<String, VecDeque<Item>>,
+    visit_counts: &'a HashMap<PageID, u32>,
+    current_page: &'a Page,
+}
+
+impl ConditionState<'_> {
+    fn eval(&self, cond: &Condition) -> Result<bool> {
+        Ok(match cond {
+            Condition::And(children) => {
+                for child in children {
+                    if !self.eval(child)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            Condition::Or(children) => {
+                for child in children {
+                    if self.eval(child)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            Condition::Not(condition) => !self.eval(condition)?,
+            Condition::Op(operation) => self.eval_operation(operation)?,
+            Condition::HasItem(name) => self.items.contains_key(name),
+            Condition::LacksItem(name) => !self.items.contains_key(name),
+            Condition::Visited(page_id) => self.visit_counts.contains_key(page_id),
+            Condition::VisitCount { page, op, value } => {
+                let count = self.visit_counts.get(page).copied().unwrap_or(0);
+                compare(
+                    &Variable::Num(count.min(i32::MAX as u32) as i32),
+                    *op,
+                    &Variable::Num(*value),
+                )
+            }
+            Condition::ItemUses(operation) => {
+                let uses_left = match self
+                    .items
+                    .get(&operation.name)
+                    .and_then(|stack| stack.back())
+                {
+                    Some(item) => item.uses_left().unwrap_or(i32::MAX),
+                    None => 0,
+                };
+                compare(
+                    &operation_lhs(operation, &Variable::Num(uses_left))?,
+                    operation.op,
+                    self.operand(operation)?,
+                )
+            }
+            Condition::CurrentPageTag(tag) => self.current_page.tags.iter().any(|t| t == tag),
+        })
+    }
+
+    fn eval_operation(&self, operation: &Operation) -> Result<bool> {
+        let var = match self.variables.get(&operation.name) {
+            Some(var) => operation_lhs(operation, var)?,
+            // A declared variable takes precedence over the built-in turn count.
+            None if operation.name == TURN_COUNT => {
+                operation_lhs(operation, &Variable::Num(turn_count(self.visit_counts)))?
+            }
+            None => return Err(Error::undeclared_variable(&operation.name)),
+        };
+        Ok(compare(&var, operation.op, self.operand(operation)?))
+    }
+
+    /// Returns the value that `operation` compares against, which is the current value of its
+    /// `value_ref` variable if it has one.
+    fn operand<'b>(&'b self, operation: &'b Operation) -> Result<&'b Variable> {
+        match &operation.value_ref {
+            Some(name) => self.variable(name),
+            None => Ok(&operation.value),
+        }
+    }
+
+    fn variable(&self, name: &str) -> Result<&Variable> {
+        self.variables
+            .get(name)
+            .ok_or_else(|| Error::undeclared_variable(name))
+    }
+}
+
+/// Returns the total of `visit_counts`, i.e. the number of pages entered, for [`Game::turn`].
+fn turn_count(visit_counts: &HashMap<PageID, u32>) -> i32 {
+    let turns: u64 = visit_counts.values().map(|&count| u64::from(count)).sum();
+    turns.min(i32::MAX as u64) as i32
+}
+
+/// Returns the left-hand side of `operation` for the value `lhs`, failing if its modulo isn't
+/// positive (see [`Operation::lhs`]).
+fn operation_lhs(operation: &Operation, lhs: &Variable) -> Result<Variable> {
+    operation
+        .lhs(lhs)
+        .ok_or_else(|| Error::expected("a positive number after `%`"))
+}
+
+/// Compare `var` to `value` using the given [`ComparisonOp`].
+///
+/// Both operands are expected to have been type-checked during parsing.
+fn compare(var: &Variable, op: ComparisonOp, value: &Variable) -> bool {
+    use ComparisonOp::*;
+    use Variable::*;
+
+    // Compare whole numbers with decimal numbers as decimal numbers.
+    let (var, value) = match (var, value) {
+        (Num(_), Num(_)) => (Cow::Borrowed(var), Cow::Borrowed(value)),
+        _ => match (var.as_f64(), value.as_f64()) {
+            (Some(x), Some(y)) => (Cow::Owned(Float(x)), Cow::Owned(Float(y))),
+            _ => (Cow::Borrowed(var), Cow::Borrowed(value)),
+        },
+    };
+
+    match op {
+        EQ => match (var.as_ref(), value.as_ref()) {
+            (Num(x), Num(y)) => x == y,
+            (Float(x), Float(y)) => x == y,
+            (Bool(x), Bool(y)) => x == y,
+            (Str(x), Str(y)) => x == y,
+            _ => unreachable!(),
+        },
+        NEQ => match (var.as_ref(), value.as_ref()) {
+            (Num(x), Num(y)) => x != y,
+            (Float(x), Float(y)) => x != y,
+            (Bool(x), Bool(y)) => x != y,
+            (Str(x), Str(y)) => x != y,
+            _ => unreachable!(),
+        },
+        GT => match (var.as_ref(), value.as_ref()) {
+            (Num(x), Num(y)) => x > y,
+            (Float(x), Float(y)) => x > y,
+            _ => unreachable!(),
+        },
+        GTE => match (var.as_ref(), value.as_ref()) {
+            (Num(x), Num(y)) => x >= y,
+            (Float(x), Float(y)) => x >= y,
+            _ => unreachable!(),
+        },
+        LT => match (var.as_ref(), value.as_ref()) {
+            (Num(x), Num(y)) => x < y,
+            (Float(x), Float(y)) => x < y,
+            _ => unreachable!(),
+        },
+        LTE => match (var.as_ref(), value.as_ref()) {
+            (Num(x), Num(y)) => x <= y,
+            (Float(x), Float(y)) => x <= y,
+            _ => unreachable!(),
+        },
+    }
+}
+
+/// What following a link would do, as returned by [`Game::peek_link`].
+#[derive(Debug, Clone)]
+pub struct LinkPreview {
+    /// Whether the link's `requires` condition is not met, in which case following it would fail.
+    /// The rest of the preview describes what would happen if it were met.
+    pub locked: bool,
+    /// Where the link would go, taking into account actions and triggers that set the destination.
+    pub dest: LinkDest,
+    /// The indices of the link's triggers whose condition would be met.
+    pub triggered: Vec<usize>,
+    /// The variables that would change, with their current and new values, sorted by name.
+    pub changed_variables: Vec<(String, Variable, Variable)>,
+    /// The items whose count would change, with their current and new counts, sorted by name.
+    pub changed_items: Vec<(String, usize, usize)>,
+    /// The notes that would be added to the journal.
+    pub new_notes: Vec<String>,
+    /// The number of prompts that would be shown.
+    pub prompts: usize,
+}
+
+/// The progress of the player through the character creation sequence.
+pub struct CharacterCreation {
+    pub steps: Vec<CreationStep>,
+    pub current: usize,
+    /// The item chosen at each step, if any, so that it can be replaced when revisiting a step.
+    chosen_items: Vec<Option<String>>,
+}
+
+impl CharacterCreation {
+    /// Returns `None` if there are no `steps`.
+    fn new(steps: &[CreationStep]) -> Option<Self> {
+        if steps.is_empty() {
+            return None;
+        }
+        Some(CharacterCreation {
+            steps: steps.to_vec(),
+            current: 0,
+            chosen_items: vec![None; steps.len()],
+        })
+    }
+
+    /// Returns the item chosen at the current step, if any.
+    pub fn chosen_item(&self) -> Option<&str> {
+        self.chosen_items[self.current].as_deref()
+    }
+}
+
+/// A point-in-time copy of the state of a [`Game`].
+#[derive(Clone)]
+pub struct Snapshot {
+    pub current_page: Rc<RefCell<Page>>,
+    pub current_link_idx: Option<usize>,
+    pub history: Vec<HistoryItem>,
+    pub visit_counts: HashMap<PageID, u32>,
+    pub variables: HashMap<String, Variable>,
+    pub items: HashMap<String, VecDeque<Item>>,
+    pub journal: Vec<String>,
+}
+
+/// The state of a [`Game`] in a form that can be written to a save file, with pages referred to by
+/// their IDs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameSave {
+    /// The version of the story the game was saved in, if the story has one.
+    #[serde(default)]
+    pub version: Option<Version>,
+    pub current_page: PageID,
+    pub current_link_idx: Option<usize>,
+    pub history: Vec<SavedHistoryItem>,
+    /// The number of times the player has entered each page they've been to.
+    #[serde(default)]
+    pub visit_counts: BTreeMap<PageID, u32>,
+    pub variables: BTreeMap<String, Variable>,
+    /// The number of times each copy of each held item has been used, in inventory order.
+    pub items: BTreeMap<String, Vec<i32>>,
+    pub journal: Vec<String>,
+    pub prompt_queue: Vec<Prompt>,
+}
+
+impl GameSave {
+    /// Reads a save from the YAML file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content =
+            fs::read_to_string(path).map_err(|_| Error::read_error(Doctype::Save, path))?;
+        serde_yaml::from_str(&content).map_err(|e| Error::parse_error(Doctype::Save, path, e))
+    }
+
+    /// Writes the save to the file at `path` as YAML, replacing the file if it exists.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = serde_yaml::to_string(self).map_err(Error::std)?;
+        fs::write(path, content)?;
+        debug!("wrote save file `{}`", path.display());
+        Ok(())
+    }
+}
+
+/// A [`HistoryItem`] in a [`GameSave`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedHistoryItem {
+    pub page: PageID,
+    pub link_idx: Option<usize>,
+}
+
+#[derive(Clone)]
 pub struct HistoryItem {
     pub page: Weak<RefCell<Page>>,
     pub link_idx: Option<usize>,
@@ -283,3 +1778,827 @@ impl HistoryItem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const ITEMS: &str = r#"
+pages: [start, next]
+variables:
+  lit: 0
+  still_held: false
+items:
+  torch:
+    max_uses: 1
+    effect: { mod-num: { name: lit, value: 1 } }
+  candle:
+    max_uses: 3
+    effect: { mod-num: { name: lit, value: 1 } }
+  lamp:
+    effect: { mod-num: { name: lit, value: 1 } }
+"#;
+
+    const ITEMS_STORY: &str = r#"
+id: start
+content: "A dark room."
+links:
+  - text: "Light the torch"
+    dest: { page: next }
+    actions:
+      - use-item: torch
+    triggers:
+      - condition: { has-item: torch }
+        actions:
+          - set-var: { name: still_held, value: true }
+---
+id: next
+content: "A lit room."
+"#;
+
+    fn acquire(game: &mut Game, name: &str, count: usize) {
+        game.run_link_actions(vec![LinkAction::AcquireItem(name.to_owned()); count]);
+    }
+
+    #[test]
+    fn last_use_removes_item_before_later_actions() {
+        let mut game = testing::game(ITEMS, ITEMS_STORY);
+        acquire(&mut game, "torch", 1);
+
+        game.run_link_actions(vec![
+            LinkAction::UseItem("torch".to_owned()),
+            LinkAction::UseItem("torch".to_owned()),
+        ]);
+        assert_eq!(game.variables["lit"], Variable::Num(1));
+        assert!(!game.items.contains_key("torch"));
+        assert!(!game
+            .eval_condition(&Condition::HasItem("torch".to_owned()))
+            .unwrap());
+    }
+
+    #[test]
+    fn triggers_see_item_removed_by_last_use() {
+        let mut game = testing::game(ITEMS, ITEMS_STORY);
+        acquire(&mut game, "torch", 1);
+
+        game.follow_link(0).unwrap();
+        assert_eq!(game.current_page.borrow().id, "next");
+        assert_eq!(game.variables["lit"], Variable::Num(1));
+        assert_eq!(game.variables["still_held"], Variable::Bool(false));
+    }
+
+    #[test]
+    fn last_use_of_one_item_in_stack_keeps_the_rest() {
+        let mut game = testing::game(ITEMS, ITEMS_STORY);
+        acquire(&mut game, "torch", 2);
+
+        game.follow_link(0).unwrap();
+        assert_eq!(game.items["torch"].len(), 1);
+        assert!(!game.items["torch"][0].is_depleted());
+        assert_eq!(game.variables["still_held"], Variable::Bool(true));
+    }
+
+    #[test]
+    fn unlimited_items_are_never_depleted() {
+        let mut game = testing::game(ITEMS, ITEMS_STORY);
+        acquire(&mut game, "lamp", 1);
+
+        game.run_link_actions(vec![LinkAction::UseItem("lamp".to_owned()); 3]);
+        assert_eq!(game.variables["lit"], Variable::Num(3));
+        assert_eq!(game.items["lamp"].len(), 1);
+        assert!(!game.items["lamp"][0].is_depleted());
+    }
+
+    fn item_uses(name: &str, op: ComparisonOp, value: i32) -> Condition {
+        Condition::ItemUses(Operation {
+            name: name.to_owned(),
+            op,
+            value: Variable::Num(value),
+            value_ref: None,
+            modulo: None,
+        })
+    }
+
+    #[test]
+    fn item_uses_compares_uses_left_with_each_op() {
+        let mut game = testing::game(ITEMS, ITEMS_STORY);
+        acquire(&mut game, "candle", 1);
+        game.run_link_actions(vec![LinkAction::UseItem("candle".to_owned())]);
+
+        // The candle has 2 uses left.
+        let cases = [
+            (ComparisonOp::EQ, 2, true),
+            (ComparisonOp::EQ, 1, false),
+            (ComparisonOp::NEQ, 1, true),
+            (ComparisonOp::NEQ, 2, false),
+            (ComparisonOp::GT, 1, true),
+            (ComparisonOp::GT, 2, false),
+            (ComparisonOp::GTE, 2, true),
+            (ComparisonOp::GTE, 3, false),
+            (ComparisonOp::LT, 3, true),
+            (ComparisonOp::LT, 2, false),
+            (ComparisonOp::LTE, 2, true),
+            (ComparisonOp::LTE, 1, false),
+        ];
+        for &(op, value, expected) in cases.iter() {
+            let cond = item_uses("candle", op, value);
+            assert_eq!(game.eval_condition(&cond).unwrap(), expected, "{}", cond);
+        }
+    }
+
+    #[test]
+    fn item_uses_of_unlimited_item_is_max() {
+        let mut game = testing::game(ITEMS, ITEMS_STORY);
+        acquire(&mut game, "lamp", 1);
+
+        let cond = item_uses("lamp", ComparisonOp::EQ, i32::MAX);
+        assert!(game.eval_condition(&cond).unwrap());
+        let cond = item_uses("lamp", ComparisonOp::LT, 1000);
+        assert!(!game.eval_condition(&cond).unwrap());
+    }
+
+    #[test]
+    fn item_uses_of_missing_item_is_zero() {
+        let game = testing::game(ITEMS, ITEMS_STORY);
+
+        let cond = item_uses("candle", ComparisonOp::EQ, 0);
+        assert!(game.eval_condition(&cond).unwrap());
+        let cond = item_uses("candle", ComparisonOp::GT, 0);
+        assert!(!game.eval_condition(&cond).unwrap());
+    }
+
+    #[test]
+    fn lacks_item_is_the_inverse_of_has_item() {
+        let mut game = testing::game(ITEMS, ITEMS_STORY);
+        let lacks: Condition = serde_yaml::from_str("lacks-item: torch").unwrap();
+        let not_has: Condition = serde_yaml::from_str("not: { has-item: torch }").unwrap();
+        assert!(game.eval_condition(&lacks).unwrap());
+        assert!(game.eval_condition(&not_has).unwrap());
+
+        acquire(&mut game, "torch", 1);
+        assert!(!game.eval_condition(&lacks).unwrap());
+        assert!(!game.eval_condition(&not_has).unwrap());
+    }
+
+    #[test]
+    fn lacks_item_of_undeclared_item_is_an_error() {
+        let story = ITEMS_STORY.replace("has-item: torch", "lacks-item: ghost");
+        let err = testing::load_game(ITEMS, &story).err().unwrap();
+        assert!(err.to_string().contains("'ghost'"), "{}", err);
+    }
+
+    #[test]
+    fn item_uses_of_undeclared_item_is_an_error() {
+        let story = r#"
+id: start
+content: "A dark room."
+links:
+  - text: "Look around"
+    dest: { page: next }
+    requires: { item-uses: "ghost < 2" }
+---
+id: next
+content: "A lit room."
+"#;
+        let err = testing::load_game(ITEMS, story).err().unwrap();
+        assert!(err.to_string().contains("'ghost'"), "{}", err);
+    }
+
+    const TRIGGERS: &str = r#"
+pages: [start, left, right]
+variables:
+  first: false
+  second: false
+"#;
+
+    fn triggers_story(stop: bool) -> String {
+        format!(
+            r#"
+id: start
+content: "A fork in the road."
+links:
+  - text: "Walk on"
+    dest: {{ page: start }}
+    triggers:
+      - condition: "first == false"
+        stop: {}
+        actions:
+          - set-var: {{ name: first, value: true }}
+          - set-dest: {{ page: left }}
+      - condition: "second == false"
+        actions:
+          - set-var: {{ name: second, value: true }}
+          - set-dest: {{ page: right }}
+---
+id: left
+content: "The left path."
+---
+id: right
+content: "The right path."
+"#,
+            stop
+        )
+    }
+
+    #[test]
+    fn all_matching_triggers_run_by_default() {
+        let mut game = testing::game(TRIGGERS, &triggers_story(false));
+        game.follow_link(0).unwrap();
+        assert_eq!(game.variables["first"], Variable::Bool(true));
+        assert_eq!(game.variables["second"], Variable::Bool(true));
+        assert_eq!(game.current_page.borrow().id, "right");
+    }
+
+    #[test]
+    fn matching_trigger_with_stop_skips_the_rest() {
+        let mut game = testing::game(TRIGGERS, &triggers_story(true));
+        game.follow_link(0).unwrap();
+        assert_eq!(game.variables["first"], Variable::Bool(true));
+        assert_eq!(game.variables["second"], Variable::Bool(false));
+        assert_eq!(game.current_page.borrow().id, "left");
+    }
+
+    #[test]
+    fn unmatched_trigger_with_stop_doesnt_skip_the_rest() {
+        let mut game = testing::game(TRIGGERS, &triggers_story(true));
+        game.variables
+            .insert("first".to_owned(), Variable::Bool(true));
+        game.follow_link(0).unwrap();
+        assert_eq!(game.variables["second"], Variable::Bool(true));
+        assert_eq!(game.current_page.borrow().id, "right");
+    }
+
+    const SHOP: &str = r#"
+pages: [shop]
+variables:
+  gold: 5
+  price: 3
+  name: price
+"#;
+
+    const SHOP_STORY: &str = r#"
+id: shop
+content: "A shop."
+links:
+  - text: "Buy"
+    dest: { page: shop }
+    requires: "gold >= price"
+  - text: "Say the password"
+    dest: { page: shop }
+    requires: 'name == "price"'
+  - text: "Say your name"
+    dest: { page: shop }
+    requires: "name == Arthur"
+"#;
+
+    #[test]
+    fn condition_value_naming_a_variable_compares_to_its_value() {
+        let mut game = testing::game(SHOP, SHOP_STORY);
+        assert!(!game.is_link_locked(0).unwrap());
+        game.variables.insert("price".to_owned(), Variable::Num(10));
+        assert!(game.is_link_locked(0).unwrap());
+    }
+
+    #[test]
+    fn quoted_condition_value_is_a_literal() {
+        let mut game = testing::game(SHOP, SHOP_STORY);
+        assert!(!game.is_link_locked(1).unwrap());
+        game.variables
+            .insert("name".to_owned(), Variable::Str("Arthur".to_owned()));
+        assert!(game.is_link_locked(1).unwrap());
+    }
+
+    #[test]
+    fn condition_value_naming_no_variable_is_a_literal() {
+        let mut game = testing::game(SHOP, SHOP_STORY);
+        assert!(game.is_link_locked(2).unwrap());
+        game.variables
+            .insert("name".to_owned(), Variable::Str("Arthur".to_owned()));
+        assert!(!game.is_link_locked(2).unwrap());
+    }
+
+    #[test]
+    fn condition_value_naming_a_mistyped_variable_is_an_error() {
+        let story = SHOP_STORY.replace("gold >= price", "gold >= name");
+        let err = testing::load_game(SHOP, &story).err().unwrap();
+        assert!(err.to_string().contains("`name`"), "{}", err);
+    }
+
+    const TOWER: &str = "pages: [gate, hall, tower]";
+
+    const TOWER_STORY: &str = r#"
+id: gate
+content: "The gate."
+links:
+  - text: "Enter"
+    dest: { page: hall }
+---
+id: hall
+content: "The hall."
+links:
+  - text: "Climb"
+    dest: { page: tower }
+---
+id: tower
+content: "The tower."
+links:
+  - text: "Start again"
+    dest: start
+  - text: "Start over"
+    dest: start-over
+"#;
+
+    fn history_ids(game: &Game) -> Vec<PageID> {
+        game.history
+            .iter()
+            .map(|item| item.page.upgrade().unwrap().borrow().id.clone())
+            .collect()
+    }
+
+    fn climb_tower() -> Game {
+        let mut game = testing::game(TOWER, TOWER_STORY);
+        game.follow_link(0).unwrap();
+        game.follow_link(0).unwrap();
+        assert_eq!(game.current_page.borrow().id, "tower");
+        game
+    }
+
+    #[test]
+    fn start_dest_goes_to_starting_page_and_records_history() {
+        let mut game = climb_tower();
+        game.follow_link(0).unwrap();
+        assert_eq!(game.current_page.borrow().id, "gate");
+        assert_eq!(history_ids(&game), vec!["gate", "hall", "tower"]);
+        assert_eq!(game.visit_counts["gate"], 2);
+
+        assert!(game.go_back());
+        assert_eq!(game.current_page.borrow().id, "tower");
+    }
+
+    #[test]
+    fn start_over_dest_goes_to_starting_page_and_clears_history() {
+        let mut game = climb_tower();
+        game.follow_link(1).unwrap();
+        assert_eq!(game.current_page.borrow().id, "gate");
+        assert!(game.history.is_empty());
+        assert!(!game.go_back());
+    }
+
+    const CLOCK: &str = r#"
+pages: [clock]
+variables:
+  hour: 5
+  name: Arthur
+"#;
+
+    const CLOCK_STORY: &str = r#"
+id: clock
+content: "A clock."
+links:
+  - text: "Wait for an odd hour"
+    dest: { page: clock }
+    requires: "hour % 2 == 1"
+"#;
+
+    #[test]
+    fn modulo_condition_compares_remainder() {
+        let mut game = testing::game(CLOCK, CLOCK_STORY);
+        assert!(!game.is_link_locked(0).unwrap());
+        game.variables.insert("hour".to_owned(), Variable::Num(4));
+        assert!(game.is_link_locked(0).unwrap());
+        // The remainder is never negative, so odd negative numbers are still odd.
+        game.variables.insert("hour".to_owned(), Variable::Num(-3));
+        assert!(!game.is_link_locked(0).unwrap());
+    }
+
+    #[test]
+    fn modulo_by_zero_or_negative_is_an_error() {
+        for modulo in ["0", "-1", "-3"].iter() {
+            let story = CLOCK_STORY.replace("hour % 2", &format!("hour % {}", modulo));
+            let err = testing::load_game(CLOCK, &story).err().unwrap();
+            assert!(err.to_string().contains("positive"), "{}: {}", modulo, err);
+        }
+    }
+
+    #[test]
+    fn modulo_of_min_number_doesnt_overflow() {
+        let mut game = testing::game(CLOCK, CLOCK_STORY);
+        game.variables
+            .insert("hour".to_owned(), Variable::Num(i32::MIN));
+        assert!(game.is_link_locked(0).unwrap());
+    }
+
+    const SUNDIAL: &str = "pages: [garden, path]";
+
+    const SUNDIAL_STORY: &str = r#"
+id: garden
+content: "A sundial."
+links:
+  - text: "Take the path"
+    dest: { page: path }
+  - text: "Read the sundial by day"
+    dest: { page: garden }
+    requires: "turn % 2 == 1"
+---
+id: path
+content: "A garden path."
+links:
+  - text: "Return to the garden"
+    dest: { page: garden }
+"#;
+
+    #[test]
+    fn turn_counts_pages_entered() {
+        let mut game = testing::game(SUNDIAL, SUNDIAL_STORY);
+        let is_odd_turn: Condition = serde_yaml::from_str(r#""turn % 2 == 1""#).unwrap();
+        assert_eq!(game.turn(), 1);
+        assert!(!game.is_link_locked(1).unwrap());
+
+        game.follow_link(0).unwrap();
+        assert_eq!(game.turn(), 2);
+        assert!(!game.eval_condition(&is_odd_turn).unwrap());
+
+        game.follow_link(0).unwrap();
+        assert_eq!(game.turn(), 3);
+        assert!(!game.is_link_locked(1).unwrap());
+    }
+
+    #[test]
+    fn declared_turn_variable_shadows_turn_count() {
+        let settings = format!("{}\nvariables: {{ turn: 2 }}", SUNDIAL);
+        let mut game = testing::game(&settings, SUNDIAL_STORY);
+        game.follow_link(0).unwrap();
+        game.follow_link(0).unwrap();
+        assert_eq!(game.turn(), 3);
+        assert!(game.is_link_locked(1).unwrap());
+
+        let settings = format!("{}\nvariables: {{ turn: day }}", SUNDIAL);
+        let err = testing::load_game(&settings, SUNDIAL_STORY).err().unwrap();
+        assert!(err.to_string().contains("`turn`"), "{}", err);
+    }
+
+    #[test]
+    fn modulo_of_non_number_is_an_error() {
+        let story = CLOCK_STORY.replace("hour % 2 == 1", "name % 2 == 1");
+        let err = testing::load_game(CLOCK, &story).err().unwrap();
+        assert!(err.to_string().contains("`name`"), "{}", err);
+    }
+
+    const DICE: &str = r#"
+pages: [table]
+variables:
+  roll: 0
+  name: Arthur
+"#;
+
+    const DICE_STORY: &str = r#"
+id: table
+content: "A table."
+links:
+  - text: "Roll a die"
+    dest: { page: table }
+    actions:
+      - roll: { name: roll, min: 1, max: 6 }
+  - text: "Roll a loaded die"
+    dest: { page: table }
+    actions:
+      - roll: { name: roll, min: 6, max: 6 }
+"#;
+
+    #[test]
+    fn roll_sets_variable_within_range() {
+        let mut game = testing::game(DICE, DICE_STORY);
+        for _ in 0..100 {
+            game.follow_link(0).unwrap();
+            match game.variables["roll"] {
+                Variable::Num(roll) => assert!((1..=6).contains(&roll), "{}", roll),
+                ref var => panic!("expected a number, got {}", var),
+            }
+        }
+        game.follow_link(1).unwrap();
+        assert_eq!(game.variables["roll"], Variable::Num(6));
+    }
+
+    #[test]
+    fn roll_with_min_above_max_is_an_error() {
+        let story = DICE_STORY.replace("min: 1, max: 6", "min: 6, max: 1");
+        assert!(testing::load_game(DICE, &story).is_err());
+    }
+
+    #[test]
+    fn roll_into_non_number_is_an_error() {
+        let story = DICE_STORY.replace("name: roll, min: 1", "name: name, min: 1");
+        let err = testing::load_game(DICE, &story).err().unwrap();
+        assert!(err.to_string().contains("`name`"), "{}", err);
+    }
+
+    fn rolls(seed: u64) -> Vec<Variable> {
+        let mut game = testing::game(&format!("{}seed: {}\n", DICE, seed), DICE_STORY);
+        (0..20)
+            .map(|_| {
+                game.follow_link(0).unwrap();
+                game.variables["roll"].clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_rolls() {
+        assert_eq!(rolls(7), rolls(7));
+        assert_ne!(rolls(7), rolls(8));
+    }
+
+    #[test]
+    fn same_seed_gives_same_random_starting_values() {
+        let settings = r#"
+pages: [table]
+seed: 99
+variables:
+  luck: { initial: { random: [1, 1000] } }
+"#;
+        let story = "id: table\ncontent: A table.";
+        let luck = || testing::game(settings, story).variables["luck"].clone();
+        assert_eq!(luck(), luck());
+    }
+
+    #[test]
+    fn with_seed_gives_same_rolls() {
+        let settings = testing::read_settings(
+            "entrypoint: story.yaml\npages: [table]\nvariables: { roll: 0 }",
+            &[],
+        );
+        let page = Rc::new(RefCell::new(
+            serde_yaml::from_str::<Page>("id: table\ncontent: A table.").unwrap(),
+        ));
+        let rolls = |seed| {
+            let mut game = Game::with_seed(
+                settings.metadata(),
+                &page,
+                settings.variables(),
+                settings.items(),
+                settings.helpers(),
+                &[],
+                seed,
+            );
+            let roll = LinkAction::Roll {
+                name: "roll".to_owned(),
+                min: 1,
+                max: 100,
+            };
+            (0..20)
+                .map(|_| {
+                    game.run_link_actions(vec![roll.clone()]);
+                    game.variables["roll"].clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rolls(3), rolls(3));
+        assert_ne!(rolls(3), rolls(4));
+    }
+
+    const CORRIDOR: &str = "pages: [east, west]\nhistory_limit: 3";
+
+    const CORRIDOR_STORY: &str = r#"
+id: east
+content: "The east end."
+links:
+  - text: "Walk west"
+    dest: { page: west }
+  - text: "Go back"
+    dest: previous
+---
+id: west
+content: "The west end."
+links:
+  - text: "Walk east"
+    dest: { page: east }
+  - text: "Go back"
+    dest: previous
+"#;
+
+    #[test]
+    fn history_is_capped_at_limit() {
+        let mut game = testing::game(CORRIDOR, CORRIDOR_STORY);
+        for n in 1..=4 {
+            game.follow_link(0).unwrap();
+            assert_eq!(game.history.len(), n.min(3));
+        }
+        assert_eq!(history_ids(&game), vec!["west", "east", "west"]);
+        assert_eq!(game.current_page.borrow().id, "east");
+    }
+
+    #[test]
+    fn going_back_past_history_limit_tells_player() {
+        let mut game = testing::game(CORRIDOR, CORRIDOR_STORY);
+        for _ in 0..4 {
+            game.follow_link(0).unwrap();
+        }
+        for _ in 0..3 {
+            game.follow_link(1).unwrap();
+        }
+        assert!(game.history.is_empty());
+        assert_eq!(game.current_page.borrow().id, "west");
+        assert!(game.pop_prompt().is_none());
+
+        game.follow_link(1).unwrap();
+        assert_eq!(game.current_page.borrow().id, "west");
+        let prompt = game.pop_prompt().unwrap();
+        assert_eq!(prompt.text, "You can't go back any further.");
+    }
+
+    const ARENA: &str = r#"
+pages: [camp, arena]
+variables:
+  hp: 10
+items:
+  trophy:
+    effect: { mod-num: { name: hp, value: 1 } }
+"#;
+
+    const ARENA_STORY: &str = r#"
+id: camp
+content: "A quiet camp."
+links:
+  - text: "Rest"
+    dest: { page: camp }
+    actions:
+      - set-checkpoint
+  - text: "Enter the arena"
+    dest: { page: arena }
+    actions:
+      - mod-num: { name: hp, value: -7 }
+      - acquire-item: trophy
+---
+id: arena
+content: "You fought bravely."
+links:
+  - text: "Try again"
+    dest: checkpoint
+"#;
+
+    fn trophies(game: &Game) -> usize {
+        game.items.get("trophy").map_or(0, VecDeque::len)
+    }
+
+    #[test]
+    fn checkpoint_dest_restores_variables_and_items() {
+        let mut game = testing::game(ARENA, ARENA_STORY);
+        game.follow_link(0).unwrap();
+        game.follow_link(1).unwrap();
+        assert_eq!(game.current_page.borrow().id, "arena");
+        assert_eq!(game.variables["hp"], Variable::Num(3));
+        assert_eq!(trophies(&game), 1);
+
+        game.follow_link(0).unwrap();
+        assert_eq!(game.current_page.borrow().id, "camp");
+        assert_eq!(game.variables["hp"], Variable::Num(10));
+        assert_eq!(trophies(&game), 0);
+        assert!(game.pop_prompt().is_none());
+    }
+
+    #[test]
+    fn checkpoint_dest_without_checkpoint_stays_on_page() {
+        let mut game = testing::game(ARENA, ARENA_STORY);
+        game.follow_link(1).unwrap();
+        game.follow_link(0).unwrap();
+        assert_eq!(game.current_page.borrow().id, "arena");
+        assert_eq!(game.variables["hp"], Variable::Num(3));
+        let prompt = game.pop_prompt().unwrap();
+        assert_eq!(prompt.text, "There's no checkpoint to return to.");
+    }
+
+    const NOTES: &str = r#"
+pages: [study]
+variables:
+  suspect: butler
+"#;
+
+    const NOTES_STORY: &str = r#"
+id: study
+content: "A study."
+links:
+  - text: "Search the desk"
+    dest: { page: study }
+    actions:
+      - add-note: { text: "The {{suspect}} lied.", once: true }
+      - add-note: "The desk is empty."
+  - text: "Suspect the maid"
+    dest: { page: study }
+    actions:
+      - set-var: { name: suspect, value: maid }
+"#;
+
+    #[test]
+    fn notes_added_once_are_deduplicated_by_text() {
+        let mut game = testing::game(NOTES, NOTES_STORY);
+        game.follow_link(0).unwrap();
+        game.follow_link(0).unwrap();
+        assert_eq!(
+            game.journal,
+            vec![
+                "The butler lied.",
+                "The desk is empty.",
+                "The desk is empty.",
+            ]
+        );
+
+        // The text is compared after interpolation, so a different suspect is a new note.
+        game.follow_link(1).unwrap();
+        game.follow_link(0).unwrap();
+        assert_eq!(game.journal.len(), 5);
+        assert_eq!(game.journal[3], "The maid lied.");
+    }
+
+    const GOLD: &str = r#"
+pages: [market, inn]
+variables:
+  gold: 5
+  hungry: true
+"#;
+
+    const GOLD_STORY: &str = r#"
+id: market
+content: "A busy market."
+links:
+  - text: "Go to the inn"
+    dest: { page: inn }
+    requires: { and: [hungry, "gold >= 3"] }
+---
+id: inn
+content: "A quiet inn."
+"#;
+
+    #[test]
+    fn condition_on_missing_variable_is_an_error() {
+        let mut game = testing::game(GOLD, GOLD_STORY);
+        game.variables.remove("gold");
+
+        let cond: Condition = serde_yaml::from_str(r#"{ and: [hungry, "gold >= 3"] }"#).unwrap();
+        let err = game.eval_condition(&cond).unwrap_err();
+        assert!(
+            matches!(&err, Error::UndeclaredVariable(name) if name == "gold"),
+            "{}",
+            err
+        );
+
+        assert!(game.follow_link(0).is_err());
+        assert_eq!(game.current_page.borrow().id, "market");
+    }
+
+    #[test]
+    fn save_with_undeclared_variable_is_an_error() {
+        let mut game = testing::game(GOLD, GOLD_STORY);
+        let mut save = game.to_save();
+        save.variables.insert("silver".to_owned(), Variable::Num(1));
+
+        let err = game.restore(save).unwrap_err();
+        assert!(
+            matches!(&err, Error::UndeclaredVariable(name) if name == "silver"),
+            "{}",
+            err
+        );
+        assert_eq!(game.variables["gold"], Variable::Num(5));
+    }
+
+    #[test]
+    fn save_records_story_version() {
+        let game = testing::game(&format!("version: \"2.1.0\"\n{}", GOLD), GOLD_STORY);
+        let save = game.to_save();
+        assert_eq!(save.version, Some(Version::new(2, 1, 0)));
+
+        let path = testing::temp_dir(&[]).join("test.save.yaml");
+        save.write(&path).unwrap();
+        assert_eq!(GameSave::read(&path).unwrap().version, save.version);
+    }
+
+    #[test]
+    fn save_from_other_major_version_is_incompatible() {
+        let mut game = testing::game(&format!("version: \"2.1.0\"\n{}", GOLD), GOLD_STORY);
+        let mut save = game.to_save();
+        let with_version = |save_version| GameSave {
+            version: save_version,
+            ..save.clone()
+        };
+        assert!(game.is_compatible_save(&with_version(Some(Version::new(2, 0, 3)))));
+        assert!(game.is_compatible_save(&with_version(Some(Version::new(2, 4, 0)))));
+        assert!(game.is_compatible_save(&with_version(None)));
+        assert!(!game.is_compatible_save(&with_version(Some(Version::new(1, 1, 0)))));
+        assert!(!game.is_compatible_save(&with_version(Some(Version::new(3, 0, 0)))));
+
+        // An incompatible save can still be restored if the player chooses to.
+        save.version = Some(Version::new(1, 0, 0));
+        save.variables.insert("gold".to_owned(), Variable::Num(1));
+        game.restore(save).unwrap();
+        assert_eq!(game.variables["gold"], Variable::Num(1));
+    }
+
+    #[test]
+    fn any_save_is_compatible_with_unversioned_story() {
+        let game = testing::game(GOLD, GOLD_STORY);
+        let mut save = game.to_save();
+        assert_eq!(save.version, None);
+        save.version = Some(Version::new(7, 0, 0));
+        assert!(game.is_compatible_save(&save));
+    }
+}
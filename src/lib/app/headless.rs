@@ -0,0 +1,161 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use cursive::utils::markup::StyledString;
+
+use crate::app::{render::interpolate, Game};
+use crate::errors::{Error, Result};
+use crate::parser::{self, LazyPages, Settings};
+use crate::types::{CreationStep, Prompt};
+
+/// Drives a [`Game`] through plain function calls instead of the terminal UI, e.g. to script a
+/// playthrough or test a story.
+///
+/// Character creation is played like the rest of the story: prompts are answered with
+/// [`submit_prompt`](Self::submit_prompt), and items are chosen with [`choose`](Self::choose).
+pub struct Headless {
+    pub game: Game,
+    /// The prompt waiting for an answer, once it has been taken from the game's queue.
+    prompt: Option<Prompt>,
+}
+
+impl Headless {
+    pub fn new(game: Game) -> Self {
+        Headless { game, prompt: None }
+    }
+
+    /// Reads and parses the storygame whose settings file is at `path`, and starts a game.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let settings = Settings::read(path)?;
+        let (starting_page, pages, lazy_pages) = if settings.lazy() {
+            let mut pages = LazyPages::new(&settings)?;
+            let starting_page = pages.entrypoint()?;
+            (
+                starting_page,
+                HashMap::new(),
+                Some(Rc::new(RefCell::new(pages))),
+            )
+        } else {
+            let (page_id, pages, _) = parser::parse_story(&settings)?;
+            (Rc::clone(&pages[&page_id]), pages, None)
+        };
+        let mut game = Game::from_settings(&settings, &starting_page);
+        game.pages = pages;
+        game.lazy_pages = lazy_pages;
+        Ok(Headless::new(game))
+    }
+
+    /// Returns the title of the current page, if it has one.
+    pub fn current_title(&self) -> Option<String> {
+        self.game.current_page.borrow().title.clone()
+    }
+
+    /// Returns the text the player would currently see: the current character creation step
+    /// during character creation, or else the content of the current page.
+    pub fn current_text(&self) -> StyledString {
+        match self.game.creation_step() {
+            Some((_, CreationStep::Prompt(Prompt { text, .. })))
+            | Some((_, CreationStep::ChooseItem { text, .. })) => interpolate(text, &self.game),
+            None => interpolate(&self.game.current_page.borrow().content, &self.game),
+        }
+    }
+
+    /// Returns the text of each choice available to the player, in order: the items to choose
+    /// from during a character creation step that offers them, or else the links on the current
+    /// page whose requirements are met.
+    pub fn choices(&self) -> Vec<String> {
+        match self.game.creation_step() {
+            Some((_, CreationStep::ChooseItem { items, .. })) => items.clone(),
+            Some((_, CreationStep::Prompt(_))) => Vec::new(),
+            None => {
+                let page = self.game.current_page.borrow();
+                self.game
+                    .filter_active_links(&page.links)
+                    .map(|(_, link)| interpolate(&link.text, &self.game).source().to_owned())
+                    .collect()
+            }
+        }
+    }
+
+    /// Makes the choice at index `idx` of [`choices`](Self::choices).
+    ///
+    /// Returns the ending message if the choice ends the game. Fails if a [`prompt`](Self::prompt)
+    /// is waiting for an answer, since the player can't follow links until it's answered.
+    pub fn choose(&mut self, idx: usize) -> Result<Option<String>> {
+        if let Some((_, step)) = self.game.creation_step() {
+            let name = match step {
+                CreationStep::ChooseItem { items, .. } => items.get(idx).cloned(),
+                CreationStep::Prompt(_) => None,
+            };
+            let name =
+                name.ok_or_else(|| Error::message(format!("no item to choose at {}", idx)))?;
+            self.game.choose_creation_item(&name);
+            self.game.next_creation_step();
+            return Ok(None);
+        }
+
+        let link_idx = {
+            let page = self.game.current_page.borrow();
+            let link_idx = self
+                .game
+                .filter_active_links(&page.links)
+                .nth(idx)
+                .map(|(link_idx, _)| link_idx);
+            link_idx.ok_or_else(|| Error::no_such_link(&page.id, idx))?
+        };
+        if self.prompt().is_some() {
+            return Err(Error::message("a prompt is waiting for an answer"));
+        }
+        let msg = self.game.follow_link(link_idx)?;
+        Ok(msg.map(|msg| interpolate(&msg, &self.game).source().to_owned()))
+    }
+
+    /// Returns the prompt waiting for an answer, if any.
+    ///
+    /// Prompts from the story must be answered with [`submit_prompt`](Self::submit_prompt), or
+    /// dismissed by submitting anything if they don't ask for a variable.
+    pub fn prompt(&mut self) -> Option<&Prompt> {
+        if self.game.character_creation.is_none() && self.prompt.is_none() {
+            self.prompt = self.game.pop_prompt();
+        }
+        match self.game.creation_step() {
+            Some((_, CreationStep::Prompt(prompt))) => Some(prompt),
+            Some(_) => None,
+            None => self.prompt.as_ref(),
+        }
+    }
+
+    /// Answers the current [`prompt`](Self::prompt) with `value`, which is parsed according to the
+    /// type of the prompt's variable, if it has one, and checked against the prompt's `choices`,
+    /// `min`, and `max`.
+    ///
+    /// Fails without answering the prompt if there's no prompt or `value` is invalid.
+    pub fn submit_prompt(&mut self, value: &str) -> Result<()> {
+        let prompt = match self.prompt() {
+            Some(prompt) => prompt.clone(),
+            None => return Err(Error::message("there is no prompt to submit")),
+        };
+        if let Some(var_name) = &prompt.variable {
+            let var = self
+                .game
+                .variables
+                .get_mut(var_name)
+                .ok_or_else(|| Error::undeclared_variable(var_name))?;
+            let var_type = var.type_();
+            let value = var_type
+                .parse(value)
+                .ok_or_else(|| Error::expected(format!("a {} for '{}'", var_type, var_name)))?;
+            prompt.check_value(&value).map_err(Error::message)?;
+            *var = value;
+        }
+
+        if self.game.creation_step().is_some() {
+            self.game.next_creation_step();
+        } else {
+            self.prompt = None;
+        }
+        Ok(())
+    }
+}
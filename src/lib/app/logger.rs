@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Mutex;
 
 use cursive::{theme, Printer, Vec2, View};
@@ -8,7 +8,9 @@ use log4rs::append::{
     console::{ConsoleAppender, Target},
     rolling_file::{
         policy::compound::{
-            roll::fixed_window::FixedWindowRoller, trigger::size::SizeTrigger, CompoundPolicy,
+            roll::{fixed_window::FixedWindowRoller, Roll},
+            trigger::size::SizeTrigger,
+            CompoundPolicy,
         },
         RollingFileAppender,
     },
@@ -19,6 +21,7 @@ use log4rs::filter::{self, threshold::ThresholdFilter};
 use same_file::is_same_file;
 
 use crate::errors::{Error, Result};
+use crate::utils;
 
 const LOG_MAX_FILE_SIZE: u64 = 100_000;
 const LOG_MAX_ARCHIVED_FILES: u32 = 2;
@@ -88,57 +91,95 @@ impl log::Log for MemoryLogger {
 }
 
 pub struct LogConfig<'a> {
-    pub dest_dir: &'a Path,
+    /// Whether to write logs to a file. If not, no log files or directories are created.
+    pub enabled: bool,
+    pub dest_dir: PathBuf,
     pub base_file_name: &'a str,
     pub file_ext: Option<&'a str>,
     pub level: log::LevelFilter,
+    /// The size in bytes at which the log file is archived and a new one is started.
+    pub max_file_size: u64,
+    /// The number of archived log files to keep.
+    pub max_archived_files: u32,
+    /// Whether to archive the existing log file and start a new one when the config is applied
+    /// with [`Logger::set_config`], i.e. when a storygame is loaded.
+    pub new_file_per_session: bool,
 }
 
 impl<'a> Default for LogConfig<'a> {
     fn default() -> Self {
         LogConfig {
-            dest_dir: Path::new(concat!("/tmp/", progname!())),
+            enabled: true,
+            dest_dir: utils::data_dir(),
             base_file_name: progname!(),
             file_ext: Some("log"),
             level: log::LevelFilter::Debug,
+            max_file_size: LOG_MAX_FILE_SIZE,
+            max_archived_files: LOG_MAX_ARCHIVED_FILES,
+            new_file_per_session: false,
         }
     }
 }
 
 impl<'a> LogConfig<'a> {
+    /// Returns the path of the log file, if logs are written to a file.
+    fn file_dest(&self) -> Option<PathBuf> {
+        if self.enabled {
+            Some(self.dest())
+        } else {
+            None
+        }
+    }
+
     fn dest(&self) -> PathBuf {
         self.dest_dir
             .join(format!("{}{}", self.base_file_name, self.fmt_file_ext()))
     }
 
     fn build(&self) -> Result<Config> {
+        if !self.enabled {
+            return self.build_with(None);
+        }
         let dest = self.dest();
+        fs::create_dir_all(&self.dest_dir)?;
 
         let log_file = RollingFileAppender::builder()
             .encoder(Box::new(PatternEncoder::default()))
             .build(
                 dest,
                 Box::new(CompoundPolicy::new(
-                    Box::new(SizeTrigger::new(LOG_MAX_FILE_SIZE)),
-                    Box::new(
-                        FixedWindowRoller::builder()
-                            .build(
-                                self.dest_dir
-                                    .join(format!(
-                                        "{}.{{}}{}",
-                                        self.base_file_name,
-                                        self.fmt_file_ext()
-                                    ))
-                                    .to_str()
-                                    .unwrap(),
-                                LOG_MAX_ARCHIVED_FILES,
-                            )
-                            .map_err(|e| Error::Std(e))?,
-                    ),
+                    Box::new(SizeTrigger::new(self.max_file_size)),
+                    Box::new(self.roller()?),
                 )),
             )
             .map_err(Error::logger)?;
 
+        self.build_with(Some(log_file))
+    }
+
+    /// Builds the roller that archives log files, as `<base_file_name>.<n>.<file_ext>`.
+    fn roller(&self) -> Result<FixedWindowRoller> {
+        FixedWindowRoller::builder()
+            .build(
+                self.dest_dir
+                    .join(format!(
+                        "{}.{{}}{}",
+                        self.base_file_name,
+                        self.fmt_file_ext()
+                    ))
+                    .to_str()
+                    .unwrap(),
+                self.max_archived_files,
+            )
+            .map_err(|e| Error::Std(e))
+    }
+
+    /// Builds a config that doesn't write logs to a file, for when the log file can't be created.
+    fn build_fallback(&self) -> Result<Config> {
+        self.build_with(None)
+    }
+
+    fn build_with(&self, log_file: Option<RollingFileAppender>) -> Result<Config> {
         let new_appender = || {
             Appender::builder()
                 .filter(Box::new(ThresholdFilter::new(self.level)))
@@ -148,9 +189,12 @@ impl<'a> LogConfig<'a> {
                 )))
         };
 
-        let mut builder =
-            Config::builder().appender(new_appender().build("log_file", Box::new(log_file)));
-        let mut root_builder = Root::builder().appender("log_file");
+        let mut builder = Config::builder();
+        let mut root_builder = Root::builder();
+        if let Some(log_file) = log_file {
+            builder = builder.appender(new_appender().build("log_file", Box::new(log_file)));
+            root_builder = root_builder.appender("log_file");
+        }
 
         #[cfg(debug_assertions)]
         {
@@ -174,16 +218,29 @@ impl<'a> LogConfig<'a> {
 }
 
 pub struct Logger {
-    dest: PathBuf,
+    /// The path of the log file, or `None` if logs aren't being written to a file.
+    dest: Option<PathBuf>,
     handle: log4rs::Handle,
+    /// The error that prevented the log file from being created, if any.
+    error: Option<Error>,
 }
 
 impl Logger {
+    /// Creates a logger from `config`.
+    ///
+    /// If the log file can't be created (e.g. because its directory isn't writable), the logger
+    /// falls back to not writing logs to a file, and the error can be retrieved with
+    /// [`take_error`](Self::take_error).
     pub fn new(config: LogConfig) -> Result<Self> {
-        let handle = log4rs::init_config(config.build()?)?;
+        let (log_config, dest, error) = match config.build() {
+            Ok(log_config) => (log_config, config.file_dest(), None),
+            Err(err) => (config.build_fallback()?, None, Some(err)),
+        };
+        let handle = log4rs::init_config(log_config)?;
         Ok(Logger {
-            dest: config.dest(),
+            dest,
             handle,
+            error,
         })
     }
 
@@ -191,29 +248,57 @@ impl Logger {
         Logger::new(LogConfig::default())
     }
 
+    /// Returns the error that prevented the log file from being created, if any, leaving `None`
+    /// in its place.
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+
     pub fn set_config(&mut self, cfg: LogConfig) -> Result<()> {
-        let prev_dest = self.dest.as_path();
-        let dest = cfg.dest();
+        let prev_dest = self.dest.as_deref();
+        let dest = match cfg.file_dest() {
+            Some(dest) => dest,
+            None => {
+                // Logs aren't written to a file, so there's nowhere to move past logs to.
+                self.handle.set_config(cfg.build()?);
+                self.dest = None;
+                return Ok(());
+            }
+        };
+
+        // Archive the log file from the previous session, unless it's the current log file.
+        if cfg.new_file_per_session && dest.is_file() {
+            let is_current =
+                prev_dest.is_some_and(|prev_dest| is_same_file(prev_dest, &dest).unwrap_or(false));
+            if !is_current {
+                cfg.roller()?.roll(&dest).map_err(|e| Error::Std(e))?;
+            }
+        }
 
         // Move past logs to new log file, if different.
-        match is_same_file(prev_dest, &dest) {
-            Ok(false) | Err(_) => {
-                if let Err(err) = fs::copy(prev_dest, &dest) {
-                    warn!(
-                        "error moving logs from '{}' to new log file '{}': {}",
-                        prev_dest.display(),
-                        dest.display(),
-                        err,
-                    );
+        if let Some(prev_dest) = prev_dest {
+            fs::create_dir_all(&cfg.dest_dir)?;
+            match is_same_file(prev_dest, &dest) {
+                Ok(false) | Err(_) => {
+                    if let Err(err) = fs::copy(prev_dest, &dest) {
+                        warn!(
+                            "error moving logs from '{}' to new log file '{}': {}",
+                            prev_dest.display(),
+                            dest.display(),
+                            err,
+                        );
+                    }
                 }
+                _ => {}
             }
-            _ => {}
         }
 
         match cfg.build() {
             Ok(config) => {
-                let _ = fs::remove_file(prev_dest);
-                self.dest = dest;
+                if let Some(prev_dest) = prev_dest {
+                    let _ = fs::remove_file(prev_dest);
+                }
+                self.dest = Some(dest);
                 self.handle.set_config(config);
                 Ok(())
             }
@@ -268,3 +353,35 @@ impl View for LogView {
         Vec2::new(w, h)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn config(enabled: bool, dest_dir: PathBuf) -> LogConfig<'static> {
+        LogConfig {
+            enabled,
+            dest_dir,
+            ..LogConfig::default()
+        }
+    }
+
+    #[test]
+    fn disabled_config_creates_no_files() {
+        let dest_dir = testing::temp_dir(&[]).join("logs");
+        let cfg = config(false, dest_dir.clone());
+        assert!(cfg.build().is_ok());
+        assert_eq!(cfg.file_dest(), None);
+        assert!(!dest_dir.exists());
+    }
+
+    #[test]
+    fn enabled_config_creates_log_dir() {
+        let dest_dir = testing::temp_dir(&[]).join("logs");
+        let cfg = config(true, dest_dir.clone());
+        assert!(cfg.build().is_ok());
+        assert_eq!(cfg.file_dest(), Some(dest_dir.join("storygamer.log")));
+        assert!(dest_dir.is_dir());
+    }
+}
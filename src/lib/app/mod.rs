@@ -1,10 +1,19 @@
 pub mod core;
+pub mod headless;
 pub mod logger;
+pub mod render;
 pub mod ui;
 
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use cursive::theme::Color;
+
 use crate::errors::Result;
+use crate::parser::{LockedChoiceDisplay, Warning};
 
 pub use self::core::Game;
+pub use self::headless::Headless;
 use self::logger::Logger;
 pub use self::ui::run;
 
@@ -12,6 +21,20 @@ pub use self::ui::run;
 pub struct AppState {
     pub game: Option<Game>,
     pub logger: Logger,
+    /// Colors for the content of pages with a given tag.
+    pub tag_colors: HashMap<String, Color>,
+    /// Whether to scroll to content appended to the current page.
+    pub autoscroll: bool,
+    /// Whether to show the destination of each link in the list of choices.
+    pub show_link_dest: bool,
+    /// Whether to show consecutive variable prompts together as a single form.
+    pub batch_prompts: bool,
+    /// How to show choices whose requirements aren't met.
+    pub locked_choice_display: LockedChoiceDisplay,
+    /// Warnings about the story that was just loaded, which have yet to be shown to the author.
+    pub load_warnings: Vec<Warning>,
+    /// The file that the player's progress in the current story is saved to.
+    pub save_path: Option<PathBuf>,
 }
 
 impl AppState {
@@ -20,6 +43,13 @@ impl AppState {
         Ok(AppState {
             game: None,
             logger: Logger::default()?,
+            tag_colors: HashMap::new(),
+            autoscroll: true,
+            show_link_dest: false,
+            batch_prompts: false,
+            locked_choice_display: LockedChoiceDisplay::Hide,
+            load_warnings: Vec::new(),
+            save_path: None,
         })
     }
 }
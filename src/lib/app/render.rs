@@ -0,0 +1,71 @@
+use cursive::utils::markup::{markdown, StyledString};
+use regex::{Captures, Regex};
+
+use crate::app::Game;
+
+lazy_static! {
+    static ref RE_MARKDOWN_IMAGE: Regex = Regex::new(r"!\[([^\]]*)\]\([^)]*\)").unwrap();
+}
+
+/// Render `content` as a template with the state of `game`, then parse the result as Markdown.
+///
+/// See [`Game::render_template`] for how templates are rendered.
+pub fn interpolate(content: &str, game: &Game) -> StyledString {
+    let content = game.render_template(content);
+    // Images can't be shown in the terminal, so replace them with a placeholder.
+    let content = RE_MARKDOWN_IMAGE.replace_all(&content, |caps: &Captures| match caps[1].trim() {
+        "" => "*[image]*".to_string(),
+        alt => format!("*[image: {}]*", alt),
+    });
+    markdown::parse(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use cursive::theme::Effect;
+
+    use super::*;
+    use crate::testing;
+
+    fn game() -> Game {
+        testing::game(
+            "pages: [start]\nvariables: { name: Arthur, gold: 5 }",
+            "id: start\ncontent: Start.",
+        )
+    }
+
+    /// Returns the text of `content` without its styles.
+    fn text(content: &StyledString) -> String {
+        content.spans().map(|span| span.content).collect()
+    }
+
+    #[test]
+    fn renders_variables() {
+        let content = interpolate("Hello, {{name}}. You have {{gold}} gold.", &game());
+        assert_eq!(text(&content), "Hello, Arthur. You have 5 gold.");
+    }
+
+    #[test]
+    fn parses_markdown_after_rendering() {
+        let content = interpolate("You are **{{name}}**.", &game());
+        assert_eq!(text(&content), "You are Arthur.");
+        assert!(content
+            .spans()
+            .any(|span| span.content == "Arthur" && span.attr.effects.contains(Effect::Bold)));
+    }
+
+    #[test]
+    fn falls_back_to_template_on_error() {
+        let content = interpolate("Hello, {{#if name}}.", &game());
+        assert_eq!(text(&content), "Hello, {{#if name}}.");
+    }
+
+    #[test]
+    fn replaces_images() {
+        let content = interpolate("![a map](map.png) ![](x.png)", &game());
+        assert_eq!(text(&content), "[image: a map] [image]");
+        assert!(content.spans().all(
+            |span| span.attr.effects.contains(Effect::Italic) || span.content.trim().is_empty()
+        ));
+    }
+}
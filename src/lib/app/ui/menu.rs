@@ -1,18 +1,23 @@
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::num::NonZeroU64;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use cursive::align::{Align, HAlign};
 use cursive::event::Event;
+use cursive::theme::Color;
 use cursive::traits::{Nameable, Resizable, Scrollable};
 use cursive::views::{Dialog, LinearLayout, OnEventView, Panel, SelectView, TextView};
 use cursive::Cursive;
 
-use crate::app::{logger::LogConfig, AppState, Game};
-use crate::parser::{self, Settings};
+use crate::app::{core::GameSave, logger::LogConfig, AppState, Game};
+use crate::parser::{self, LazyPages, Settings};
 use crate::utils::is_parent_path;
 
-use super::{on_menu_back, redraw_content};
+use super::{on_menu_back, redraw_content, show_load_warnings};
 
 mod constants {
     pub mod container {
@@ -43,6 +48,7 @@ pub fn open(siv: &mut Cursive) {
                 load_storygame(s, path);
                 s.pop_layer();
                 redraw_content(s);
+                show_load_warnings(s);
             }
         })
         .autojump()
@@ -71,8 +77,23 @@ pub fn load_storygame<P: AsRef<Path>>(siv: &mut Cursive, path: P) {
     let settings = unwrap_or_notify!(siv, Settings::read(path));
     debug!("loading storygame: parsed settings");
 
-    let starting_page = unwrap_or_notify!(siv, parser::parse(&settings));
-    let game = Game::new(&starting_page, settings.variables(), settings.items());
+    // Lazily parsed pages aren't validated as a whole, so there are no warnings to show.
+    let (starting_page, pages, lazy_pages, warnings) = if settings.lazy() {
+        let mut pages = unwrap_or_notify!(siv, LazyPages::new(&settings));
+        let starting_page = unwrap_or_notify!(siv, pages.entrypoint());
+        (
+            starting_page,
+            HashMap::new(),
+            Some(Rc::new(RefCell::new(pages))),
+            Vec::new(),
+        )
+    } else {
+        let (page_id, pages, warnings) = unwrap_or_notify!(siv, parser::parse_story(&settings));
+        (Rc::clone(&pages[&page_id]), pages, None, warnings)
+    };
+    let mut game = Game::from_settings(&settings, &starting_page);
+    game.pages = pages;
+    game.lazy_pages = lazy_pages;
     debug!("loading storygame: parsed game");
 
     // Update app state.
@@ -80,9 +101,25 @@ pub fn load_storygame<P: AsRef<Path>>(siv: &mut Cursive, path: P) {
         siv,
         siv.with_user_data(|app: &mut AppState| {
             app.game.replace(game);
+            app.tag_colors = settings
+                .tag_colors()
+                .iter()
+                .filter_map(|(tag, color)| Some((tag.clone(), Color::parse(color)?)))
+                .collect();
+            app.autoscroll = settings.autoscroll();
+            app.show_link_dest = settings.show_link_dest();
+            app.batch_prompts = settings.batch_prompts();
+            app.locked_choice_display = settings.locked_choice_display();
+            app.save_path = settings.save_path();
+            app.load_warnings = if settings.author_mode() {
+                warnings
+            } else {
+                Vec::new()
+            };
             let log = settings.logger();
             let default = LogConfig::default();
             app.logger.set_config(LogConfig {
+                enabled: log.enabled,
                 base_file_name: log
                     .base_file_name
                     .as_ref()
@@ -98,7 +135,12 @@ pub fn load_storygame<P: AsRef<Path>>(siv: &mut Cursive, path: P) {
                         s => Some(s),
                     }),
                 level: log.level.unwrap_or_else(|| default.level),
-                ..Default::default()
+                max_file_size: log
+                    .max_file_size
+                    .map_or(default.max_file_size, NonZeroU64::get),
+                max_archived_files: log.max_archived_files.unwrap_or(default.max_archived_files),
+                new_file_per_session: log.new_file_per_session,
+                dest_dir: log.dest_dir.clone().unwrap_or(default.dest_dir),
             })
         })
         .transpose()
@@ -106,6 +148,76 @@ pub fn load_storygame<P: AsRef<Path>>(siv: &mut Cursive, path: P) {
     debug!("loading storygame: complete");
 }
 
+/// Restore the player's progress in the current story from its save file.
+pub fn load_progress(siv: &mut Cursive) {
+    let save_path = siv
+        .with_user_data(|app: &mut AppState| {
+            app.game.as_ref()?;
+            app.save_path.clone()
+        })
+        .flatten();
+    let save_path = match save_path {
+        Some(save_path) if save_path.exists() => save_path,
+        Some(_) => {
+            siv.add_layer(Dialog::info(
+                "No progress has been saved for this story yet.",
+            ));
+            return;
+        }
+        None => {
+            siv.add_layer(Dialog::info("There is no story open to load progress for."));
+            return;
+        }
+    };
+
+    let save = unwrap_or_notify!(siv, GameSave::read(&save_path));
+    let story_version = siv
+        .with_user_data(|app: &mut AppState| {
+            let game = app.game.as_ref()?;
+            if game.is_compatible_save(&save) {
+                None
+            } else {
+                game.metadata.version
+            }
+        })
+        .flatten();
+    match (story_version, save.version) {
+        (Some(story_version), Some(save_version)) => {
+            warn!(
+                "save version {} is incompatible with story version {}",
+                save_version, story_version
+            );
+            siv.add_layer(
+                Dialog::text(format!(
+                    "This progress was saved in version {} of the story, but this is version {}. \
+                     It may not load correctly.\n\nLoad it anyway?",
+                    save_version, story_version
+                ))
+                .title("Incompatible Save")
+                .dismiss_button("Cancel")
+                .button("Load Anyway", move |s: &mut Cursive| {
+                    s.pop_layer();
+                    restore_progress(s, save.clone(), &save_path);
+                }),
+            );
+        }
+        _ => restore_progress(siv, save, &save_path),
+    }
+}
+
+fn restore_progress(siv: &mut Cursive, save: GameSave, save_path: &Path) {
+    unwrap_or_notify!(
+        siv,
+        siv.with_user_data(|app: &mut AppState| match app.game.as_mut() {
+            Some(game) => game.restore(save),
+            None => Ok(()),
+        })
+        .transpose()
+    );
+    debug!("loaded progress: {}", save_path.display());
+    redraw_content(siv);
+}
+
 pub fn close(siv: &mut Cursive) {
     siv.with_user_data(|app: &mut AppState| app.game = None);
     redraw_content(siv);
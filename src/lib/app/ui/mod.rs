@@ -1,22 +1,27 @@
+use std::collections::BTreeMap;
 use std::iter;
+use std::mem;
+use std::path::Path;
 use std::rc::Rc;
 
-use cursive::align::HAlign;
+use cursive::align::{Align, HAlign};
 use cursive::event::{Event, Key};
 use cursive::menu::MenuTree;
-use cursive::theme::{BaseColor, Effect, Style};
+use cursive::theme::{BaseColor, Color, Effect, Style};
 use cursive::traits::*;
 use cursive::utils::markup::{markdown, StyledString};
-use cursive::view::{scroll::Scroller, Margins, Scrollable};
+use cursive::utils::span::IndexedSpan;
+use cursive::view::{scroll::Scroller, Margins, ScrollStrategy, Scrollable};
 use cursive::views::{
-    Dialog, DummyView, EditView, LinearLayout, ListView, OnEventView, PaddedView, Panel,
+    BoxedView, Dialog, DummyView, EditView, LinearLayout, ListView, OnEventView, PaddedView, Panel,
     ScrollView, SelectView, TextView,
 };
 use cursive::{Cursive, Rect};
-use handlebars::Handlebars;
 
-use crate::app::{logger::LogView, AppState, Game};
-use crate::types::{Prompt, Variable};
+use crate::app::{logger::LogView, render::interpolate, AppState, Game};
+use crate::parser::{self, LockedChoiceDisplay};
+use crate::types::{CreationStep, PageID, Prompt, PromptButton, Variable};
+use crate::utils;
 
 macro_rules! unwrap_or_notify {
     ($siv:expr, $expr:expr) => {{
@@ -43,9 +48,13 @@ mod menu;
 mod constants {
     pub mod labels {
         pub const FILE: &str = "File";
+        pub const JOURNAL: &str = "Journal (^O)";
+        pub const INVENTORY: &str = "Inventory (^E)";
+        pub const HISTORY: &str = "History (^P)";
         pub const HELP: &str = "Help (^H)";
 
         pub const NEXT: &str = "Next (^N)";
+        pub const END: &str = "The End (^N)";
         pub const BACK: &str = "Back (^B)";
         pub const QUIT: &str = "Quit (^Q)";
     }
@@ -57,6 +66,10 @@ mod constants {
                 ("Focus previous element", "<S-Tab>"),
                 ("Focus menubar", "<Esc>"),
                 ("Show help", "^H"),
+                ("Show journal", "^O"),
+                ("Show inventory", "^E"),
+                ("Return to last checkpoint", "^R"),
+                ("Go back to an earlier page", "^P"),
                 ("Quit", "^Q"),
             ];
             pub const NAVIGATION: &[(&str, &str)] = &[
@@ -66,7 +79,7 @@ mod constants {
                 ("Scroll to beginning", "g, <Home>"),
                 ("Scroll to end", "G, <End>"),
                 ("Goto next section", "^N"),
-                ("Goto previous section", "^P"),
+                ("Examine surroundings", "x"),
             ];
         }
     }
@@ -78,12 +91,18 @@ mod constants {
 pub fn run() {
     let mut siv = cursive::default();
 
-    let app_state = AppState::new().unwrap();
+    let mut app_state = AppState::new().unwrap();
+    let log_error = app_state.logger.take_error();
     siv.set_user_data::<AppState>(app_state);
 
     siv.add_global_callback(Key::Esc, |s| s.select_menubar());
     siv.add_global_callback(Event::CtrlChar('q'), on_quit);
     siv.add_global_callback(Event::CtrlChar('h'), on_help);
+    siv.add_global_callback(Event::CtrlChar('o'), on_journal);
+    // ^I is indistinguishable from <Tab> in most terminals, so use ^E for "equipment".
+    siv.add_global_callback(Event::CtrlChar('e'), on_inventory);
+    siv.add_global_callback(Event::CtrlChar('r'), on_restore_checkpoint);
+    siv.add_global_callback(Event::CtrlChar('p'), on_history);
 
     siv.menubar()
         .add_subtree(
@@ -91,11 +110,21 @@ pub fn run() {
             MenuTree::new()
                 .leaf("Open...", menu::open)
                 .leaf("Close", menu::close)
-                .leaf("Save Progress", |_| {}) // TODO
+                .leaf("Save Progress", on_save_progress)
+                .leaf("Load Progress", menu::load_progress)
+                .leaf("Story Info", on_story_info)
+                .leaf("Restart Story", on_restart)
+                .leaf("Return to Checkpoint", on_restore_checkpoint)
+                .leaf("Previous Page", on_go_back)
+                .leaf(constants::labels::HISTORY, on_history)
                 .delimiter()
                 .leaf(constants::labels::QUIT, on_quit),
         )
         .add_delimiter()
+        .add_leaf(constants::labels::JOURNAL, on_journal)
+        .add_delimiter()
+        .add_leaf(constants::labels::INVENTORY, on_inventory)
+        .add_delimiter()
         .add_leaf(constants::labels::HELP, on_help)
         .add_delimiter()
         .add_leaf(constants::labels::QUIT, on_quit);
@@ -109,6 +138,23 @@ pub fn run() {
 
     siv.add_layer(DummyView);
     redraw_content(&mut siv);
+    show_load_warnings(&mut siv);
+
+    if let Some(err) = log_error {
+        siv.add_layer(
+            Dialog::around(
+                TextView::new(format!(
+                    "Logs will not be saved, because the log file could not be created:\n\n{}",
+                    err.to_string_verbose()
+                ))
+                .align(Align::center()),
+            )
+            .h_align(HAlign::Center)
+            .title("Warning")
+            .dismiss_button("OK")
+            .max_width(80),
+        );
+    }
 
     siv.run();
 }
@@ -125,29 +171,45 @@ lazy_static! {
 fn redraw_content(siv: &mut Cursive) {
     // Content view - container for main content.
     fn content_view(s: &mut Cursive) -> impl View {
-        let (title, content) = s
+        let (title, content, is_ending) = s
             .with_user_data(|app: &mut AppState| {
                 app.game.as_ref().map(|game| {
                     let page = game.current_page.borrow();
-                    let content = interpolate(&page.content, &game);
-                    (page.title.clone(), content)
+                    let mut content = interpolate(&page.content, &game);
+                    // Color the content by the first of the page's tags that has a color.
+                    if let Some(color) = page.tags.iter().find_map(|tag| app.tag_colors.get(tag)) {
+                        content = colorize(content, *color);
+                    }
+                    (page.title.clone(), content, game.is_ending())
                 })
             })
             .flatten()
-            .unwrap_or_else(|| (None, StyledString::plain(*FILLER_TEXT)));
+            .unwrap_or_else(|| (None, StyledString::plain(*FILLER_TEXT), false));
 
         OnEventView::new({
             let mut dialog =
                 Dialog::around(TextView::new(content).scrollable().with_name("content"))
                     .h_align(HAlign::Center)
-                    .padding_lrtb(0, 0, 0, 1)
-                    .button(constants::labels::NEXT, on_continue);
-            if let Some(title) = title {
-                dialog = dialog.title(format!("\"{}\"", title));
+                    .padding_lrtb(0, 0, 0, 1);
+            let title = title.map(|title| format!("\"{}\"", title));
+            if is_ending {
+                // Mark endings, so the player knows there's nowhere left to go.
+                dialog = dialog
+                    .button(constants::labels::END, on_continue)
+                    .title(match title {
+                        Some(title) => format!("{} ~ The End", title),
+                        None => "The End".to_owned(),
+                    });
+            } else {
+                dialog = dialog.button(constants::labels::NEXT, on_continue);
+                if let Some(title) = title {
+                    dialog = dialog.title(title);
+                }
             }
             dialog.full_width()
         })
         .on_event(Event::CtrlChar('n'), on_continue)
+        .on_event('x', on_examine)
         .on_event('k', mk_scroll("content", |_| -1))
         .on_event('j', mk_scroll("content", |_| 1))
         .on_event(
@@ -226,8 +288,7 @@ fn redraw_content(siv: &mut Cursive) {
         let vars_view = s
             .with_user_data(|app: &mut AppState| {
                 app.game.as_ref().map(|game: &Game| {
-                    let mut vars: Vec<_> = game.variables.iter().collect();
-                    vars.sort_by(|(k0, _), (k1, _)| k0.cmp(k1));
+                    let vars: BTreeMap<_, _> = game.variables.iter().collect();
 
                     let mut view = ListView::new();
                     for (name, value) in vars {
@@ -277,8 +338,13 @@ fn redraw_content(siv: &mut Cursive) {
 
     siv.pop_layer();
 
-    // If there are Prompts in the queue, display the next dialog.
-    if let Some(dialog) = pop_prompt_dialog(siv) {
+    // If there are Prompts in the queue, display the next dialog, or a form for several at once.
+    if let Some(form) = pop_prompt_form(siv) {
+        siv.add_layer(form);
+    } else if let Some(dialog) = pop_prompt_dialog(siv) {
+        siv.add_layer(dialog);
+    // If character creation is in progress, display the current step.
+    } else if let Some(dialog) = creation_step_dialog(siv) {
         siv.add_layer(dialog);
     // Otherwise, display the main layout.
     } else {
@@ -295,85 +361,397 @@ fn pop_prompt_dialog(siv: &mut Cursive) -> Option<impl View> {
     siv.with_user_data(|app: &mut AppState| {
         let game = app.game.as_mut().unwrap();
 
-        game.pop_prompt().map(|Prompt { text, variable }| {
-            let content = interpolate(&text, &game);
+        game.pop_prompt().map(|prompt| {
+            let content = interpolate(&prompt.text, &game);
+            let (position, count) = game.prompt_progress();
 
-            match variable {
+            match prompt.variable {
                 // Prompt has a `variable`, so create an input dialog.
-                Some(var_name) => {
-                    let var_name_clone = var_name.clone();
+                Some(ref var_name) => {
+                    // Start with the current value, so the player can edit it rather than retype.
+                    let current = variable_input_content(game, var_name);
+                    let prompt = Rc::new(prompt);
+                    let prompt_clone = Rc::clone(&prompt);
+                    let input = prompt_input_view(&prompt, current, "prompt-input", {
+                        let prompt = Rc::clone(&prompt);
+                        move |s: &mut Cursive, input: &str| on_prompt_submit(s, input, &prompt)
+                    });
                     Dialog::around(
                         LinearLayout::vertical()
                             .child(PaddedView::new(
                                 Margins::lrtb(1, 1, 1, 1),
                                 TextView::new(content),
                             ))
-                            .child(Panel::new(
-                                EditView::new()
-                                    .on_submit(move |s: &mut Cursive, input: &str| {
-                                        on_prompt_submit(s, input, &var_name);
-                                    })
-                                    .with_name("prompt-input"),
-                            )),
+                            .child(Panel::new(input)),
                     )
                     .button("Ok", move |s: &mut Cursive| {
-                        let input = s
-                            .call_on_name("prompt-input", |view: &mut EditView| view.get_content())
-                            .unwrap();
-                        on_prompt_submit(s, input.as_ref(), &var_name_clone);
+                        if let Some(input) = prompt_input(s, "prompt-input") {
+                            on_prompt_submit(s, &input, &prompt_clone);
+                        }
                     })
-                    .title("PROMPT")
+                    .title(prompt_title("PROMPT", position, count))
                 }
                 // Prompt has no `variable`, so create a simple info dialog.
-                None => Dialog::text(content)
-                    .button("Ok", |s: &mut Cursive| {
-                        s.pop_layer();
-                        redraw_content(s);
-                    })
-                    .title("INFO"),
+                None => {
+                    let Prompt { buttons, .. } = prompt;
+                    // Leave out buttons for links whose `requires` condition isn't met, like
+                    // in the list of choices.
+                    let buttons: Vec<_> = {
+                        let page = game.current_page.borrow();
+                        let active: Vec<_> = game
+                            .filter_active_links(&page.links)
+                            .map(|(link_idx, _)| link_idx)
+                            .collect();
+                        buttons
+                            .into_iter()
+                            .filter(|button| {
+                                button
+                                    .link
+                                    .is_none_or(|link_idx| active.contains(&link_idx))
+                            })
+                            .collect()
+                    };
+                    let mut dialog =
+                        Dialog::text(content).title(prompt_title("INFO", position, count));
+                    if buttons.is_empty() {
+                        dialog.add_button("Ok", on_prompt_dismiss);
+                    }
+                    for PromptButton { label, link } in buttons {
+                        match link {
+                            Some(link_idx) => dialog.add_button(label, move |s: &mut Cursive| {
+                                s.pop_layer();
+                                on_follow_link(s, link_idx);
+                            }),
+                            None => dialog.add_button(label, on_prompt_dismiss),
+                        }
+                    }
+                    dialog
+                }
             }
         })
     })
     .unwrap()
 }
 
-fn on_prompt_submit(siv: &mut Cursive, input: &str, var_name: &String) {
-    let value: Variable = match input.parse() {
-        Ok(value) => value,
-        Err(err) => {
-            siv.add_layer(Dialog::info(format!("Invalid value: {}.", err)));
-            return;
+/// The title for a prompt dialog, with the prompt's `position` among the `count` prompts in its
+/// series if there's more than one, e.g. "PROMPT (2/3)".
+fn prompt_title(title: &str, position: usize, count: usize) -> String {
+    if count > 1 {
+        format!("{} ({}/{})", title, position, count)
+    } else {
+        title.to_owned()
+    }
+}
+
+/// Create a form for the variable prompts at the front of the queue, if prompts are batched and
+/// there are at least two of them.
+fn pop_prompt_form(siv: &mut Cursive) -> Option<Dialog> {
+    let fields: Vec<(StyledString, String, Prompt)> = siv
+        .with_user_data(|app: &mut AppState| {
+            if !app.batch_prompts {
+                return Vec::new();
+            }
+            let game = app.game.as_mut().unwrap();
+            let mut prompts = game.pop_variable_prompts();
+            // A single prompt is shown in its own dialog as usual.
+            if prompts.len() < 2 {
+                for prompt in prompts.drain(..).rev() {
+                    game.unpop_prompt(prompt);
+                }
+            }
+            prompts
+                .into_iter()
+                .map(|prompt| {
+                    let current = variable_input_content(game, prompt.variable.as_ref().unwrap());
+                    (interpolate(&prompt.text, game), current, prompt)
+                })
+                .collect()
+        })
+        .unwrap();
+    if fields.is_empty() {
+        return None;
+    }
+
+    let prompts: Rc<Vec<Prompt>> =
+        Rc::new(fields.iter().map(|(.., prompt)| prompt.clone()).collect());
+    let mut layout = LinearLayout::vertical();
+    for (i, (content, current, prompt)) in fields.into_iter().enumerate() {
+        let prompts = Rc::clone(&prompts);
+        layout.add_child(PaddedView::new(
+            Margins::lrtb(1, 1, 1, 0),
+            TextView::new(content),
+        ));
+        layout.add_child(Panel::new(prompt_input_view(
+            &prompt,
+            current,
+            &prompt_form_field(i),
+            move |s: &mut Cursive, _: &str| on_prompt_form_submit(s, &prompts),
+        )));
+    }
+    Some(
+        Dialog::around(layout.scrollable())
+            .button("Ok", move |s: &mut Cursive| {
+                on_prompt_form_submit(s, &prompts)
+            })
+            .title("PROMPT"),
+    )
+}
+
+/// The name of the input view for the `i`th field of a prompt form.
+fn prompt_form_field(i: usize) -> String {
+    format!("prompt-input-{}", i)
+}
+
+/// Validate every field of a prompt form, then assign them all to the variables of `prompts`. If a
+/// field is invalid, nothing is assigned and that field is focused.
+fn on_prompt_form_submit(siv: &mut Cursive, prompts: &[Prompt]) {
+    let mut values = Vec::with_capacity(prompts.len());
+    for (i, prompt) in prompts.iter().enumerate() {
+        let field = prompt_form_field(i);
+        let input = match prompt_input(siv, &field) {
+            Some(input) => input,
+            None => return,
+        };
+        match parse_variable_input(siv, &input, prompt) {
+            Ok(value) => values.push(value),
+            Err(msg) => {
+                let _ = siv.focus_name(&field);
+                siv.add_layer(Dialog::info(msg));
+                return;
+            }
         }
-    };
+    }
 
-    let maybe_err = if input.is_empty() {
-        Err("Input must not be empty.".to_string())
-    } else {
-        siv.with_user_data(|app: &mut AppState| {
-            let variable = app
-                .game
-                .as_mut()
-                .unwrap()
-                .variables
-                .get_mut(var_name)
-                .unwrap();
-            if variable.type_() != value.type_() {
-                return Err(format!("Please enter a {}.", variable.type_()));
+    siv.with_user_data(|app: &mut AppState| {
+        let game = app.game.as_mut().unwrap();
+        for (prompt, value) in prompts.iter().zip(values) {
+            let var_name = prompt.variable.as_ref().unwrap();
+            *game.variables.get_mut(var_name).unwrap() = value;
+        }
+    });
+    siv.pop_layer();
+    redraw_content(siv);
+}
+
+fn on_prompt_dismiss(siv: &mut Cursive) {
+    siv.pop_layer();
+    redraw_content(siv);
+}
+
+fn on_prompt_submit(siv: &mut Cursive, input: &str, prompt: &Prompt) {
+    if set_variable_from_input(siv, input, prompt) {
+        siv.pop_layer();
+        redraw_content(siv);
+    }
+}
+
+/// Create the view for answering `prompt`, which has a variable: a list of the prompt's `choices`
+/// if it has any, or else a text field starting with `content`. The view is named `name`, and
+/// `on_submit` is called with the input when the player presses Enter.
+fn prompt_input_view<F>(prompt: &Prompt, content: String, name: &str, on_submit: F) -> BoxedView
+where
+    F: Fn(&mut Cursive, &str) + 'static,
+{
+    if prompt.choices.is_empty() {
+        return BoxedView::boxed(
+            EditView::new()
+                .content(content)
+                .on_submit(on_submit)
+                .with_name(name),
+        );
+    }
+    let mut select = SelectView::<String>::new()
+        .with_all_str(prompt.choices.iter())
+        .on_submit(move |s: &mut Cursive, choice: &String| on_submit(s, choice));
+    if let Some(i) = prompt.choices.iter().position(|choice| *choice == content) {
+        select.set_selection(i);
+    }
+    BoxedView::boxed(select.with_name(name).scrollable())
+}
+
+/// Returns the current value of the variable named `var_name` as the player would enter it, to
+/// start a prompt's input with. An unchanged value is accepted like any other input.
+fn variable_input_content(game: &Game, var_name: &str) -> String {
+    match game.variables.get(var_name) {
+        Some(Variable::Str(s)) => s.clone(),
+        Some(value) => value.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Get the input from the view named `name` that was created by [`prompt_input_view`].
+fn prompt_input(siv: &mut Cursive, name: &str) -> Option<String> {
+    siv.call_on_name(name, |view: &mut EditView| view.get_content().to_string())
+        .or_else(|| {
+            siv.call_on_name(name, |view: &mut SelectView<String>| {
+                view.selection().map(|choice| (*choice).clone())
+            })
+            .flatten()
+        })
+}
+
+/// Parse `input` and assign it to the variable of `prompt`.
+///
+/// If the input is invalid, an error dialog is shown and `false` is returned.
+fn set_variable_from_input(siv: &mut Cursive, input: &str, prompt: &Prompt) -> bool {
+    match parse_variable_input(siv, input, prompt) {
+        Ok(value) => {
+            let var_name = prompt.variable.as_ref().unwrap();
+            siv.with_user_data(|app: &mut AppState| {
+                *app.game
+                    .as_mut()
+                    .unwrap()
+                    .variables
+                    .get_mut(var_name)
+                    .unwrap() = value;
+            });
+            true
+        }
+        Err(msg) => {
+            siv.add_layer(Dialog::info(msg));
+            false
+        }
+    }
+}
+
+/// Parse `input` as a value for the variable of `prompt`, returning a message for the player if
+/// the input is invalid or not allowed by the prompt.
+///
+/// The input is parsed according to the variable's type, so e.g. `true` is accepted as-is for a
+/// string variable rather than being read as a boolean.
+fn parse_variable_input(
+    siv: &mut Cursive,
+    input: &str,
+    prompt: &Prompt,
+) -> Result<Variable, String> {
+    if input.is_empty() {
+        return Err("Input must not be empty.".to_string());
+    }
+
+    let var_name = prompt.variable.as_ref().unwrap();
+    let var_type = siv
+        .with_user_data(|app: &mut AppState| app.game.as_ref().unwrap().variables[var_name].type_())
+        .unwrap();
+    let value = var_type
+        .parse(input)
+        .ok_or_else(|| format!("Please enter a {}.", var_type))?;
+    prompt.check_value(&value)?;
+    Ok(value)
+}
+
+/// Create a dialog for the current character creation step, if character creation is in
+/// progress.
+fn creation_step_dialog(siv: &mut Cursive) -> Option<Dialog> {
+    siv.with_user_data(|app: &mut AppState| {
+        let game = app.game.as_ref()?;
+        let cc = game.character_creation.as_ref()?;
+        let (idx, step) = game.creation_step()?;
+
+        let view = match step {
+            CreationStep::Prompt(prompt) => {
+                let mut layout = LinearLayout::vertical().child(PaddedView::new(
+                    Margins::lrtb(1, 1, 1, 1),
+                    TextView::new(interpolate(&prompt.text, game)),
+                ));
+                if let Some(var_name) = &prompt.variable {
+                    layout.add_child(Panel::new(prompt_input_view(
+                        prompt,
+                        variable_input_content(game, var_name),
+                        "creation-input",
+                        |s: &mut Cursive, _: &str| on_creation_next(s),
+                    )));
+                }
+                layout
             }
+            CreationStep::ChooseItem { text, items } => {
+                let mut select = SelectView::<String>::new()
+                    .on_submit(|s: &mut Cursive, _: &String| on_creation_next(s));
+                for name in items {
+                    let label = match game.item_defs[name].description.as_ref() {
+                        Some(description) => format!("{} - {}", name, description),
+                        None => name.clone(),
+                    };
+                    select.add_item(label, name.clone());
+                }
+                if let Some(chosen) = cc.chosen_item() {
+                    if let Some(i) = items.iter().position(|name| name == chosen) {
+                        select.set_selection(i);
+                    }
+                }
+                LinearLayout::vertical()
+                    .child(PaddedView::new(
+                        Margins::lrtb(1, 1, 1, 1),
+                        TextView::new(interpolate(text, game)),
+                    ))
+                    .child(Panel::new(select.with_name("creation-select").scrollable()))
+            }
+        };
 
-            *variable = value;
-            Ok(())
+        let mut dialog =
+            Dialog::around(view).title(format!("CHARACTER ({}/{})", idx + 1, cc.steps.len()));
+        if idx > 0 {
+            dialog.add_button(constants::labels::BACK, |s: &mut Cursive| {
+                s.with_user_data(|app: &mut AppState| {
+                    if let Some(game) = app.game.as_mut() {
+                        game.prev_creation_step();
+                    }
+                });
+                redraw_content(s);
+            });
+        }
+        dialog.add_button(constants::labels::NEXT, on_creation_next);
+        Some(dialog)
+    })
+    .flatten()
+}
+
+/// Complete the current character creation step and advance to the next.
+fn on_creation_next(siv: &mut Cursive) {
+    let step = siv
+        .with_user_data(|app: &mut AppState| {
+            app.game
+                .as_ref()
+                .and_then(|game| game.creation_step().map(|(_, step)| step.clone()))
         })
-        .unwrap()
-    };
+        .flatten();
+
+    match step {
+        Some(CreationStep::Prompt(prompt)) if prompt.variable.is_some() => {
+            let input = match prompt_input(siv, "creation-input") {
+                Some(input) => input,
+                None => return,
+            };
+            if !set_variable_from_input(siv, &input, &prompt) {
+                return;
+            }
+        }
+        Some(CreationStep::Prompt(_)) => {}
+        Some(CreationStep::ChooseItem { .. }) => {
+            let selection = siv
+                .call_on_name("creation-select", |view: &mut SelectView<String>| {
+                    view.selection()
+                })
+                .flatten();
+            match selection {
+                Some(name) => {
+                    siv.with_user_data(|app: &mut AppState| {
+                        if let Some(game) = app.game.as_mut() {
+                            game.choose_creation_item(&name);
+                        }
+                    });
+                }
+                None => return,
+            }
+        }
+        None => return,
+    }
 
-    match maybe_err {
-        Ok(_) => {
-            siv.pop_layer();
-            redraw_content(siv);
+    siv.with_user_data(|app: &mut AppState| {
+        if let Some(game) = app.game.as_mut() {
+            game.next_creation_step();
         }
-        Err(msg) => siv.add_layer(Dialog::info(msg)),
-    };
+    });
+    redraw_content(siv);
 }
 
 fn mk_scroll<F>(view_name: &'static str, f: F) -> impl Fn(&mut Cursive)
@@ -394,22 +772,77 @@ where
     }
 }
 
-fn interpolate(content: &str, game: &Game) -> StyledString {
-    let reg = Handlebars::new();
-    let content = match reg.render_template(content, &game.variables) {
-        Ok(content) => content,
-        Err(err) => {
-            error!("error rendering template: {}", err);
-            content.to_owned()
+/// Append `content` to the end of the current page's content.
+///
+/// If autoscroll is enabled and the content view is scrolled to the bottom, it stays at the bottom
+/// so the new content is visible. If the player has scrolled up, the scroll position is kept.
+pub fn append_content(siv: &mut Cursive, content: StyledString) {
+    let autoscroll = siv
+        .with_user_data(|app: &mut AppState| app.autoscroll)
+        .unwrap_or(false);
+    siv.call_on_name("content", |view: &mut ScrollView<TextView>| {
+        let follow = autoscroll && view.is_at_bottom();
+        view.get_inner_mut().append(content);
+        if follow {
+            // Applied on the next layout, once the new content has been measured.
+            view.set_scroll_strategy(ScrollStrategy::StickToBottom);
         }
-    };
-    markdown::parse(content)
+    });
+}
+
+/// Apply `color` to all of `s`, preserving any other styles.
+fn colorize(s: StyledString, color: Color) -> StyledString {
+    restyle(s, Style::from(color))
+}
+
+/// Apply `style` to all of `s`, preserving any other styles.
+fn restyle(s: StyledString, style: Style) -> StyledString {
+    let spans = s
+        .spans_raw()
+        .iter()
+        .map(|span| IndexedSpan {
+            attr: style.combine(span.attr),
+            ..span.clone()
+        })
+        .collect();
+    StyledString::with_spans(s.source(), spans)
 }
 
 /*
  * Event handling.
  */
 
+/// Follow the link on the current page with the given `link_idx`, showing the ending if the link
+/// ends the game.
+fn on_follow_link(siv: &mut Cursive, link_idx: usize) {
+    let result = siv
+        .with_user_data(|app: &mut AppState| {
+            let game = app.game.as_mut().unwrap();
+            game.follow_link(link_idx)
+                .map(|msg| msg.map(|msg| (game.metadata.title.clone(), interpolate(&msg, &game))))
+        })
+        .unwrap();
+    let game_over = unwrap_or_notify!(siv, result);
+
+    if let Some((title, msg)) = game_over {
+        siv.add_layer(game_over_dialog(title, msg));
+    } else {
+        redraw_content(siv);
+    }
+}
+
+/// The dialog shown when the game ends, with the story's `title` and an ending `msg`.
+fn game_over_dialog<S: Into<StyledString>>(title: String, msg: S) -> impl View {
+    OnEventView::new(
+        Dialog::around(TextView::new(msg).h_align(HAlign::Center))
+            .title(title)
+            .h_align(HAlign::Center)
+            .button("OK", |s| s.quit())
+            .button("Cancel", on_menu_back),
+    )
+    .on_event(Event::CtrlChar('b'), on_menu_back)
+}
+
 // TODO: factor out the view creation into a `link_select_view` function.
 fn on_continue(siv: &mut Cursive) {
     let current_page = match siv
@@ -424,41 +857,65 @@ fn on_continue(siv: &mut Cursive) {
     let prompt = &current_page.borrow().prompt;
     let links = &current_page.borrow().links;
 
-    let mut select = SelectView::<usize>::new().on_submit(|s: &mut Cursive, link_idx: &usize| {
-        s.pop_layer();
-
-        let game_over = s
-            .with_user_data(|app: &mut AppState| {
-                let game = app.game.as_mut().unwrap();
-                game.follow_link(*link_idx)
-                    .map(|msg| interpolate(&msg, &game))
-            })
-            .flatten();
+    // Locked links are listed without a link index, so choosing them does nothing.
+    let mut select = SelectView::<Option<usize>>::new().on_submit(
+        |s: &mut Cursive, link_idx: &Option<usize>| {
+            if let Some(link_idx) = *link_idx {
+                s.pop_layer();
+                on_follow_link(s, link_idx);
+            }
+        },
+    );
 
-        if let Some(msg) = game_over {
-            s.add_layer(
-                OnEventView::new(
-                    Dialog::around(TextView::new(msg).h_align(HAlign::Center))
-                        .h_align(HAlign::Center)
-                        .button("OK", |s| s.quit())
-                        .button("Cancel", on_menu_back),
-                )
-                .on_event(Event::CtrlChar('b'), on_menu_back),
-            );
-        } else {
-            redraw_content(s);
-        }
-    });
+    let any_active = siv
+        .with_user_data(|app: &mut AppState| {
+            let show_link_dest = app.show_link_dest;
+            let locked_choice_display = app.locked_choice_display;
+            let game = app.game.as_mut().unwrap();
+            let active: Vec<_> = game
+                .filter_active_links(links)
+                .map(|(idx, _)| idx)
+                .collect();
+            for (idx, link) in links.iter().enumerate() {
+                let locked = !active.contains(&idx);
+                if locked && locked_choice_display == LockedChoiceDisplay::Hide {
+                    continue;
+                }
+                let mut sstr = StyledString::from("> ");
+                if let Some(icon) = &link.icon {
+                    sstr.append_plain(format!("{} ", icon));
+                }
+                let mut text = interpolate(&link.text, &game);
+                if let Some(style) = link.style.as_deref().and_then(utils::parse_style) {
+                    text = restyle(text, style);
+                }
+                sstr.append(text);
+                if show_link_dest {
+                    sstr.append_styled(format!("  ↪ ({}) ", link.dest), Effect::Italic);
+                }
+                if !locked {
+                    select.add_item(sstr, Some(idx));
+                    continue;
+                }
+                if locked_choice_display == LockedChoiceDisplay::DisableWithReason {
+                    if let Some(cond) = &link.requires {
+                        sstr.append_styled(format!("  (requires {})", cond), Effect::Italic);
+                    }
+                }
+                select.add_item(restyle(sstr, Style::from(BaseColor::Black.light())), None);
+            }
+            !active.is_empty()
+        })
+        .unwrap();
 
-    siv.with_user_data(|app: &mut AppState| {
-        let game = app.game.as_mut().unwrap();
-        for (idx, link) in game.filter_active_links(links) {
-            let mut sstr = StyledString::from("> ");
-            sstr.append(interpolate(&link.text, &game));
-            sstr.append_styled(format!("  ↪ ({}) ", link.dest), Effect::Italic);
-            select.add_item(sstr, idx);
-        }
-    });
+    // If there's nowhere left to go, end the game instead of showing a list with no choices.
+    if !any_active {
+        let title = siv
+            .with_user_data(|app: &mut AppState| app.game.as_ref().unwrap().metadata.title.clone())
+            .unwrap();
+        siv.add_layer(game_over_dialog(title, "The End"));
+        return;
+    }
 
     siv.add_layer(
         OnEventView::new(
@@ -515,6 +972,512 @@ fn on_help(siv: &mut Cursive) {
     );
 }
 
+fn on_examine(siv: &mut Cursive) {
+    let examine = siv
+        .with_user_data(|app: &mut AppState| {
+            app.game.as_ref().and_then(|game| {
+                let page = game.current_page.borrow();
+                page.examine
+                    .as_ref()
+                    .map(|text| (page.title.clone(), interpolate(text, game)))
+            })
+        })
+        .flatten();
+
+    if let Some((title, text)) = examine {
+        let mut dialog = Dialog::around(TextView::new(text).scrollable())
+            .h_align(HAlign::Center)
+            .button("Done", on_menu_back);
+        if let Some(title) = title {
+            dialog = dialog.title(format!("\"{}\"", title));
+        }
+        siv.add_layer(
+            OnEventView::new(
+                dialog.max_width(((siv.screen_size().x as f32 * 0.75).round() as usize).min(80)),
+            )
+            .on_event('x', on_menu_back)
+            .on_event(Event::CtrlChar('b'), on_menu_back),
+        );
+    }
+}
+
+fn on_journal(siv: &mut Cursive) {
+    let journal = match siv
+        .with_user_data(|app: &mut AppState| app.game.as_ref().map(|game| game.journal.clone()))
+        .flatten()
+    {
+        Some(journal) => journal,
+        None => return,
+    };
+
+    let mut layout = LinearLayout::vertical();
+    if journal.is_empty() {
+        layout.add_child(TextView::new("Nothing here yet.").h_align(HAlign::Center));
+    }
+    for (i, note) in journal.into_iter().enumerate() {
+        if i > 0 {
+            layout.add_child(DummyView);
+        }
+        let mut s = StyledString::styled(format!("{}. ", i + 1), Effect::Bold);
+        s.append(markdown::parse(note));
+        layout.add_child(TextView::new(s));
+    }
+
+    siv.add_layer(
+        OnEventView::new(
+            Dialog::around(Panel::new(layout.scrollable()).title("Journal"))
+                .h_align(HAlign::Center)
+                .button("Done", on_menu_back)
+                .max_width(((siv.screen_size().x as f32 * 0.75).round() as usize).min(60)),
+        )
+        .on_event(Event::CtrlChar('o'), on_menu_back)
+        .on_event(Event::CtrlChar('b'), on_menu_back),
+    );
+}
+
+/// Show the items the player holds, each of which can be selected to show its description.
+fn on_inventory(siv: &mut Cursive) {
+    let items = match siv
+        .with_user_data(|app: &mut AppState| {
+            app.game.as_ref().map(|game| {
+                let mut items: Vec<_> = game
+                    .items
+                    .iter()
+                    .filter_map(|(name, stack)| {
+                        let item = stack.back()?;
+                        Some((
+                            name.clone(),
+                            item.description().map(str::to_owned),
+                            stack.len(),
+                            item.fmt_uses(),
+                        ))
+                    })
+                    .collect();
+                items.sort();
+                items
+            })
+        })
+        .flatten()
+    {
+        Some(items) => items,
+        None => return,
+    };
+
+    let mut layout = LinearLayout::vertical();
+    if items.is_empty() {
+        layout.add_child(TextView::new("You are carrying nothing.").h_align(HAlign::Center));
+    } else {
+        let mut select = SelectView::<(String, Option<String>)>::new().on_submit(
+            |s: &mut Cursive, (name, description): &(String, Option<String>)| {
+                // Depleted items are removed from the inventory, so every item listed can be used.
+                let item_name = name.clone();
+                s.add_layer(
+                    OnEventView::new(
+                        Dialog::text(description.as_deref().unwrap_or("Nothing special."))
+                            .title(name.as_str())
+                            .h_align(HAlign::Center)
+                            .button("Use", move |s| on_use_item(s, &item_name))
+                            .button("Done", on_menu_back)
+                            .max_width(60),
+                    )
+                    .on_event(Event::CtrlChar('b'), on_menu_back),
+                );
+            },
+        );
+        for (name, description, count, uses) in items {
+            let mut label = StyledString::styled(format!("> {}", name), Effect::Bold);
+            label.append_plain(format!(" x{}", count));
+            label.append_styled(format!("  ({} used)", uses), Effect::Italic);
+            if let Some(description) = &description {
+                label.append_plain(format!(" - {}", description));
+            }
+            select.add_item(label, (name, description));
+        }
+        layout.add_child(select);
+    }
+
+    siv.add_layer(
+        OnEventView::new(
+            Dialog::around(Panel::new(layout.scrollable()).title("Inventory"))
+                .h_align(HAlign::Center)
+                .button("Done", on_menu_back)
+                .max_width(((siv.screen_size().x as f32 * 0.75).round() as usize).min(60)),
+        )
+        .on_event(Event::CtrlChar('e'), on_menu_back)
+        .on_event(Event::CtrlChar('b'), on_menu_back),
+    );
+}
+
+/// Use the item named `name` from the inventory, showing the ending if its effect ends the game.
+fn on_use_item(siv: &mut Cursive, name: &str) {
+    // Close the item's description and the inventory.
+    siv.pop_layer();
+    siv.pop_layer();
+
+    let result = siv
+        .with_user_data(|app: &mut AppState| {
+            let game = app.game.as_mut().unwrap();
+            game.use_item(name)
+                .map(|msg| msg.map(|msg| (game.metadata.title.clone(), interpolate(&msg, game))))
+        })
+        .unwrap();
+    let game_over = unwrap_or_notify!(siv, result);
+
+    if let Some((title, msg)) = game_over {
+        siv.add_layer(game_over_dialog(title, msg));
+    } else {
+        redraw_content(siv);
+        // Reopen the inventory to show what's left.
+        on_inventory(siv);
+    }
+}
+
+/// Show the story's metadata and an overview of its possible endings.
+fn on_story_info(siv: &mut Cursive) {
+    let info = siv
+        .with_user_data(|app: &mut AppState| {
+            app.game.as_ref().map(|game| {
+                let pages = parser::reachable_pages(&game.starting_page);
+                // Count every page if the story was parsed up front, including unreachable ones.
+                let stats = if game.pages.is_empty() {
+                    parser::story_stats(&pages)
+                } else {
+                    parser::story_stats(&game.pages)
+                };
+                (
+                    game.metadata.clone(),
+                    parser::endings(&pages, &game.item_defs),
+                    stats,
+                    game.lazy_pages.is_some(),
+                )
+            })
+        })
+        .flatten();
+    let (metadata, endings, stats, lazy) = match info {
+        Some(info) => info,
+        None => return,
+    };
+
+    let mut about = StyledString::styled(metadata.title, Effect::Bold);
+    if let Some(author) = metadata.author {
+        about.append_plain(format!("\nby {}", author));
+    }
+    if let Some(version) = metadata.version {
+        about.append_plain(format!("\nv{}", version));
+    }
+
+    let mut summary = format!(
+        "{} words, {} pages, {} choices\nabout {} min to read",
+        stats.words,
+        stats.pages,
+        stats.choices,
+        stats.reading_minutes(),
+    );
+    if lazy {
+        summary.push_str("\n(counting only pages visited so far)");
+    }
+
+    let mut layout = LinearLayout::vertical();
+    if endings.is_empty() {
+        layout.add_child(TextView::new("No endings found.").h_align(HAlign::Center));
+    }
+    for (page_id, msg) in endings {
+        let mut s = StyledString::plain(format!("> {} ", msg.trim()));
+        s.append_styled(format!("({})", page_id), Effect::Italic);
+        layout.add_child(TextView::new(s));
+    }
+
+    siv.add_layer(
+        OnEventView::new(
+            Dialog::around(
+                LinearLayout::vertical()
+                    .child(TextView::new(about).h_align(HAlign::Center))
+                    .child(DummyView)
+                    .child(TextView::new(summary).h_align(HAlign::Center))
+                    .child(DummyView)
+                    .child(Panel::new(layout.scrollable()).title("Endings")),
+            )
+            .title("Story Info")
+            .h_align(HAlign::Center)
+            .button("Done", on_menu_back)
+            .max_width(((siv.screen_size().x as f32 * 0.75).round() as usize).min(60)),
+        )
+        .on_event(Event::CtrlChar('b'), on_menu_back),
+    );
+}
+
+/// Show the warnings about the story that was just loaded, if any, each of which can be selected to
+/// jump to the page it's about.
+fn show_load_warnings(siv: &mut Cursive) {
+    let warnings = siv
+        .with_user_data(|app: &mut AppState| mem::take(&mut app.load_warnings))
+        .unwrap_or_default();
+    if warnings.is_empty() {
+        return;
+    }
+
+    let mut select = SelectView::<Option<PageID>>::new().on_submit(|s, page_id| {
+        if let Some(page_id) = page_id {
+            s.pop_layer();
+            s.with_user_data(|app: &mut AppState| {
+                if let Some(game) = app.game.as_mut() {
+                    game.jump_to_page(page_id);
+                }
+            });
+            redraw_content(s);
+        }
+    });
+    for warning in warnings {
+        let label = match warning.page_id {
+            Some(_) => format!("> {} ↪", warning),
+            None => format!("> {}", warning),
+        };
+        select.add_item(label, warning.page_id);
+    }
+
+    siv.add_layer(
+        OnEventView::new(
+            Dialog::around(
+                LinearLayout::vertical()
+                    .child(
+                        TextView::new(
+                            "The story has some likely mistakes. Select one to go to its page.",
+                        )
+                        .h_align(HAlign::Center),
+                    )
+                    .child(DummyView)
+                    .child(Panel::new(select.scrollable())),
+            )
+            .title("Warnings")
+            .h_align(HAlign::Center)
+            .button("Play", on_menu_back)
+            .max_width(((siv.screen_size().x as f32 * 0.75).round() as usize).min(80)),
+        )
+        .on_event(Event::CtrlChar('b'), on_menu_back),
+    );
+}
+
+/// Save the player's progress to the story's save file, asking first if it would replace a save.
+fn on_save_progress(siv: &mut Cursive) {
+    let save_path = siv
+        .with_user_data(|app: &mut AppState| {
+            app.game.as_ref()?;
+            app.save_path.clone()
+        })
+        .flatten();
+    let save_path = match save_path {
+        Some(save_path) => save_path,
+        None => {
+            siv.add_layer(Dialog::info("There is no story in progress to save."));
+            return;
+        }
+    };
+
+    fn save(siv: &mut Cursive, save_path: &Path) {
+        let save = siv
+            .with_user_data(|app: &mut AppState| app.game.as_ref().map(Game::to_save))
+            .flatten();
+        if let Some(save) = save {
+            unwrap_or_notify!(siv, save.write(save_path));
+            siv.add_layer(Dialog::info(format!(
+                "Progress saved to '{}'.",
+                save_path.display()
+            )));
+        }
+    }
+
+    if save_path.exists() {
+        siv.add_layer(
+            OnEventView::new(
+                Dialog::text(format!(
+                    "Replace the saved progress in '{}'?",
+                    save_path.display()
+                ))
+                .h_align(HAlign::Center)
+                .button("OK", move |s| {
+                    s.pop_layer();
+                    save(s, &save_path);
+                })
+                .button("Cancel", on_menu_back),
+            )
+            .on_event(Event::CtrlChar('b'), on_menu_back),
+        );
+    } else {
+        save(siv, &save_path);
+    }
+}
+
+fn on_restore_checkpoint(siv: &mut Cursive) {
+    let has_checkpoint = siv
+        .with_user_data(|app: &mut AppState| {
+            app.game.as_ref().map(|game| game.last_checkpoint.is_some())
+        })
+        .flatten();
+
+    match has_checkpoint {
+        None => {}
+        Some(false) => siv.add_layer(Dialog::info("No checkpoint has been reached yet.")),
+        Some(true) => siv.add_layer(
+            OnEventView::new(
+                Dialog::text(
+                    "Return to the last checkpoint? Any progress since then will be lost.",
+                )
+                .h_align(HAlign::Center)
+                .button("OK", |s| {
+                    s.pop_layer();
+                    s.with_user_data(|app: &mut AppState| {
+                        if let Some(game) = app.game.as_mut() {
+                            game.restore_checkpoint();
+                        }
+                    });
+                    redraw_content(s);
+                })
+                .button("Cancel", on_menu_back),
+            )
+            .on_event(Event::CtrlChar('b'), on_menu_back),
+        ),
+    }
+}
+
+fn on_restart(siv: &mut Cursive) {
+    let has_game = siv
+        .with_user_data(|app: &mut AppState| app.game.is_some())
+        .unwrap();
+    if !has_game {
+        return;
+    }
+    siv.add_layer(
+        OnEventView::new(
+            Dialog::text("Restart the story from the beginning? All progress will be lost.")
+                .h_align(HAlign::Center)
+                .button("OK", |s| {
+                    s.pop_layer();
+                    s.with_user_data(|app: &mut AppState| {
+                        if let Some(game) = app.game.as_mut() {
+                            game.restart();
+                        }
+                    });
+                    redraw_content(s);
+                })
+                .button("Cancel", on_menu_back),
+        )
+        .on_event(Event::CtrlChar('b'), on_menu_back),
+    )
+}
+
+fn on_go_back(siv: &mut Cursive) {
+    let can_go_back = siv
+        .with_user_data(|app: &mut AppState| {
+            app.game
+                .as_ref()
+                .map(|game| !game.history.is_empty() && game.character_creation.is_none())
+        })
+        .flatten();
+
+    match can_go_back {
+        None => {}
+        Some(false) => siv.add_layer(Dialog::info("There is no previous page to go back to.")),
+        Some(true) => siv.add_layer(
+            OnEventView::new(
+                Dialog::text(
+                    "Go back to the previous page? Choices made since then will not be undone.",
+                )
+                .h_align(HAlign::Center)
+                .button("OK", |s| {
+                    s.pop_layer();
+                    s.with_user_data(|app: &mut AppState| {
+                        if let Some(game) = app.game.as_mut() {
+                            game.go_back();
+                        }
+                    });
+                    redraw_content(s);
+                })
+                .button("Cancel", on_menu_back),
+            )
+            .on_event(Event::CtrlChar('b'), on_menu_back),
+        ),
+    }
+}
+
+/// Show the pages the player has been through, most recent first, any of which can be selected to
+/// go back to it.
+fn on_history(siv: &mut Cursive) {
+    let history = match siv
+        .with_user_data(|app: &mut AppState| {
+            let game = app.game.as_ref()?;
+            if game.character_creation.is_some() {
+                return None;
+            }
+            // Pages that no longer exist are listed without a history index, so they can't be
+            // selected.
+            let history: Vec<_> = game
+                .history
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(idx, item)| match item.page.upgrade() {
+                    Some(page) => {
+                        let page = page.borrow();
+                        let label = page.title.clone().unwrap_or_else(|| page.id.clone());
+                        (label, Some(idx))
+                    }
+                    None => ("(page no longer exists)".to_owned(), None),
+                })
+                .collect();
+            Some(history)
+        })
+        .flatten()
+    {
+        Some(history) => history,
+        None => return,
+    };
+
+    if history.is_empty() {
+        siv.add_layer(Dialog::info("There is no previous page to go back to."));
+        return;
+    }
+
+    let mut select = SelectView::<Option<usize>>::new().on_submit(
+        |s: &mut Cursive, history_idx: &Option<usize>| {
+            if let Some(history_idx) = *history_idx {
+                s.pop_layer();
+                s.with_user_data(|app: &mut AppState| {
+                    if let Some(game) = app.game.as_mut() {
+                        game.go_back_to(history_idx);
+                    }
+                });
+                redraw_content(s);
+            }
+        },
+    );
+    for (label, history_idx) in history {
+        match history_idx {
+            Some(_) => select.add_item(format!("> {}", label), history_idx),
+            None => select.add_item(
+                StyledString::styled(format!("  {}", label), BaseColor::Black.light()),
+                None,
+            ),
+        }
+    }
+
+    siv.add_layer(
+        OnEventView::new(
+            Dialog::around(
+                LinearLayout::vertical()
+                    .child(TextView::new(
+                        "Go back to which page? Choices made since then will not be undone.",
+                    ))
+                    .child(Panel::new(select.scrollable()).title("History")),
+            )
+            .h_align(HAlign::Center)
+            .button("Cancel", on_menu_back)
+            .max_width(((siv.screen_size().x as f32 * 0.75).round() as usize).min(60)),
+        )
+        .on_event(Event::CtrlChar('p'), on_menu_back)
+        .on_event(Event::CtrlChar('b'), on_menu_back),
+    );
+}
+
 fn on_quit(siv: &mut Cursive) {
     siv.add_layer(
         OnEventView::new(
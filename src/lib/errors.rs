@@ -38,6 +38,8 @@ pub enum Doctype {
     Settings,
     /// A story file.
     Story,
+    /// A file that the player's progress was saved to.
+    Save,
 }
 
 impl fmt::Display for Doctype {
@@ -45,6 +47,7 @@ impl fmt::Display for Doctype {
         match self {
             Doctype::Settings => write!(f, "Settings"),
             Doctype::Story => write!(f, "Story"),
+            Doctype::Save => write!(f, "Save"),
         }
     }
 }
@@ -57,10 +60,28 @@ pub enum Error {
     Unexpected(String),
     /// Invalid Page ID used in a story file.
     UndeclaredPageID(PageID),
+    /// The same page ID used for more than one page, in the story files at `first_path` and
+    /// `path`, which may be the same file.
+    DuplicatePageID {
+        page_id: PageID,
+        first_path: PathBuf,
+        path: PathBuf,
+    },
+    /// A link whose destination is an undeclared page ID.
+    BrokenLink {
+        /// The path of the story file containing the link, if the page has one.
+        path: Option<PathBuf>,
+        page_id: PageID,
+        link_idx: usize,
+        link_text: String,
+        dest: PageID,
+    },
     /// Undeclared variable used in story file.
     UndeclaredVariable(String),
     /// Undeclared item used in story file.
     UndeclaredItem(String),
+    /// Undeclared link template included in story file.
+    UndeclaredTemplate(String),
     /// Wrong value type used in story file.
     BadValueType {
         value: Variable,
@@ -72,6 +93,32 @@ pub enum Error {
         var_type: VarType,
         expected: VarType,
     },
+    /// Template helpers that depend on each other in a cycle, in dependency order.
+    CyclicHelpers(Vec<String>),
+    /// The entrypoint in the settings file does not refer to any page.
+    MissingEntrypoint(PathBuf),
+    /// The base directory in the settings file contains no story files.
+    NoStoryFiles(PathBuf),
+    /// Page IDs declared in the settings file that no story file has a page for.
+    MissingPages(Vec<PageID>),
+    /// Pages that can't be reached from the entrypoint, when the settings forbid them.
+    UnreachablePages(Vec<PageID>),
+    /// Attempted to follow a link that doesn't exist.
+    NoSuchLink {
+        page_id: PageID,
+        link_idx: usize,
+    },
+    /// Attempted to follow a link whose requirements are not met.
+    LinkLocked {
+        page_id: PageID,
+        link_text: String,
+    },
+    /// An error that occurred while evaluating or following a link at runtime.
+    InLink {
+        page_id: PageID,
+        link_idx: usize,
+        error: Box<Error>,
+    },
     /// Error reading a file.
     ReadError {
         doctype: Doctype,
@@ -106,6 +153,7 @@ impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
             Error::ParseError { error, .. } => Some(error),
+            Error::InLink { error, .. } => Some(error.as_ref()),
             Error::Internal { error } => match error {
                 InternalError::CellBorrow(e) => Some(e),
                 InternalError::CellBorrowMut(e) => Some(e),
@@ -178,6 +226,34 @@ impl Error {
     pub fn undeclared_page_id<S: ToString>(s: S) -> Self {
         Error::UndeclaredPageID(s.to_string())
     }
+    /// Constructor method for [`Error::DuplicatePageID`].
+    pub fn duplicate_page_id<S: ToString, P: AsRef<Path>, Q: AsRef<Path>>(
+        page_id: S,
+        first_path: P,
+        path: Q,
+    ) -> Self {
+        Error::DuplicatePageID {
+            page_id: page_id.to_string(),
+            first_path: first_path.as_ref().to_path_buf(),
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+    /// Constructor method for [`Error::BrokenLink`].
+    pub fn broken_link<S: ToString, T: ToString, U: ToString>(
+        path: Option<&Path>,
+        page_id: S,
+        link_idx: usize,
+        link_text: T,
+        dest: U,
+    ) -> Self {
+        Error::BrokenLink {
+            path: path.map(Path::to_path_buf),
+            page_id: page_id.to_string(),
+            link_idx,
+            link_text: link_text.to_string(),
+            dest: dest.to_string(),
+        }
+    }
     /// Constructor method for [`Error::UndeclaredVariable`].
     pub fn undeclared_variable<S: ToString>(s: S) -> Self {
         Error::UndeclaredVariable(s.to_string())
@@ -185,6 +261,10 @@ impl Error {
     pub fn undeclared_item<S: ToString>(s: S) -> Self {
         Error::UndeclaredItem(s.to_string())
     }
+    /// Constructor method for [`Error::UndeclaredTemplate`].
+    pub fn undeclared_template<S: ToString>(s: S) -> Self {
+        Error::UndeclaredTemplate(s.to_string())
+    }
     pub fn bad_value_type(value: &Variable, expected: VarType) -> Self {
         Error::BadValueType {
             value: value.clone(),
@@ -202,6 +282,60 @@ impl Error {
             expected,
         }
     }
+    /// Constructor method for [`Error::CyclicHelpers`].
+    pub fn cyclic_helpers<I>(names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        Error::CyclicHelpers(names.into_iter().map(|s| s.to_string()).collect())
+    }
+    /// Constructor method for [`Error::MissingEntrypoint`].
+    pub fn missing_entrypoint<P: AsRef<Path>>(path: P) -> Self {
+        Error::MissingEntrypoint(path.as_ref().to_path_buf())
+    }
+    /// Constructor method for [`Error::NoStoryFiles`].
+    pub fn no_story_files<P: AsRef<Path>>(base_dir: P) -> Self {
+        Error::NoStoryFiles(base_dir.as_ref().to_path_buf())
+    }
+    /// Constructor method for [`Error::MissingPages`].
+    pub fn missing_pages<I>(page_ids: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        Error::MissingPages(page_ids.into_iter().map(|s| s.to_string()).collect())
+    }
+    /// Constructor method for [`Error::UnreachablePages`].
+    pub fn unreachable_pages<I>(page_ids: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        Error::UnreachablePages(page_ids.into_iter().map(|s| s.to_string()).collect())
+    }
+    /// Constructor method for [`Error::NoSuchLink`].
+    pub fn no_such_link<S: ToString>(page_id: S, link_idx: usize) -> Self {
+        Error::NoSuchLink {
+            page_id: page_id.to_string(),
+            link_idx,
+        }
+    }
+    /// Constructor method for [`Error::LinkLocked`].
+    pub fn link_locked<S: ToString, T: ToString>(page_id: S, link_text: T) -> Self {
+        Error::LinkLocked {
+            page_id: page_id.to_string(),
+            link_text: link_text.to_string(),
+        }
+    }
+    /// Constructor method for [`Error::InLink`].
+    pub fn in_link<S: ToString>(page_id: S, link_idx: usize, error: Error) -> Self {
+        Error::InLink {
+            page_id: page_id.to_string(),
+            link_idx,
+            error: Box::new(error),
+        }
+    }
     /// Constructor method for [`Error::ReadError`].
     pub fn read_error<P: AsRef<Path>>(doctype: Doctype, path: P) -> Self {
         Error::ReadError {
@@ -293,8 +427,41 @@ impl Error {
                 "invalid page ID".to_string(),
                 format!("no page exists with ID '{}'", id),
             ],
+            Error::DuplicatePageID {
+                page_id,
+                first_path,
+                path,
+            } => vec![
+                format!(
+                    "duplicate page ID '{}' in `{}`, first used in `{}`",
+                    page_id,
+                    path.display(),
+                    first_path.display()
+                ),
+                "each page must have a unique ID".to_string(),
+            ],
+            Error::BrokenLink {
+                path,
+                page_id,
+                link_idx,
+                link_text,
+                dest,
+            } => {
+                let location = match path {
+                    Some(path) => format!(" in `{}`", path.display()),
+                    None => String::new(),
+                };
+                vec![
+                    format!(
+                        "broken link \"{}\" (link {}) on page '{}'{}",
+                        link_text, link_idx, page_id, location
+                    ),
+                    format!("no page exists with ID '{}'", dest),
+                ]
+            }
             Error::UndeclaredVariable(name) => vec![format!("undeclared variable '{}'", name)],
             Error::UndeclaredItem(name) => vec![format!("undeclared item '{}'", name)],
+            Error::UndeclaredTemplate(name) => vec![format!("undeclared link template '{}'", name)],
             Error::BadValueType { value, expected } => vec![
                 format!("bad type for value {:?}", value),
                 format!("expected a {}", expected),
@@ -307,6 +474,63 @@ impl Error {
                 format!("variable `{}` has wrong type", var_name),
                 format!("expected a {}, but got a {}", var_type, expected),
             ],
+            Error::CyclicHelpers(names) => vec![
+                "helpers depend on each other in a cycle".to_string(),
+                names.join(" -> "),
+            ],
+            Error::MissingEntrypoint(path) => vec![
+                format!("no page found for entrypoint `{}`", path.display()),
+                "note: the entrypoint must be a page ID, or the path of a story file (relative to \
+                `base_dir`) whose first page is the starting page"
+                    .to_string(),
+            ],
+            Error::NoStoryFiles(base_dir) => vec![
+                format!("no story files found in base_dir `{}`", base_dir.display()),
+                "note: `base_dir` is the directory containing the story files, and is relative to \
+                the current directory"
+                    .to_string(),
+            ],
+            Error::MissingPages(page_ids) => vec![
+                format!(
+                    "no page found for declared page ID(s) {}",
+                    page_ids
+                        .iter()
+                        .map(|id| format!("'{}'", id))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                "note: each page ID in `pages` must be the ID of a page in a story file"
+                    .to_string(),
+            ],
+            Error::UnreachablePages(page_ids) => vec![
+                format!(
+                    "no links lead to page(s) {}",
+                    page_ids
+                        .iter()
+                        .map(|id| format!("'{}'", id))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                "note: `strict_reachability` requires every page to be reachable from the entrypoint"
+                    .to_string(),
+            ],
+            Error::NoSuchLink { page_id, link_idx } => vec![format!(
+                "page '{}' has no link at index {}",
+                page_id, link_idx
+            )],
+            Error::LinkLocked { page_id, link_text } => vec![
+                format!("link \"{}\" on page '{}' is locked", link_text, page_id),
+                "its requirements are not met".to_string(),
+            ],
+            Error::InLink {
+                page_id,
+                link_idx,
+                error,
+            } => {
+                let mut parts = vec![format!("on page '{}', link {}", page_id, link_idx)];
+                parts.extend(error.to_string_vec(verbose));
+                parts
+            }
             Error::ReadError { doctype, path } => vec![format!(
                 "could not read {} file at `{}`",
                 doctype,
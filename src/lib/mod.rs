@@ -14,3 +14,6 @@ pub mod errors;
 pub mod parser;
 pub mod types;
 mod utils;
+
+#[cfg(test)]
+mod testing;
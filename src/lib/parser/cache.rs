@@ -0,0 +1,302 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use super::{finalize_pages, PageMap, Settings};
+use crate::errors::{Error, Result};
+use crate::types::{Page, PageID};
+use crate::utils;
+
+/// The contents of a story cache file.
+#[derive(Serialize, Deserialize)]
+struct StoryCache {
+    /// The [`key`] of the story files the cache was created from.
+    key: u64,
+    entrypoint: PageID,
+    pages: Vec<Page>,
+}
+
+/// A 64-bit FNV-1a hasher. Unlike [`DefaultHasher`](std::collections::hash_map::DefaultHasher),
+/// its output is the same in every build of the app, so caches aren't invalidated by updating the
+/// Rust toolchain.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    /// Adds `bytes` to the hash, preceded by their length so that consecutive inputs can't run
+    /// together.
+    fn write(&mut self, bytes: &[u8]) {
+        let len = (bytes.len() as u64).to_le_bytes();
+        for byte in len.iter().chain(bytes) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Computes a key that changes whenever the settings file or any of the story file `sources`
+/// change, or the app is updated.
+pub fn key(settings: &Settings, sources: &[(PathBuf, String)]) -> u64 {
+    let mut hasher = Fnv1a::new();
+    hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+    let settings_content = settings.source().and_then(|path| fs::read(path).ok());
+    hasher.write(settings_content.as_deref().unwrap_or_default());
+    for (path, content) in sources {
+        hasher.write(path.to_string_lossy().as_bytes());
+        hasher.write(content.as_bytes());
+    }
+    hasher.0
+}
+
+/// Returns the path of the cache file, which is named after the settings file's path, so that
+/// each story has its own cache.
+///
+/// Caches are kept in the app's cache directory (see [`utils::cache_dir`]) rather than next to the
+/// settings file, so that they aren't mixed in with the story's files, e.g. in version control,
+/// and stories in read-only directories can still be cached.
+fn path(settings: &Settings) -> Option<PathBuf> {
+    let source = settings.source()?;
+    let source = source
+        .canonicalize()
+        .unwrap_or_else(|_| source.to_path_buf());
+    let mut hasher = Fnv1a::new();
+    hasher.write(source.to_string_lossy().as_bytes());
+    Some(utils::cache_dir().join(format!("story-{:016x}.json", hasher.0)))
+}
+
+/// Loads the entrypoint page ID and pages from the cache, if the cache exists and has the given
+/// `key`. Returns [`None`](Option::None) on a cache miss or if the cache can't be read.
+pub fn load(settings: &Settings, key: u64) -> Option<(PageID, PageMap)> {
+    let path = path(settings)?;
+    let content = fs::read_to_string(&path).ok()?;
+    let cache: StoryCache = match serde_json::from_str(&content) {
+        Ok(cache) => cache,
+        Err(err) => {
+            debug!("ignoring invalid story cache `{}`: {}", path.display(), err);
+            return None;
+        }
+    };
+    if cache.key != key {
+        debug!("story cache `{}` is out of date", path.display());
+        return None;
+    }
+
+    let pages = cache
+        .pages
+        .into_iter()
+        .map(|page| (page.id.clone(), Rc::new(RefCell::new(page))))
+        .collect();
+    match finalize_pages(settings, pages) {
+        Ok(pages) if pages.contains_key(&cache.entrypoint) => Some((cache.entrypoint, pages)),
+        Ok(_) => None,
+        Err(err) => {
+            debug!("ignoring invalid story cache `{}`: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Writes the entrypoint page ID and `pages` to the cache with the given `key`.
+pub fn store(settings: &Settings, key: u64, entrypoint: &PageID, pages: &PageMap) -> Result<()> {
+    let path = match path(settings) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let cache = StoryCache {
+        key,
+        entrypoint: entrypoint.clone(),
+        pages: pages.values().map(|page| page.borrow().clone()).collect(),
+    };
+    let content = serde_json::to_string(&cache).map_err(Error::std)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, content)?;
+    debug!("wrote story cache `{}`", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use either::Either::Right;
+
+    use super::*;
+    use crate::parser::{parse_story, read_sources, Warning};
+    use crate::testing;
+    use crate::types::{Condition, LinkDest};
+
+    const SETTINGS: &str = r#"
+cache: true
+pages: [start, vault, attic]
+variables:
+  gold: 5
+  price: 2
+  hour: 3
+  name: Arthur
+  brave: true
+items:
+  key:
+    max_uses: 2
+    effect: { mod-num: { name: gold, value: 1 } }
+"#;
+
+    const STORY: &str = r#"
+id: start
+content: "The start."
+tags: [hall]
+links:
+  - text: "Open the vault"
+    dest: { page: vault }
+    requires:
+      and:
+        - brave
+        - "gold >= price"
+        - "hour % 2 == 1"
+        - 'name != "King Arthur"'
+        - { or: [{ has-item: key }, { lacks-item: key }] }
+        - { not: { visited: vault } }
+        - { item-uses: "key < 2" }
+        - { visit-count: { page: start, op: "<=", value: 3 } }
+        - { current-page-tag: hall }
+    triggers:
+      - condition: { op: { name: gold, op: ">", value: 3 } }
+        actions:
+          - mod-num: { name: gold, value: -1 }
+---
+id: vault
+content: "The vault."
+links:
+  - text: "Back"
+    dest: previous
+---
+id: attic
+content: "Nothing links here."
+"#;
+
+    /// Writes the story and points the app's cache at a temporary directory, returning the
+    /// story's settings.
+    fn cached_story(settings: &str, story: &str) -> Settings {
+        testing::temp_cache_dir();
+        testing::read_settings(
+            &format!("entrypoint: story.yaml\n{}", settings),
+            &[("story.yaml", story)],
+        )
+    }
+
+    /// Serializes `pages` to JSON, sorted by page ID, for comparing pages.
+    fn to_json(pages: &PageMap) -> Vec<String> {
+        let mut pages: Vec<_> = pages
+            .values()
+            .map(|page| serde_json::to_string(&*page.borrow()).unwrap())
+            .collect();
+        pages.sort();
+        pages
+    }
+
+    fn key_of(settings: &Settings) -> u64 {
+        key(settings, &read_sources(settings).unwrap())
+    }
+
+    #[test]
+    fn cache_round_trips_pages() {
+        let settings = cached_story(SETTINGS, STORY);
+        let (page_id, pages, _) = parse_story(&settings).unwrap();
+        assert!(path(&settings).unwrap().exists());
+
+        let (cached_id, cached_pages) = load(&settings, key_of(&settings)).unwrap();
+        assert_eq!(cached_id, page_id);
+        assert_eq!(to_json(&cached_pages), to_json(&pages));
+
+        let start = cached_pages["start"].borrow();
+        let cond = start.links[0].requires.as_ref().unwrap();
+        assert_eq!(
+            cond.to_string(),
+            pages["start"].borrow().links[0]
+                .requires
+                .as_ref()
+                .unwrap()
+                .to_string()
+        );
+        match cond {
+            Condition::And(conds) => match &conds[1] {
+                Condition::Op(operation) => {
+                    assert_eq!(operation.value_ref.as_deref(), Some("price"))
+                }
+                cond => panic!("expected an operation, got: {}", cond),
+            },
+            cond => panic!("expected an `and` condition, got: {}", cond),
+        }
+        // Links to other pages are resolved again.
+        assert!(matches!(&start.links[0].dest, LinkDest::Page(Right(_))));
+    }
+
+    #[test]
+    fn cache_hit_gives_same_warnings() {
+        let settings = cached_story(SETTINGS, STORY);
+        let (_, _, warnings) = parse_story(&settings).unwrap();
+        let (_, _, cached_warnings) = parse_story(&settings).unwrap();
+        assert!(!warnings.is_empty());
+        let messages = |warnings: &[Warning]| -> Vec<String> {
+            warnings.iter().map(|warning| warning.to_string()).collect()
+        };
+        assert_eq!(messages(&cached_warnings), messages(&warnings));
+    }
+
+    #[test]
+    fn changed_story_misses_cache() {
+        let settings = cached_story(SETTINGS, STORY);
+        parse_story(&settings).unwrap();
+        let old_key = key_of(&settings);
+
+        let story_path = settings.base_dir().join("story.yaml");
+        fs::write(&story_path, STORY.replace("The vault.", "The empty vault.")).unwrap();
+        let new_key = key_of(&settings);
+        assert_ne!(new_key, old_key);
+        assert!(load(&settings, new_key).is_none());
+
+        let (_, pages, _) = parse_story(&settings).unwrap();
+        assert_eq!(pages["vault"].borrow().content, "The empty vault.");
+        assert!(load(&settings, new_key).is_some());
+    }
+
+    #[test]
+    fn corrupt_cache_falls_back_to_parsing() {
+        let settings = cached_story(SETTINGS, STORY);
+        parse_story(&settings).unwrap();
+        let cache_path = path(&settings).unwrap();
+
+        for content in ["{ not json", "{\"key\": 1, \"entrypoint\": \"start\"}", ""].iter() {
+            fs::write(&cache_path, content).unwrap();
+            assert!(load(&settings, key_of(&settings)).is_none(), "{}", content);
+
+            let (page_id, pages, _) = parse_story(&settings).unwrap();
+            assert_eq!(page_id, "start");
+            assert_eq!(pages.len(), 3);
+            assert!(load(&settings, key_of(&settings)).is_some(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn key_is_stable() {
+        // Changing how keys are computed invalidates every cache, so it should be deliberate.
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"storygamer");
+        assert_eq!(hasher.0, 0xd065_e20f_214d_b69c);
+    }
+
+    #[test]
+    fn each_story_has_its_own_cache() {
+        let settings = cached_story(SETTINGS, STORY);
+        let other = cached_story(SETTINGS, STORY);
+        assert_ne!(path(&settings), path(&other));
+        assert!(path(&settings)
+            .unwrap()
+            .starts_with(testing::temp_cache_dir()));
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::errors::{Doctype, Error, Result};
+use crate::types::Page;
+
+/// Metadata at the start of a story file, which applies to every page in the file.
+///
+/// Front-matter is the first YAML document in the file, if that document has neither of the
+/// `content` and `links` fields that every page has. For example:
+///
+/// ```yaml
+/// ---
+/// author: "Anonymous"
+/// tags: ["chapter-1"]
+/// ---
+/// id: "intro"
+/// # ...
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FrontMatter {
+    /// The author of the pages in the file, unless a page has its own `author`.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Tags added to every page in the file.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Unknown fields, which are ignored.
+    #[serde(flatten)]
+    other: HashMap<String, serde_yaml::Value>,
+}
+
+impl FrontMatter {
+    /// Parses the first YAML document `value` of the story file at `path` into front-matter, or
+    /// returns [`None`](Option::None) if the document is a page.
+    pub fn parse(path: &Path, value: &serde_yaml::Value) -> Result<Option<Self>> {
+        if !Self::is_front_matter(value) {
+            return Ok(None);
+        }
+
+        let front_matter: FrontMatter = serde_yaml::from_value(value.clone())
+            .map_err(|e| Error::parse_error(Doctype::Story, path, e))?;
+        for key in front_matter.other.keys() {
+            warn!(
+                "ignoring unknown front-matter field `{}` in '{}'",
+                key,
+                path.display()
+            );
+        }
+        Ok(Some(front_matter))
+    }
+
+    /// Returns `true` if the first YAML document `value` of a story file is front-matter rather
+    /// than a page.
+    pub fn is_front_matter(value: &serde_yaml::Value) -> bool {
+        match value.as_mapping() {
+            Some(mapping) => ["content", "links"]
+                .iter()
+                .all(|&key| !mapping.contains_key(&serde_yaml::Value::from(key))),
+            None => false,
+        }
+    }
+
+    /// Merges the front-matter into `page`.
+    pub fn apply(&self, page: &mut Page) {
+        if page.author.is_none() {
+            page.author = self.author.clone();
+        }
+        let mut tags = self.tags.clone();
+        tags.retain(|tag| !page.tags.contains(tag));
+        tags.append(&mut page.tags);
+        page.tags = tags;
+    }
+}
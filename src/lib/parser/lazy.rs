@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde::Deserialize;
+
+use super::{
+    check_missing_pages, clean_page, entrypoint_page_id, page_id_or_default, parse_page,
+    read_sources, split_documents, validate_settings, FrontMatter, Settings,
+};
+use crate::errors::{Doctype, Error, Result};
+use crate::types::{Page, PageID, PageSource};
+
+/// The pages of a storygame, parsed on demand the first time each one is needed.
+///
+/// Creating a `LazyPages` reads every story file and finds the ID of each page, and validates the
+/// settings eagerly, but pages themselves are only parsed and validated by [`get`](Self::get).
+/// This makes loading large stories faster, at the cost of mistakes in a page (e.g. an undeclared
+/// variable) not being reported until the player reaches it. Links are resolved when they are
+/// followed rather than up front, and [`Page.parents`] is not filled in.
+pub struct LazyPages {
+    settings: Settings,
+    /// The source and YAML document of each page, and the front-matter of its file.
+    documents: HashMap<PageID, (PageSource, String, Option<Rc<FrontMatter>>)>,
+    pages: HashMap<PageID, Rc<RefCell<Page>>>,
+}
+
+impl LazyPages {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        // Only the `id` field is deserialized when indexing pages.
+        #[derive(Deserialize)]
+        struct PageHeader {
+            #[serde(default)]
+            id: PageID,
+        }
+
+        let mut documents: HashMap<PageID, (PageSource, _, _)> = HashMap::new();
+        for (path, content) in read_sources(settings)? {
+            let mut file_documents =
+                split_documents(&content, settings.one_page_per_file()).peekable();
+            let front_matter = match file_documents.peek() {
+                Some((_, document)) if !settings.one_page_per_file() => {
+                    let value = serde_yaml::from_str(document)
+                        .map_err(|e| Error::parse_error(Doctype::Story, &path, e))?;
+                    FrontMatter::parse(&path, &value)?.map(Rc::new)
+                }
+                _ => None,
+            };
+            if front_matter.is_some() {
+                file_documents.next();
+            }
+
+            for (index, document) in file_documents {
+                let PageHeader { id } = serde_yaml::from_str(document)
+                    .map_err(|e| Error::parse_error(Doctype::Story, &path, e))?;
+                let id = page_id_or_default(settings, &path, id)?;
+                // Check that page IDs are declared in settings.
+                if !settings.pages().contains(&id) {
+                    return Err(Error::undeclared_page_id(id));
+                }
+                if let Some((first, ..)) = documents.get(&id) {
+                    return Err(Error::duplicate_page_id(&id, &first.path, &path));
+                }
+                let source = PageSource {
+                    path: path.clone(),
+                    index,
+                };
+                documents.insert(id, (source, document.to_owned(), front_matter.clone()));
+            }
+        }
+        check_missing_pages(settings.pages(), |page_id| documents.contains_key(page_id))?;
+
+        validate_settings(settings)?;
+
+        Ok(LazyPages {
+            settings: settings.clone(),
+            documents,
+            pages: HashMap::new(),
+        })
+    }
+
+    /// Returns the [`Page`] which is designated as the entrypoint, parsing it if needed.
+    pub fn entrypoint(&mut self) -> Result<Rc<RefCell<Page>>> {
+        let sources = self
+            .documents
+            .iter()
+            .map(|(page_id, (source, ..))| (page_id.clone(), Some(source.clone())))
+            .collect();
+        let page_id = entrypoint_page_id(&self.settings, sources)
+            .ok_or_else(|| Error::missing_entrypoint(self.settings.entrypoint()))?;
+        self.get(&page_id)
+    }
+
+    /// Returns the [`Page`] with the given ID, parsing and validating it the first time.
+    pub fn get(&mut self, page_id: &str) -> Result<Rc<RefCell<Page>>> {
+        if let Some(page) = self.pages.get(page_id) {
+            return Ok(Rc::clone(page));
+        }
+        let (source, document, front_matter) = self
+            .documents
+            .get(page_id)
+            .ok_or_else(|| Error::undeclared_page_id(page_id))?;
+
+        debug!("parsing page '{}'", page_id);
+        let page = Rc::new(RefCell::new(parse_page(
+            &self.settings,
+            &source.path,
+            source.index,
+            document,
+            front_matter.as_deref(),
+        )?));
+        clean_page(&page, None, &self.settings)?;
+
+        self.pages.insert(page_id.to_owned(), Rc::clone(&page));
+        Ok(page)
+    }
+}
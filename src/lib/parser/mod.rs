@@ -1,21 +1,37 @@
+mod cache;
+mod front_matter;
+mod lazy;
+mod report;
 mod settings;
+mod stats;
 
+use std::borrow;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::iter;
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
+use std::thread;
 
-use either::Either::*;
+use either::{Either, Either::*};
 use regex::Regex;
 use same_file::is_same_file;
+use serde::de;
 
-pub use self::settings::Settings;
-use crate::errors::{Doctype, Error, InternalError, Result};
+pub use self::front_matter::FrontMatter;
+pub use self::lazy::LazyPages;
+pub use self::report::{ValidationReport, Warning, WarningKind};
+pub use self::settings::{LockedChoiceDisplay, Metadata, Settings};
+pub use self::stats::{story_stats, StoryStats, WORDS_PER_MINUTE};
+use crate::errors::{Doctype, Error, Result};
 use crate::types::{
-    ComparisonOp, Condition, ItemDef, LinkAction, LinkDest, Operation, Page, PageID, Prompt,
-    VarType, Variable,
+    ComparisonOp, Condition, CreationStep, Expr, ItemDef, Link, LinkAction, LinkDest, Operation,
+    Page, PageID, PageSource, Prompt, VarType, Variable, VariableDef, TURN_COUNT,
 };
 
+type PageMap = HashMap<PageID, Rc<RefCell<Page>>>;
+
 lazy_static! {
     static ref RE_DOCUMENT_SEP: Regex =
         Regex::new(r#"(?xm) (\A | (^ \.{3} .* $)?) (^ -{3} \s* $) | \A"#).unwrap();
@@ -25,218 +41,1672 @@ lazy_static! {
 ///
 /// 1. Reads files from [`Settings.base_dir`].
 /// 2. Parses file contents into [`Page`] objects.
-/// 3. Validates and finalizes parsed data.
+/// 3. Validates and finalizes parsed data, logging warnings for likely mistakes.
 /// 4. Returns the [`Page`] which is designated as the entrypoint.
 pub fn parse(settings: &Settings) -> Result<Rc<RefCell<Page>>> {
-    let mut pages = read_pages(settings)?;
-    let pages_clone = pages.clone();
+    parse_with_warnings(settings).map(|(page, _)| page)
+}
 
-    let page_ids = settings.pages();
+/// Like [`parse`], but also returns the warnings that were logged while validating the pages.
+pub fn parse_with_warnings(settings: &Settings) -> Result<(Rc<RefCell<Page>>, Vec<Warning>)> {
+    parse_story(settings)
+        .map(|(page_id, mut pages, warnings)| (pages.remove(&page_id).unwrap(), warnings))
+}
+
+/// Like [`parse_with_warnings`], but returns every page by ID along with the ID of the entrypoint
+/// page, so that pages which no link leads to are kept as well.
+pub fn parse_story(settings: &Settings) -> Result<(PageID, PageMap, Vec<Warning>)> {
+    let sources = read_sources(settings)?;
+
+    // If caching is enabled, try to skip parsing by loading the pages from the cache. Cached pages
+    // are checked the same way as freshly parsed ones, so they give the same warnings.
+    let cache_key = if settings.cache() {
+        Some(cache::key(settings, &sources))
+    } else {
+        None
+    };
+    let cached = cache_key.and_then(|key| cache::load(settings, key));
+    let is_cached = cached.is_some();
+    let (page_id, pages) = match cached {
+        Some(cached) => {
+            debug!("loaded pages from cache");
+            cached
+        }
+        None => {
+            let pages = finalize_pages(
+                settings,
+                read_pages(settings, &sources, parallel_min_files())?,
+            )?;
+            (pages_entrypoint_id(settings, &pages)?, pages)
+        }
+    };
+    let mut warnings = validate(&pages);
+    validate_settings(settings)?;
+
+    warnings.extend(check_reachability(settings, &pages[&page_id], &pages)?);
+    for warning in warnings.iter() {
+        warn!("{}", warning);
+    }
+
+    if let (Some(key), false) = (cache_key, is_cached) {
+        if let Err(err) = cache::store(settings, key, &page_id, &pages) {
+            warn!("failed to write story cache: {}", err);
+        }
+    }
+    Ok((page_id, pages, warnings))
+}
+
+/// Reads, parses, and checks a storygame using the given [`Settings`], collecting the errors that
+/// would prevent it from being played and the warnings that [`validate`] finds.
+///
+/// Unlike [`parse`], this never loads the pages from the cache. Parsing stops at the first error,
+/// so if there are errors, there may be more mistakes that aren't reported yet.
+pub fn analyze(settings: &Settings) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    match parse_pages(settings) {
+        Ok(pages) => {
+            report.warnings = validate(&pages);
+            let reachability = pages_entrypoint_id(settings, &pages)
+                .and_then(|page_id| check_reachability(settings, &pages[&page_id], &pages));
+            match reachability {
+                Ok(warnings) => report.warnings.extend(warnings),
+                Err(err) => report.errors.push(err),
+            }
+        }
+        Err(err) => report.errors.push(err),
+    }
+    if let Err(err) = validate_settings(settings) {
+        report.errors.push(err);
+    }
+    report
+}
+
+/// Validates the parts of [`Settings`] that refer to other parts of the settings.
+fn validate_settings(settings: &Settings) -> Result<()> {
     let variables = settings.variables();
     let items = settings.items();
 
-    for page_id in pages_clone.keys() {
-        // Check that page IDs are declared in settings.
-        if !page_ids.contains(page_id) {
-            return Err(Error::undeclared_page_id(page_id));
+    validate_helpers(settings)?;
+
+    // Check that timers count down number variables, and go to declared pages when they run out.
+    for name in settings.timers().keys() {
+        match variables.get(name) {
+            Some(var) if var.type_() == VarType::Num => {}
+            Some(var) => return Err(Error::bad_variable_type(name, var.type_(), VarType::Num)),
+            None => return Err(Error::undeclared_variable(name)),
         }
-        let page = pages
-            .get_mut(page_id)
-            .ok_or_else(|| Error::undeclared_page_id(page_id))?;
-
-        /*
-         * Define `clean_*` functions.
-         */
-
-        let clean_link_dest = |dest: &mut LinkDest| -> Result<()> {
-            if let LinkDest::Page(ref mut to_page) = dest {
-                if let Left(ref to_page_id) = to_page {
-                    let child = Rc::clone(
-                        pages_clone
-                            .get(to_page_id)
-                            .ok_or_else(|| Error::undeclared_page_id(to_page_id))?,
-                    );
-                    if let Ok(mut child_ref) = child.try_borrow_mut() {
-                        child_ref.parents.push(Rc::downgrade(&page));
-                    }
-                    *to_page = Right(child);
-                }
+    }
+    for to_page_id in timer_page_ids(settings) {
+        if !settings.pages().contains(to_page_id) {
+            return Err(Error::undeclared_page_id(to_page_id));
+        }
+    }
+
+    // Check that timers and items that go to a page named by a variable name a string variable.
+    let set_dest_actions = settings
+        .timers()
+        .values()
+        .flat_map(|timer| timer.on_zero.iter())
+        .chain(items.values().map(|def| &def.effect));
+    for action in set_dest_actions {
+        if let LinkAction::SetDest(LinkDest::PageFromVar(name)) = action {
+            match variables.get(name) {
+                Some(var) if var.type_() == VarType::Str => {}
+                Some(var) => return Err(Error::bad_variable_type(name, var.type_(), VarType::Str)),
+                None => return Err(Error::undeclared_variable(name)),
             }
-            Ok(())
-        };
+        }
+    }
 
-        let clean_action = |action: &mut LinkAction| -> Result<()> {
-            match action {
-                // Check that variables are declared in settings and that values have correct types.
-                LinkAction::SetVar { name, value } => match variables.get(name) {
-                    Some(var) if var.type_eq(value) => {}
-                    Some(var) => {
-                        return Err(Error::bad_value_type(value, var.type_()));
-                    }
-                    None => return Err(Error::undeclared_variable(name)),
-                },
-                LinkAction::ModNum { name, .. } => match variables.get(name) {
-                    Some(Variable::Num(_)) => {}
-                    Some(var) => {
-                        return Err(Error::bad_variable_type(name, var.type_(), VarType::Num))
-                    }
-                    None => return Err(Error::undeclared_variable(name)),
+    // Check that character creation steps refer to declared variables and items.
+    for step in settings.character_creation() {
+        match step {
+            CreationStep::Prompt(
+                prompt @ Prompt {
+                    variable,
+                    buttons,
+                    requires,
+                    ..
                 },
-                LinkAction::ToggleBool(name) => match variables.get(name) {
-                    Some(Variable::Bool(_)) => {}
-                    Some(var) => {
-                        return Err(Error::bad_variable_type(name, var.type_(), VarType::Bool))
+            ) => {
+                if let Some(var_name) = variable {
+                    if !variables.contains_key(var_name) {
+                        return Err(Error::undeclared_variable(var_name));
                     }
-                    None => return Err(Error::undeclared_variable(name)),
-                },
-                &mut LinkAction::SetDest(ref mut dest) => {
-                    clean_link_dest(dest)?;
                 }
-                LinkAction::Prompt(Prompt { variable, .. }) => {
-                    if let Some(var_name) = variable {
-                        if !variables.contains_key(var_name.as_str()) {
-                            return Err(Error::undeclared_variable(var_name));
-                        }
-                    }
+                validate_prompt_input(prompt, variables)?;
+                // There's no page to follow links on during character creation.
+                if !buttons.is_empty() {
+                    return Err(Error::unexpected(
+                        "`buttons` in a character creation prompt",
+                    ));
+                }
+                // Every character creation step is shown.
+                if requires.is_some() {
+                    return Err(Error::unexpected(
+                        "`requires` in a character creation prompt",
+                    ));
                 }
-                LinkAction::AcquireItem(name)
-                | LinkAction::DropItem(name)
-                | LinkAction::UseItem(name) => {
+            }
+            CreationStep::ChooseItem { items: choices, .. } => {
+                if choices.is_empty() {
+                    return Err(Error::expected(
+                        "at least one item to choose from in character creation",
+                    ));
+                }
+                for name in choices {
                     if !items.contains_key(name) {
                         return Err(Error::undeclared_item(name));
                     }
                 }
             }
-            Ok(())
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the `min`, `max`, and `choices` of a prompt (whose variable has been checked to
+/// exist) are only given for a variable that they make sense for.
+fn validate_prompt_input(prompt: &Prompt, variables: &HashMap<String, VariableDef>) -> Result<()> {
+    let var_type = match prompt
+        .variable
+        .as_ref()
+        .and_then(|name| variables.get(name))
+    {
+        Some(var) => var.type_(),
+        None if prompt.min.is_some() || prompt.max.is_some() || !prompt.choices.is_empty() => {
+            return Err(Error::expected(
+                "a `variable` in a prompt with `min`, `max`, or `choices`",
+            ))
+        }
+        None => return Ok(()),
+    };
+    if (prompt.min.is_some() || prompt.max.is_some()) && !var_type.is_numeric() {
+        return Err(Error::unexpected(format!(
+            "`min` or `max` in a prompt for a {} variable",
+            var_type
+        )));
+    }
+    if let (Some(min), Some(max)) = (prompt.min, prompt.max) {
+        if min > max {
+            return Err(Error::expected(format!(
+                "a minimum no greater than the maximum ({}) in a prompt",
+                max
+            )));
+        }
+    }
+    for choice in prompt.choices.iter() {
+        let value = var_type.parse(choice).ok_or_else(|| {
+            Error::expected(format!("a {} for the prompt choice {:?}", var_type, choice))
+        })?;
+        prompt.check_value(&value).map_err(|_| {
+            Error::expected(format!(
+                "prompt choices between the `min` and `max`, but got {:?}",
+                choice
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// Returns the IDs of the pages that timers send the player to when they run out.
+fn timer_page_ids(settings: &Settings) -> Vec<&PageID> {
+    set_dest_page_ids(
+        settings
+            .timers()
+            .values()
+            .flat_map(|timer| timer.on_zero.iter()),
+    )
+}
+
+/// Returns the IDs of the pages that the effects of items send the player to.
+fn item_page_ids(settings: &Settings) -> Vec<&PageID> {
+    set_dest_page_ids(settings.items().values().map(|def| &def.effect))
+}
+
+/// Returns the IDs of the pages that the `set-dest` actions in `actions` send the player to, by
+/// page ID or at random.
+fn set_dest_page_ids<'a, I>(actions: I) -> Vec<&'a PageID>
+where
+    I: IntoIterator<Item = &'a LinkAction>,
+{
+    let mut page_ids = Vec::new();
+    for action in actions {
+        let to_pages = match action {
+            LinkAction::SetDest(LinkDest::Page(to_page)) => std::slice::from_ref(to_page),
+            LinkAction::SetDest(LinkDest::Random(to_pages)) => to_pages.as_slice(),
+            _ => &[],
         };
+        page_ids.extend(
+            to_pages
+                .iter()
+                .filter_map(|to_page| to_page.as_ref().left()),
+        );
+    }
+    page_ids
+}
+
+/// Returns whether any link, timer, or item in the story sends the player to a page named by a
+/// variable, which can't be known until the story is played.
+fn has_page_from_var(settings: &Settings, pages: &HashMap<PageID, Rc<RefCell<Page>>>) -> bool {
+    let is_page_from_var = |dest: &LinkDest| matches!(dest, LinkDest::PageFromVar(_));
+    let is_set_page_from_var = |action: &LinkAction| match action {
+        LinkAction::SetDest(dest) => is_page_from_var(dest),
+        _ => false,
+    };
+    pages.values().any(|page| {
+        page.borrow().links.iter().any(|link| {
+            is_page_from_var(&link.dest) || link_actions(link).any(is_set_page_from_var)
+        })
+    }) || settings
+        .timers()
+        .values()
+        .any(|timer| timer.on_zero.iter().any(is_set_page_from_var))
+        || settings
+            .items()
+            .values()
+            .any(|def| is_set_page_from_var(&def.effect))
+}
+
+/// Checks that template helpers only refer to number variables and other helpers, and that no
+/// helper depends on itself.
+fn validate_helpers(settings: &Settings) -> Result<()> {
+    let variables = settings.variables();
+    let helpers = settings.helpers();
 
-        fn clean_operation(
-            operation: &mut Operation,
-            variables: &HashMap<String, Variable>,
-        ) -> Result<()> {
-            let var_name = &operation.name;
-            let var = variables
-                .get(var_name)
-                .ok_or_else(|| Error::undeclared_variable(var_name))?;
-
-            use ComparisonOp::*;
-            match operation.op {
-                GT | GTE | LT | LTE => {
-                    if var.type_() != VarType::Num {
-                        return Err(Error::bad_variable_type(
-                            var_name,
-                            var.type_(),
-                            VarType::Num,
-                        ));
+    for (name, expr) in helpers {
+        if variables.contains_key(name) {
+            return Err(Error::message(format!(
+                "helper '{}' has the same name as a variable",
+                name
+            )));
+        }
+        for var_name in expr.variables() {
+            match variables.get(var_name) {
+                Some(var) if !var.type_().is_numeric() => {
+                    return Err(Error::bad_variable_type(
+                        var_name,
+                        var.type_(),
+                        VarType::Num,
+                    ))
+                }
+                Some(_) => {}
+                None if helpers.contains_key(var_name) => {}
+                None => return Err(Error::undeclared_variable(var_name)),
+            }
+        }
+    }
+
+    // Detect cycles with a depth-first search from each helper.
+    fn visit<'a>(
+        name: &'a str,
+        helpers: &'a HashMap<String, Expr>,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(i) = path.iter().position(|&n| n == name) {
+            return Err(Error::cyclic_helpers(path[i..].iter().chain(&[name])));
+        }
+        if let Some(expr) = helpers.get(name) {
+            path.push(name);
+            for dep in expr.variables() {
+                visit(dep, helpers, path, done)?;
+            }
+            path.pop();
+        }
+        done.insert(name);
+        Ok(())
+    }
+
+    let mut names: Vec<&String> = helpers.keys().collect();
+    names.sort();
+    let mut done = HashSet::new();
+    for name in names {
+        visit(name, helpers, &mut Vec::new(), &mut done)?;
+    }
+    Ok(())
+}
+
+/// Finds the ID of the entrypoint page, given the ID and source of each page in the story.
+///
+/// [`Settings.entrypoint`] is resolved, in order of precedence, as:
+/// 1. A page ID.
+/// 2. The path of a file whose stem is a page ID.
+/// 3. The path of a file, in which case the first page in the file is used.
+fn entrypoint_page_id(
+    settings: &Settings,
+    sources: Vec<(PageID, Option<PageSource>)>,
+) -> Option<PageID> {
+    let entrypoint = settings.entrypoint();
+    let has_page = |page_id: &str| sources.iter().any(|(id, _)| id == page_id);
+    if let Some(page_id) = entrypoint.to_str() {
+        if has_page(page_id) {
+            return Some(page_id.to_owned());
+        }
+    }
+    if let Some(page_id) = entrypoint.file_stem().and_then(|stem| stem.to_str()) {
+        if has_page(page_id) {
+            return Some(page_id.to_owned());
+        }
+    }
+    let path = settings.base_dir().join(entrypoint);
+    sources
+        .into_iter()
+        .filter_map(|(page_id, source)| {
+            let source = source?;
+            match is_same_file(&source.path, &path) {
+                Ok(true) => Some((source.index, page_id)),
+                _ => None,
+            }
+        })
+        .min()
+        .map(|(_, page_id)| page_id)
+}
+
+/// Returns the ID of the entrypoint page among fully parsed `pages`.
+fn pages_entrypoint_id(
+    settings: &Settings,
+    pages: &HashMap<PageID, Rc<RefCell<Page>>>,
+) -> Result<PageID> {
+    let sources = pages
+        .values()
+        .map(|page| {
+            let page = page.borrow();
+            (page.id.clone(), page.source.clone())
+        })
+        .collect();
+    entrypoint_page_id(settings, sources)
+        .ok_or_else(|| Error::missing_entrypoint(settings.entrypoint()))
+}
+
+/// Finds the pages that can't be reached from `entrypoint`, returning a warning for each one, or
+/// an error if [`Settings::strict_reachability`] is set and no destination is a
+/// [`LinkDest::PageFromVar`].
+fn check_reachability(
+    settings: &Settings,
+    entrypoint: &Rc<RefCell<Page>>,
+    pages: &HashMap<PageID, Rc<RefCell<Page>>>,
+) -> Result<Vec<Warning>> {
+    let mut unreachable = find_unreachable(entrypoint, pages);
+    // Timers and items can send the player to a page from anywhere.
+    for page_id in timer_page_ids(settings)
+        .into_iter()
+        .chain(item_page_ids(settings))
+    {
+        if let Some(page) = pages.get(page_id) {
+            let reachable = reachable_pages(page);
+            unreachable.retain(|page_id| !reachable.contains_key(page_id));
+        }
+    }
+    if settings.strict_reachability()
+        && !unreachable.is_empty()
+        && !has_page_from_var(settings, pages)
+    {
+        return Err(Error::unreachable_pages(unreachable));
+    }
+    Ok(unreachable
+        .into_iter()
+        .map(|page_id| {
+            let message = format!("page '{}' can't be reached from the entrypoint", page_id);
+            Warning::in_page(WarningKind::Unreachable, &page_id, message)
+        })
+        .collect())
+}
+
+/// Reads, parses, and validates every [`Page`] in a storygame using the given [`Settings`].
+///
+/// Returns a map of page IDs to their pages, with all links between pages resolved.
+pub fn parse_pages(settings: &Settings) -> Result<HashMap<PageID, Rc<RefCell<Page>>>> {
+    let sources = read_sources(settings)?;
+    finalize_pages(
+        settings,
+        read_pages(settings, &sources, parallel_min_files())?,
+    )
+}
+
+/// Parser internals for benchmarks, which aren't part of the public API.
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+
+    /// Reads the path and content of every story file in [`Settings.base_dir`], sorted by path.
+    pub fn read_sources(settings: &Settings) -> Result<Vec<(PathBuf, String)>> {
+        super::read_sources(settings)
+    }
+
+    /// Parses the pages in the story file `sources` without validating them, in parallel if there
+    /// are at least `parallel_min_files` of them.
+    pub fn read_pages(
+        settings: &Settings,
+        sources: &[(PathBuf, String)],
+        parallel_min_files: usize,
+    ) -> Result<HashMap<PageID, Rc<RefCell<Page>>>> {
+        super::read_pages(settings, sources, parallel_min_files)
+    }
+}
+
+/// Validates parsed `pages` and resolves the links between them.
+fn finalize_pages(
+    settings: &Settings,
+    pages: HashMap<PageID, Rc<RefCell<Page>>>,
+) -> Result<HashMap<PageID, Rc<RefCell<Page>>>> {
+    let page_ids = settings.pages();
+
+    for page_id in pages.keys() {
+        // Check that page IDs are declared in settings.
+        if !page_ids.contains(page_id) {
+            return Err(Error::undeclared_page_id(page_id));
+        }
+    }
+    check_missing_pages(page_ids, |page_id| pages.contains_key(page_id))?;
+    for page in pages.values() {
+        clean_page(page, Some(&pages), settings)?;
+    }
+
+    Ok(pages)
+}
+
+/// Checks that every page ID declared in settings has a page, according to `has_page`.
+fn check_missing_pages<F>(page_ids: &HashSet<PageID>, has_page: F) -> Result<()>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut missing: Vec<_> = page_ids
+        .iter()
+        .filter(|page_id| !has_page(page_id))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    // Sort so the error is the same every time.
+    missing.sort();
+    Err(Error::missing_pages(missing))
+}
+
+/// Validates and finalizes a parsed [`Page`].
+///
+/// If `pages` is given, link destinations are resolved to the pages they refer to and each page's
+/// `parents` are filled in. Otherwise, destinations are only checked against the page IDs declared
+/// in settings, and are left to be resolved when the link is followed.
+fn clean_page(
+    page: &Rc<RefCell<Page>>,
+    pages: Option<&HashMap<PageID, Rc<RefCell<Page>>>>,
+    settings: &Settings,
+) -> Result<()> {
+    let page_ids = settings.pages();
+    let variables = settings.variables();
+    let items = settings.items();
+
+    /*
+     * Define `clean_*` functions.
+     */
+
+    let page_id = page.borrow().id.clone();
+    let clean_to_page = |to_page: &mut Either<PageID, Rc<RefCell<Page>>>| -> Result<()> {
+        if let Left(ref mut to_page_id) = to_page {
+            // Resolve relative links, e.g. `../chapter2/start`.
+            *to_page_id = resolve_page_id(&page_id, to_page_id)
+                .ok_or_else(|| Error::undeclared_page_id(&to_page_id))?;
+            let to_page_id = &*to_page_id;
+            // Without a page map, only check that the page ID is declared in settings.
+            let pages = match pages {
+                Some(pages) => pages,
+                None if page_ids.contains(to_page_id) => return Ok(()),
+                None => return Err(Error::undeclared_page_id(to_page_id)),
+            };
+            let child = Rc::clone(
+                pages
+                    .get(to_page_id)
+                    .ok_or_else(|| Error::undeclared_page_id(to_page_id))?,
+            );
+            if let Ok(mut child_ref) = child.try_borrow_mut() {
+                child_ref.parents.push(Rc::downgrade(page));
+            }
+            *to_page = Right(child);
+        }
+        Ok(())
+    };
+    let clean_link_dest = |dest: &mut LinkDest| -> Result<()> {
+        match dest {
+            LinkDest::PrevPages(0) => return Err(Error::expected("at least 1 page to go back")),
+            LinkDest::Page(to_page) => clean_to_page(to_page)?,
+            LinkDest::Random(to_pages) => {
+                if to_pages.is_empty() {
+                    return Err(Error::expected("at least 1 page to choose from at random"));
+                }
+                for to_page in to_pages.iter_mut() {
+                    clean_to_page(to_page)?;
+                }
+            }
+            // The page ID is only known at runtime, so only check the variable.
+            LinkDest::PageFromVar(name) => match variables.get(name) {
+                Some(var) if var.type_() == VarType::Str => {}
+                Some(var) => return Err(Error::bad_variable_type(name, var.type_(), VarType::Str)),
+                None => return Err(Error::undeclared_variable(name)),
+            },
+            _ => {}
+        }
+        Ok(())
+    };
+
+    let clean_action = |action: &mut LinkAction| -> Result<()> {
+        match action {
+            // Check that variables are declared in settings and that values have correct types.
+            LinkAction::SetVar { name, value } => match variables.get(name) {
+                Some(var) if var.type_() == value.type_() => {}
+                // Allow whole numbers to be given for decimal numbers, e.g. `10` for `10.0`.
+                Some(var) if var.type_() == VarType::Float && value.type_() == VarType::Num => {
+                    *value = Variable::Float(value.as_f64().unwrap());
+                }
+                Some(var) => {
+                    return Err(Error::bad_value_type(value, var.type_()));
+                }
+                None => return Err(Error::undeclared_variable(name)),
+            },
+            LinkAction::SetVarFrom { name, from } => {
+                let var = variables
+                    .get(name)
+                    .ok_or_else(|| Error::undeclared_variable(&name))?;
+                let from_var = variables
+                    .get(from)
+                    .ok_or_else(|| Error::undeclared_variable(&from))?;
+                if from_var.type_() != var.type_() {
+                    return Err(Error::bad_variable_type(
+                        from,
+                        from_var.type_(),
+                        var.type_(),
+                    ));
+                }
+            }
+            LinkAction::AppendStr { name, .. } => match variables.get(name) {
+                Some(var) if var.type_() == VarType::Str => {}
+                Some(var) => return Err(Error::bad_variable_type(name, var.type_(), VarType::Str)),
+                None => return Err(Error::undeclared_variable(name)),
+            },
+            LinkAction::ModNum { name, .. } => match variables.get(name) {
+                Some(var) if var.type_() == VarType::Num => {}
+                Some(var) => return Err(Error::bad_variable_type(name, var.type_(), VarType::Num)),
+                None => return Err(Error::undeclared_variable(name)),
+            },
+            LinkAction::ModNumByVar { name, by, .. } => {
+                for name in [name, by] {
+                    match variables.get(name) {
+                        Some(var) if var.type_() == VarType::Num => {}
+                        Some(var) => {
+                            return Err(Error::bad_variable_type(name, var.type_(), VarType::Num))
+                        }
+                        None => return Err(Error::undeclared_variable(name)),
                     }
-                    if operation.value.type_() != VarType::Num {
-                        return Err(Error::bad_value_type(&operation.value, VarType::Num));
+                }
+            }
+            LinkAction::ModFloat { name, value } => match variables.get(name) {
+                Some(var) if var.type_() == VarType::Float => {
+                    if !value.is_finite() {
+                        return Err(Error::expected("a finite number for `mod-float`"));
                     }
                 }
-                _ => {}
-            };
-            Ok(())
-        }
-
-        fn clean_condition(
-            cond: &mut Condition,
-            variables: &HashMap<String, Variable>,
-            items: &HashMap<String, ItemDef>,
-        ) -> Result<()> {
-            match cond {
-                Condition::And(children) | Condition::Or(children) => {
-                    for child in children.iter_mut() {
-                        clean_condition(child, variables, items)?;
+                Some(var) => {
+                    return Err(Error::bad_variable_type(name, var.type_(), VarType::Float))
+                }
+                None => return Err(Error::undeclared_variable(name)),
+            },
+            LinkAction::Roll { name, min, max } => {
+                match variables.get(name) {
+                    Some(var) if var.type_() == VarType::Num => {}
+                    Some(var) => {
+                        return Err(Error::bad_variable_type(name, var.type_(), VarType::Num))
                     }
+                    None => return Err(Error::undeclared_variable(name)),
                 }
-                Condition::Op(operation) => {
-                    clean_operation(operation, variables)?;
+                if min > max {
+                    return Err(Error::expected(format!(
+                        "a minimum no greater than the maximum ({}) in `roll`",
+                        max
+                    )));
                 }
-                Condition::Not(condition) => {
-                    clean_condition(condition, variables, items)?;
+            }
+            LinkAction::ToggleBool(name) => match variables.get(name) {
+                Some(var) if var.type_() == VarType::Bool => {}
+                Some(var) => {
+                    return Err(Error::bad_variable_type(name, var.type_(), VarType::Bool))
                 }
-                Condition::HasItem(name) => {
-                    if !items.contains_key(name) {
-                        return Err(Error::undeclared_item(name));
+                None => return Err(Error::undeclared_variable(name)),
+            },
+            &mut LinkAction::SetDest(ref mut dest) => {
+                clean_link_dest(dest)?;
+            }
+            LinkAction::Prompt(prompt) => {
+                if let Some(var_name) = &prompt.variable {
+                    if !variables.contains_key(var_name.as_str()) {
+                        return Err(Error::undeclared_variable(var_name));
                     }
+                    if !prompt.buttons.is_empty() {
+                        return Err(Error::unexpected("`buttons` in a prompt with a `variable`"));
+                    }
+                }
+                validate_prompt_input(prompt, variables)?;
+                if let Some(cond) = prompt.requires.as_mut() {
+                    clean_condition(cond, &page_id, page_ids, variables, items)?;
+                }
+            }
+            LinkAction::AddNote(_) | LinkAction::SetCheckpoint => {}
+            LinkAction::AcquireItem(name)
+            | LinkAction::DropItem(name)
+            | LinkAction::UseItem(name) => {
+                if !items.contains_key(name) {
+                    return Err(Error::undeclared_item(name));
                 }
             }
-            Ok(())
+        }
+        Ok(())
+    };
+
+    fn clean_operation(
+        operation: &mut Operation,
+        variables: &HashMap<String, VariableDef>,
+    ) -> Result<()> {
+        let var_name = &operation.name;
+        let var_type = match variables.get(var_name) {
+            Some(var) => var.type_(),
+            // A declared variable takes precedence over the built-in turn count.
+            None if var_name == TURN_COUNT => VarType::Num,
+            None => return Err(Error::undeclared_variable(var_name)),
+        };
+        check_modulo(operation)?;
+        if operation.modulo.is_some() && var_type != VarType::Num {
+            return Err(Error::bad_variable_type(var_name, var_type, VarType::Num));
         }
 
-        /*
-         * Loop through pages and run `clean_*` functions on them.
-         */
+        use ComparisonOp::*;
+        match operation.op {
+            GT | GTE | LT | LTE => {
+                if !var_type.is_numeric() {
+                    return Err(Error::bad_variable_type(var_name, var_type, VarType::Num));
+                }
+                check_operand_type(operation, variables, var_type)?;
+            }
+            EQ | NEQ => {
+                check_operand_type(operation, variables, var_type)?;
+            }
+        };
+        Ok(())
+    }
 
-        for link in &mut page.borrow_mut().links.iter_mut() {
-            clean_link_dest(&mut link.dest)?;
+    /// Checks that the `modulo` of `operation`, if any, is positive, so that the remainder is
+    /// well-defined and can't overflow.
+    fn check_modulo(operation: &Operation) -> Result<()> {
+        match operation.modulo {
+            Some(modulo) if modulo <= 0 => Err(Error::expected("a positive number after `%`")),
+            _ => Ok(()),
+        }
+    }
 
-            for trigger in link.triggers.iter_mut() {
-                clean_condition(&mut trigger.condition, variables, items)?;
-                for action in trigger.actions.iter_mut() {
-                    clean_action(action)?;
+    /// Checks that the value `operation` compares against can be compared with the `expected`
+    /// type, resolving its `value_ref` to a declared variable or else clearing it.
+    fn check_operand_type(
+        operation: &mut Operation,
+        variables: &HashMap<String, VariableDef>,
+        expected: VarType,
+    ) -> Result<()> {
+        match operation
+            .value_ref
+            .as_ref()
+            .map(|name| (name, variables.get(name)))
+        {
+            Some((name, Some(var))) => {
+                if !var.type_().is_comparable_to(expected) {
+                    return Err(Error::bad_variable_type(name, var.type_(), expected));
                 }
             }
-            for action in link.actions.iter_mut() {
-                clean_action(action)?;
+            _ => {
+                operation.value_ref = None;
+                if !operation.value.type_().is_comparable_to(expected) {
+                    return Err(Error::bad_value_type(&operation.value, expected));
+                }
             }
         }
+        Ok(())
     }
 
-    // Return entrypoint page.
-    Ok(pages
-        .remove(
-            &settings
-                .entrypoint()
-                .file_stem()
-                .ok_or_else(|| InternalError::PathAttr("file_stem"))?
-                .to_str()
-                .unwrap()
-                .to_owned(),
-        )
-        .unwrap())
+    fn clean_condition(
+        cond: &mut Condition,
+        page_id: &str,
+        page_ids: &HashSet<PageID>,
+        variables: &HashMap<String, VariableDef>,
+        items: &HashMap<String, ItemDef>,
+    ) -> Result<()> {
+        match cond {
+            Condition::And(children) | Condition::Or(children) => {
+                for child in children.iter_mut() {
+                    clean_condition(child, page_id, page_ids, variables, items)?;
+                }
+            }
+            Condition::Op(operation) => {
+                clean_operation(operation, variables)?;
+            }
+            Condition::Not(condition) => {
+                clean_condition(condition, page_id, page_ids, variables, items)?;
+            }
+            Condition::HasItem(name) | Condition::LacksItem(name) => {
+                if !items.contains_key(name) {
+                    return Err(Error::undeclared_item(name));
+                }
+            }
+            Condition::ItemUses(operation) => {
+                if !items.contains_key(&operation.name) {
+                    return Err(Error::undeclared_item(&operation.name));
+                }
+                check_modulo(operation)?;
+                check_operand_type(operation, variables, VarType::Num)?;
+            }
+            Condition::CurrentPageTag(_) => {}
+            Condition::Visited(visited_id)
+            | Condition::VisitCount {
+                page: visited_id, ..
+            } => {
+                // Resolve relative page IDs, like in links.
+                *visited_id = resolve_page_id(page_id, visited_id)
+                    .filter(|visited_id| page_ids.contains(visited_id))
+                    .ok_or_else(|| Error::undeclared_page_id(&visited_id))?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Run `clean_*` functions on the page's links.
+     */
+
+    let path = page
+        .borrow()
+        .source
+        .as_ref()
+        .map(|source| source.path.clone());
+    for (link_idx, link) in page.borrow_mut().links.iter_mut().enumerate() {
+        // Report undeclared destinations along with the link they're in.
+        let link_text = link.text.clone();
+        let broken_link = |err| match err {
+            Error::UndeclaredPageID(dest) => {
+                Error::broken_link(path.as_deref(), &page_id, link_idx, &link_text, dest)
+            }
+            err => err,
+        };
+        let clean_link_action = |action: &mut LinkAction| match action {
+            LinkAction::SetDest(dest) => clean_link_dest(dest).map_err(broken_link),
+            action => clean_action(action),
+        };
+
+        clean_link_dest(&mut link.dest).map_err(broken_link)?;
+
+        if let Some(cond) = link.requires.as_mut() {
+            clean_condition(cond, &page_id, page_ids, variables, items)?;
+        }
+        for trigger in link.triggers.iter_mut() {
+            clean_condition(&mut trigger.condition, &page_id, page_ids, variables, items)?;
+            for action in trigger
+                .actions
+                .iter_mut()
+                .chain(trigger.else_actions.iter_mut())
+            {
+                clean_link_action(action)?;
+            }
+        }
+        for action in link.actions.iter_mut() {
+            clean_link_action(action)?;
+        }
+    }
+
+    Ok(())
 }
 
-fn read_pages(settings: &Settings) -> Result<HashMap<String, Rc<RefCell<Page>>>> {
-    let config_path = settings.source();
+/// Checks `pages` for likely mistakes that don't prevent the story from being played, returning a
+/// [`Warning`] for each one.
+pub fn validate(pages: &HashMap<PageID, Rc<RefCell<Page>>>) -> Vec<Warning> {
+    let mut page_ids: Vec<&PageID> = pages.keys().collect();
+    page_ids.sort();
+
+    let mut warnings = Vec::new();
+
+    for page_id in page_ids {
+        let page = pages[page_id].borrow();
+        // Blank content is allowed for link-only pages, but is usually unintentional.
+        if page.content.trim().is_empty() {
+            warnings.push(Warning::in_page(
+                WarningKind::EmptyContent,
+                page_id,
+                format!("page '{}' has no content", page_id),
+            ));
+        }
+        // A link back to the same page that does nothing leaves the player where they were.
+        for (link_idx, link) in page.links.iter().enumerate() {
+            let is_self_link = match &link.dest {
+                LinkDest::CurrentPage => true,
+                LinkDest::Page(Left(to_page_id)) => to_page_id == page_id,
+                LinkDest::Page(Right(to_page)) => Rc::ptr_eq(to_page, &pages[page_id]),
+                _ => false,
+            };
+            if is_self_link && link.actions.is_empty() && link.triggers.is_empty() {
+                warnings.push(Warning::in_link(
+                    WarningKind::NoOpSelfLink,
+                    page_id,
+                    link_idx,
+                    format!(
+                        "link \"{}\" on page '{}' links to the same page without doing anything",
+                        link.text, page_id
+                    ),
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Returns the pages in `pages` that have the given `tag`, sorted by page ID.
+pub fn pages_with_tag(
+    pages: &HashMap<PageID, Rc<RefCell<Page>>>,
+    tag: &str,
+) -> Vec<Rc<RefCell<Page>>> {
+    let mut tagged: Vec<_> = pages
+        .values()
+        .filter(|page| page.borrow().tags.iter().any(|t| t == tag))
+        .map(Rc::clone)
+        .collect();
+    tagged.sort_by(|a, b| a.borrow().id.cmp(&b.borrow().id));
+    tagged
+}
 
-    // Read content from all source files.
-    let mut sources = Vec::new();
-    for entry in fs::read_dir(settings.base_dir())? {
-        let path = entry?.path();
-        if !path.is_file() {
+/// Returns every distinct ending message in `pages`, each paired with the ID of the first page (by
+/// ID) that can end the game with it, sorted by page ID.
+///
+/// Endings are collected from the destinations, actions, and triggers of each page's links,
+/// including the effects of `items` used by those actions.
+pub fn endings<D: borrow::Borrow<ItemDef>>(
+    pages: &HashMap<PageID, Rc<RefCell<Page>>>,
+    items: &HashMap<String, D>,
+) -> Vec<(PageID, String)> {
+    fn collect<'a, D: borrow::Borrow<ItemDef>>(
+        action: &'a LinkAction,
+        items: &'a HashMap<String, D>,
+        used_items: &mut Vec<&'a str>,
+        messages: &mut Vec<String>,
+    ) {
+        match action {
+            LinkAction::SetDest(LinkDest::EndGame(msg)) => messages.push(msg.clone()),
+            // Items can use other items, so only visit each item's effect once.
+            LinkAction::UseItem(name) if !used_items.contains(&name.as_str()) => {
+                used_items.push(name);
+                if let Some(def) = items.get(name) {
+                    let def: &ItemDef = borrow::Borrow::borrow(def);
+                    collect(&def.effect, items, used_items, messages);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut page_ids: Vec<&PageID> = pages.keys().collect();
+    page_ids.sort();
+
+    let mut endings = Vec::new();
+    let mut seen = HashSet::new();
+    for page_id in page_ids {
+        let page = pages[page_id].borrow();
+        let mut messages = Vec::new();
+        for link in page.links.iter() {
+            if let LinkDest::EndGame(msg) = &link.dest {
+                messages.push(msg.clone());
+            }
+            for action in link_actions(link) {
+                collect(action, items, &mut Vec::new(), &mut messages);
+            }
+        }
+        for msg in messages {
+            if seen.insert(msg.clone()) {
+                endings.push((page_id.clone(), msg));
+            }
+        }
+    }
+    endings
+}
+
+/// Returns the pages that can be reached by following links from `start`, including `start`.
+///
+/// Only links whose destinations have been resolved to pages are followed, so when the story is
+/// parsed lazily, this only includes pages that have been loaded.
+pub fn reachable_pages(start: &Rc<RefCell<Page>>) -> HashMap<PageID, Rc<RefCell<Page>>> {
+    let mut pages = HashMap::new();
+    let mut stack = vec![Rc::clone(start)];
+    while let Some(page) = stack.pop() {
+        let page_id = page.borrow().id.clone();
+        if pages.contains_key(&page_id) {
             continue;
         }
-        if let Some(cfg_path) = config_path {
-            if let Ok(true) = is_same_file(&path, cfg_path) {
+        for link in page.borrow().links.iter() {
+            let dests =
+                iter::once(&link.dest).chain(link_actions(link).filter_map(
+                    |action| match action {
+                        LinkAction::SetDest(dest) => Some(dest),
+                        _ => None,
+                    },
+                ));
+            for dest in dests {
+                match dest {
+                    LinkDest::Page(Right(child)) => stack.push(Rc::clone(child)),
+                    LinkDest::Random(to_pages) => {
+                        for to_page in to_pages.iter() {
+                            if let Right(child) = to_page {
+                                stack.push(Rc::clone(child));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        pages.insert(page_id, page);
+    }
+    pages
+}
+
+/// Returns the IDs of the pages in `pages` that can't be reached by following links from
+/// `entrypoint`, sorted by ID.
+///
+/// Links are followed through their destinations, including random ones, and through the
+/// destinations set by their actions and triggers. Pages reached only by going back aren't
+/// counted, since going back can only return to a page that was reached some other way.
+pub fn find_unreachable(
+    entrypoint: &Rc<RefCell<Page>>,
+    pages: &HashMap<PageID, Rc<RefCell<Page>>>,
+) -> Vec<PageID> {
+    let reachable = reachable_pages(entrypoint);
+    let mut unreachable: Vec<PageID> = pages
+        .keys()
+        .filter(|page_id| !reachable.contains_key(*page_id))
+        .cloned()
+        .collect();
+    unreachable.sort();
+    unreachable
+}
+
+/// Returns all of the actions that following `link` can run, from the link itself and from its
+/// triggers.
+fn link_actions(link: &Link) -> impl Iterator<Item = &LinkAction> {
+    link.actions.iter().chain(
+        link.triggers
+            .iter()
+            .flat_map(|trigger| trigger.actions.iter().chain(trigger.else_actions.iter())),
+    )
+}
+
+/// Parses the pages in the given story file `sources`, in parallel if there are at least
+/// `parallel_min_files` of them.
+fn read_pages(
+    settings: &Settings,
+    sources: &[(PathBuf, String)],
+    parallel_min_files: usize,
+) -> Result<HashMap<String, Rc<RefCell<Page>>>> {
+    let one_page_per_file = settings.one_page_per_file();
+    let parsed_files = map_parallel(sources, parallel_min_files, |(_, content)| {
+        parse_story_file(content, one_page_per_file)
+    });
+
+    // Finish each page, collecting every duplicate page ID to report at once.
+    let mut pages = HashMap::new();
+    let mut page_paths: HashMap<PageID, &Path> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for ((path, _), parsed_file) in sources.iter().zip(parsed_files) {
+        let parsed_file = parsed_file.map_err(|e| Error::parse_error(Doctype::Story, path, e))?;
+        let front_matter = match &parsed_file.front_matter {
+            Some(value) => FrontMatter::parse(path, value)?,
+            None => None,
+        };
+        for (index, page) in parsed_file.pages {
+            let page = finish_page(settings, path, index, page, front_matter.as_ref())?;
+            if let Some(first_path) = page_paths.get(&page.id) {
+                duplicates.push(Error::duplicate_page_id(&page.id, first_path, path));
                 continue;
             }
+            page_paths.insert(page.id.clone(), path);
+            pages.insert(page.id.clone(), Rc::new(RefCell::new(page)));
         }
-        let content = fs::read_to_string(&path)?;
-        sources.push((path, content));
     }
 
-    // Parse content into one or more pages from each source file.
-    let parsed_pages = sources
-        .iter()
-        .flat_map(|(path, content)| {
-            RE_DOCUMENT_SEP.split(content).filter_map(move |s| {
-                if s.trim().is_empty() {
-                    return None;
-                }
-                Some(
-                    serde_yaml::from_str::<Page>(s)
-                        .map_err(|e| Error::parse_error(Doctype::Story, path, e)),
-                )
-            })
-        })
-        .collect::<Result<Vec<Page>>>()?;
+    if duplicates.len() == 1 {
+        return Err(duplicates.remove(0));
+    }
+    if let Some(err) = Error::errors(duplicates) {
+        return Err(err);
+    }
+    Ok(pages)
+}
+
+/// The minimum number of story files for which they're read and parsed in parallel. For fewer
+/// files, the overhead of spawning threads outweighs the gains (see `benches/parse.rs`).
+const PARALLEL_MIN_FILES: usize = 16;
+
+/// Returns the minimum number of story files to read and parse in parallel: never with a single
+/// CPU, where threads only add overhead, and otherwise [`PARALLEL_MIN_FILES`].
+fn parallel_min_files() -> usize {
+    match thread::available_parallelism() {
+        Ok(n) if n.get() > 1 => PARALLEL_MIN_FILES,
+        _ => usize::MAX,
+    }
+}
+
+/// The pages of a story file, each with its index among the documents in the file, and the file's
+/// front-matter, if any, as a YAML value.
+struct ParsedFile {
+    front_matter: Option<serde_yaml::Value>,
+    pages: Vec<(usize, Page)>,
+}
+
+// SAFETY: `Page` isn't `Send` because its links can hold `Rc`s to other pages and its `parents`
+// are `Weak`s. Pages fresh from deserialization hold neither: their links refer to other pages
+// only by ID, and `parents` isn't deserialized and so is empty. They can therefore be moved to the
+// thread that finishes them, which is the only one that ever links them together.
+unsafe impl Send for ParsedFile {}
+
+/// Parses the `content` of a story file into pages, stopping at the first page that fails to
+/// parse. If `one_page_per_file` isn't set, the first document may be front-matter instead.
+fn parse_story_file(
+    content: &str,
+    one_page_per_file: bool,
+) -> std::result::Result<ParsedFile, serde_yaml::Error> {
+    let mut parsed_file = ParsedFile {
+        front_matter: None,
+        pages: Vec::new(),
+    };
+    for (index, document) in split_documents(content, one_page_per_file) {
+        match serde_yaml::from_str::<Page>(document) {
+            Ok(page) => parsed_file.pages.push((index, page)),
+            // Front-matter is never a valid page, so only look for it if the first page fails.
+            Err(err) if index == 0 && !one_page_per_file => {
+                let value = serde_yaml::from_str(document)?;
+                if !FrontMatter::is_front_matter(&value) {
+                    return Err(err);
+                }
+                parsed_file.front_matter = Some(value);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(parsed_file)
+}
+
+/// Runs `f` on each of the story file `items` (e.g. paths or sources), in parallel if there are
+/// at least `min_items`, returning the results in the same order as `items`.
+fn map_parallel<T, U, F>(items: &[T], min_items: usize, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    if items.len() < min_items {
+        return items.iter().map(f).collect();
+    }
+    let num_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = items.len().div_ceil(num_threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Reads the path and content of every story file in [`Settings.base_dir`], sorted by path.
+///
+/// If [`Settings::page_ids_from_paths`] is set, story files in subdirectories (other than hidden
+/// ones) are read as well.
+fn read_sources(settings: &Settings) -> Result<Vec<(PathBuf, String)>> {
+    let config_path = settings.source();
+
+    let mut paths = Vec::new();
+    let mut dirs = vec![settings.base_dir().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            // Subdirectories are only read if page IDs can be derived from them.
+            if path.is_dir() && settings.page_ids_from_paths() {
+                let is_hidden = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with('.'));
+                if !is_hidden {
+                    dirs.push(path);
+                }
+                continue;
+            }
+            if !path.is_file() {
+                continue;
+            }
+            if let Some(cfg_path) = config_path {
+                if let Ok(true) = is_same_file(&path, cfg_path) {
+                    continue;
+                }
+            }
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Err(Error::no_story_files(settings.base_dir()));
+    }
+    // Sort by path so that errors are reported in the same order every time.
+    paths.sort();
 
-    // Convert pages Vec to a HashMap.
-    let pages = parsed_pages
+    let contents = map_parallel(&paths, parallel_min_files(), |path: &PathBuf| {
+        fs::read_to_string(path)
+    });
+    paths
         .into_iter()
-        .map(|p: Page| (p.id.to_owned(), Rc::new(RefCell::new(p))))
-        .collect();
+        .zip(contents)
+        .map(|(path, content)| Ok((path, content?)))
+        .collect()
+}
 
-    Ok(pages)
+/// Splits the `content` of a story file into YAML documents, each paired with its index among
+/// the (non-empty) documents in the file.
+///
+/// Like YAML itself, this only treats `---` and `...` lines as document markers when they're
+/// unindented, so a horizontal rule inside a page's (necessarily indented) `content` block is
+/// left alone. If `one_page_per_file` is set, the content isn't split at all.
+fn split_documents(content: &str, one_page_per_file: bool) -> impl Iterator<Item = (usize, &str)> {
+    let documents = if one_page_per_file {
+        Left(iter::once(content))
+    } else {
+        Right(RE_DOCUMENT_SEP.split(content))
+    };
+    documents.filter(|s| !s.trim().is_empty()).enumerate()
+}
+
+/// Parses a single YAML document from the story file at `path` into a [`Page`], applying the
+/// file's `front_matter` if it has any.
+fn parse_page(
+    settings: &Settings,
+    path: &Path,
+    index: usize,
+    document: &str,
+    front_matter: Option<&FrontMatter>,
+) -> Result<Page> {
+    let page = serde_yaml::from_str::<Page>(document)
+        .map_err(|e| Error::parse_error(Doctype::Story, path, e))?;
+    finish_page(settings, path, index, page, front_matter)
+}
+
+/// Fills in the parts of a [`Page`] that come from its story file rather than its own document.
+fn finish_page(
+    settings: &Settings,
+    path: &Path,
+    index: usize,
+    mut page: Page,
+    front_matter: Option<&FrontMatter>,
+) -> Result<Page> {
+    page.id = page_id_or_default(settings, path, page.id)?;
+    page.source = Some(PageSource {
+        path: path.to_path_buf(),
+        index,
+    });
+    if let Some(front_matter) = front_matter {
+        front_matter.apply(&mut page);
+    }
+    // Add the links from included templates, which are cleaned along with the page's own links.
+    for name in page.include_links.iter() {
+        let links = settings
+            .templates()
+            .get(name)
+            .ok_or_else(|| Error::undeclared_template(name))?;
+        page.links.extend(links.iter().cloned());
+    }
+    Ok(page)
+}
+
+/// Returns the given page `id`, or if it's empty (i.e. the page doesn't declare an ID), an ID
+/// derived from the `path` of the page's story file if [`Settings::page_ids_from_paths`] is set.
+fn page_id_or_default(settings: &Settings, path: &Path, id: PageID) -> Result<PageID> {
+    if !id.is_empty() {
+        return Ok(id);
+    }
+    if settings.page_ids_from_paths() {
+        if let Some(id) = page_id_from_path(settings.base_dir(), path) {
+            return Ok(id);
+        }
+    }
+    Err(Error::parse_error(
+        Doctype::Story,
+        path,
+        de::Error::missing_field("id"),
+    ))
+}
+
+/// Derives a page ID from the `path` of a story file: the path relative to `base_dir`, without
+/// its file extension, and with directories separated by `/`.
+///
+/// For example, the page in `<base_dir>/chapter1/intro.yaml` has the ID `chapter1/intro`.
+pub fn page_id_from_path(base_dir: &Path, path: &Path) -> Option<PageID> {
+    let path = path.strip_prefix(base_dir).ok()?.with_extension("");
+    let parts = path
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+/// Resolves the ID of a page linked to from the page `from_page_id`.
+///
+/// IDs starting with `./` or `../` are relative to the directory of `from_page_id` (the part
+/// before its last `/`), so `../chapter2/start` links from `chapter1/end` to `chapter2/start`.
+/// Other IDs are returned as-is. Returns [`None`](Option::None) if a relative ID goes above
+/// [`Settings.base_dir`].
+pub(crate) fn resolve_page_id(from_page_id: &str, to_page_id: &str) -> Option<PageID> {
+    if !(to_page_id.starts_with("./") || to_page_id.starts_with("../")) {
+        return Some(to_page_id.to_owned());
+    }
+    let mut parts: Vec<_> = from_page_id.split('/').collect();
+    parts.pop();
+    for part in to_page_id.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const MULTI_PAGE_FILE: &str = r#"
+id: intro
+content: "The beginning."
+links:
+  - text: "Next"
+    dest: { page: middle }
+---
+id: middle
+content: "The middle."
+"#;
+
+    fn entrypoint_of(entrypoint: &str) -> Result<PageID> {
+        let settings = testing::read_settings(
+            &format!("entrypoint: {:?}\npages: [intro, middle]", entrypoint),
+            &[("chapter.yaml", MULTI_PAGE_FILE)],
+        );
+        parse_story(&settings).map(|(page_id, ..)| page_id)
+    }
+
+    #[test]
+    fn entrypoint_file_with_several_pages_starts_at_first_page() {
+        assert_eq!(entrypoint_of("chapter.yaml").unwrap(), "intro");
+    }
+
+    #[test]
+    fn entrypoint_page_id_in_multi_page_file() {
+        assert_eq!(entrypoint_of("middle").unwrap(), "middle");
+    }
+
+    #[test]
+    fn missing_entrypoint_is_an_error() {
+        let err = entrypoint_of("epilogue.yaml").unwrap_err();
+        assert!(matches!(err, Error::MissingEntrypoint(_)), "{}", err);
+    }
+
+    const CONDITIONS: &str = r#"
+entrypoint: story.yaml
+pages: [start]
+variables:
+  brave: true
+  name: Arthur
+  gold: 5
+  weight: 1.5
+"#;
+
+    /// Parses a story with a link that requires `requires`, given in YAML.
+    fn parse_requires(requires: &str) -> Result<PageMap> {
+        let story = format!(
+            r#"
+id: start
+content: "Start."
+links:
+  - text: "Go"
+    dest: {{ page: start }}
+    requires: {}
+"#,
+            requires
+        );
+        let settings = testing::read_settings(CONDITIONS, &[("story.yaml", &story)]);
+        parse_pages(&settings)
+    }
+
+    /// Returns the error that `err` was ultimately caused by, outside of any links.
+    fn innermost(err: &Error) -> &Error {
+        match err {
+            Error::InLink { error, .. } => innermost(error),
+            Error::Errors(errors) => innermost(&errors.head),
+            err => err,
+        }
+    }
+
+    #[test]
+    fn equality_with_mistyped_value_is_an_error() {
+        let conditions = [
+            ("\"brave == knight\"", VarType::Bool),
+            ("\"brave != 3\"", VarType::Bool),
+            ("\"name == 5\"", VarType::Str),
+            ("\"name != true\"", VarType::Str),
+            ("\"gold == true\"", VarType::Num),
+            ("\"gold != many\"", VarType::Num),
+            ("\"weight == heavy\"", VarType::Float),
+            (
+                "{ op: { name: brave, op: \"==\", value: yes please } }",
+                VarType::Bool,
+            ),
+            ("{ op: [name, \"!=\", 1] }", VarType::Str),
+        ];
+        for (requires, var_type) in conditions.iter() {
+            let err = parse_requires(requires).err().unwrap();
+            match innermost(&err) {
+                Error::BadValueType { expected, .. } => {
+                    assert_eq!(expected, var_type, "{}", requires)
+                }
+                err => panic!("{}: expected a bad value type, got: {}", requires, err),
+            }
+        }
+    }
+
+    #[test]
+    fn equality_with_mistyped_variable_is_an_error() {
+        let conditions = [
+            ("\"gold == name\"", "name", VarType::Num),
+            ("\"brave != gold\"", "gold", VarType::Bool),
+            ("\"name == brave\"", "brave", VarType::Str),
+        ];
+        for (requires, name, var_type) in conditions.iter() {
+            let err = parse_requires(requires).err().unwrap();
+            match innermost(&err) {
+                Error::BadVariableType {
+                    var_name, expected, ..
+                } => {
+                    assert_eq!(var_name, name, "{}", requires);
+                    assert_eq!(expected, var_type, "{}", requires);
+                }
+                err => panic!("{}: expected a bad variable type, got: {}", requires, err),
+            }
+        }
+    }
+
+    #[test]
+    fn equality_with_matching_types_is_ok() {
+        let conditions = [
+            "\"brave == false\"",
+            "\"name != Lancelot\"",
+            "\"gold == 5\"",
+            "\"weight == 2\"",
+            "\"gold != weight\"",
+        ];
+        for requires in conditions.iter() {
+            if let Err(err) = parse_requires(requires) {
+                panic!("{}: {}", requires, err);
+            }
+        }
+    }
+
+    const HORIZONTAL_RULE: &str = r#"
+id: intro
+content: |
+  The beginning.
+
+  ---
+
+  The end...
+  ...
+links:
+  - text: "Next"
+    dest: { page: middle }
+---
+id: middle
+content: "The middle."
+"#;
+
+    #[test]
+    fn separators_inside_content_dont_split_documents() {
+        let documents: Vec<_> = split_documents(HORIZONTAL_RULE, false).collect();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].0, 0);
+        assert!(documents[0].1.contains("  ---\n"));
+        assert!(documents[1].1.contains("id: middle"));
+    }
+
+    #[test]
+    fn horizontal_rule_is_kept_in_page_content() {
+        let settings = testing::read_settings(
+            "entrypoint: story.yaml\npages: [intro, middle]",
+            &[("story.yaml", HORIZONTAL_RULE)],
+        );
+        let pages = parse_pages(&settings).unwrap();
+        assert_eq!(
+            pages["intro"].borrow().content,
+            "The beginning.\n\n---\n\nThe end...\n...\n"
+        );
+        assert_eq!(pages["middle"].borrow().content, "The middle.");
+    }
+
+    #[test]
+    fn one_page_per_file_doesnt_split_documents() {
+        let content = "id: intro\ncontent: Start.\n---\n";
+        assert_eq!(split_documents(content, true).count(), 1);
+        assert_eq!(split_documents(content, false).count(), 1);
+        assert_eq!(split_documents(HORIZONTAL_RULE, true).count(), 1);
+    }
+
+    #[test]
+    fn one_page_per_file_parses_each_file_as_a_page() {
+        let settings = testing::read_settings(
+            "entrypoint: intro.yaml\npages: [intro, middle]\none_page_per_file: true",
+            &[
+                (
+                    "intro.yaml",
+                    "id: intro\ncontent: Start.\nlinks:\n  - { text: Next, dest: { page: middle } }\n",
+                ),
+                ("middle.yaml", "---\nid: middle\ncontent: Middle.\n"),
+            ],
+        );
+        let pages = parse_pages(&settings).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages["middle"].borrow().content, "Middle.");
+    }
+
+    #[test]
+    fn base_dir_with_only_settings_file_is_an_error() {
+        let settings = testing::read_settings("entrypoint: start.yaml\npages: [start]", &[]);
+        let err = parse_story(&settings).unwrap_err();
+        assert!(
+            matches!(&err, Error::NoStoryFiles(dir) if dir == settings.base_dir()),
+            "{}",
+            err
+        );
+    }
+
+    /// Parses a story whose page `start` has a link, second on the page, with the destination
+    /// `dest` and a trigger that sets the destination to `trigger_dest`.
+    fn parse_broken_link(dest: &str, trigger_dest: &str) -> Error {
+        let story = format!(
+            r#"
+id: start
+content: "Start."
+links:
+  - text: "Stay"
+    dest: {{ page: start }}
+  - text: "Into the void"
+    dest: {{ page: {} }}
+    triggers:
+      - condition: "lost == true"
+        actions:
+          - set-dest: {{ page: {} }}
+"#,
+            dest, trigger_dest
+        );
+        let settings = testing::read_settings(
+            "entrypoint: story.yaml\npages: [start]\nvariables: { lost: true }",
+            &[("story.yaml", &story)],
+        );
+        parse_pages(&settings).unwrap_err()
+    }
+
+    fn assert_broken_link(err: &Error) {
+        match innermost(err) {
+            Error::BrokenLink {
+                path,
+                page_id,
+                link_idx,
+                link_text,
+                dest,
+            } => {
+                assert!(path.as_ref().unwrap().ends_with("story.yaml"), "{:?}", path);
+                assert_eq!(page_id, "start");
+                assert_eq!(*link_idx, 1);
+                assert_eq!(link_text, "Into the void");
+                assert_eq!(dest, "nowhere");
+            }
+            err => panic!("expected a broken link, got: {}", err),
+        }
+    }
+
+    #[test]
+    fn link_to_undeclared_page_is_a_broken_link() {
+        let err = parse_broken_link("nowhere", "start");
+        assert_broken_link(&err);
+        assert!(err.to_string().contains("\"Into the void\""), "{}", err);
+    }
+
+    #[test]
+    fn set_dest_to_undeclared_page_is_a_broken_link() {
+        assert_broken_link(&parse_broken_link("start", "nowhere"));
+    }
+
+    /// Writes a story with `count` files, `file00.yaml` and so on, each with front-matter and two
+    /// pages, and with `broken` substituted into the files whose numbers are in `broken_files`.
+    fn many_files(count: usize, broken: &str, broken_files: &[usize]) -> Settings {
+        let files: Vec<_> = (0..count)
+            .map(|i| {
+                let content = format!(
+                    r#"
+author: "Author {i}"
+---
+id: page{i}a
+content: "Page {i}a."
+links:
+  - text: "Next"
+    dest: {{ page: page{i}b }}
+---
+id: page{i}b
+content: "Page {i}b."
+{extra}
+"#,
+                    i = i,
+                    extra = if broken_files.contains(&i) {
+                        broken
+                    } else {
+                        ""
+                    },
+                );
+                (format!("file{:02}.yaml", i), content)
+            })
+            .collect();
+        let page_ids: Vec<_> = (0..count)
+            .flat_map(|i| vec![format!("page{}a", i), format!("page{}b", i)])
+            .collect();
+        let files: Vec<_> = files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str()))
+            .collect();
+        testing::read_settings(
+            &format!("entrypoint: file00.yaml\npages: [{}]", page_ids.join(", ")),
+            &files,
+        )
+    }
+
+    /// Parses the pages of `settings` serially and in parallel.
+    fn read_pages_both_ways(settings: &Settings) -> [Result<PageMap>; 2] {
+        let sources = read_sources(settings).unwrap();
+        [
+            read_pages(settings, &sources, usize::MAX),
+            read_pages(settings, &sources, 1),
+        ]
+    }
+
+    #[test]
+    fn parallel_parsing_gives_same_pages() {
+        let settings = many_files(PARALLEL_MIN_FILES + 4, "", &[]);
+        let [serial, parallel] = read_pages_both_ways(&settings);
+        let (serial, parallel) = (serial.unwrap(), parallel.unwrap());
+        assert_eq!(serial.len(), (PARALLEL_MIN_FILES + 4) * 2);
+        assert_eq!(parallel.len(), serial.len());
+        for (page_id, page) in serial.iter() {
+            let (page, other) = (page.borrow(), parallel[page_id].borrow());
+            assert_eq!(page.content, other.content);
+            assert_eq!(page.author, other.author);
+            assert_eq!(page.source, other.source);
+        }
+
+        let page = serial["page7b"].borrow();
+        assert_eq!(page.author.as_deref(), Some("Author 7"));
+        assert!(page.source.as_ref().unwrap().path.ends_with("file07.yaml"));
+        // Front-matter counts as the first document in the file.
+        assert_eq!(page.source.as_ref().unwrap().index, 2);
+    }
+
+    #[test]
+    fn parallel_parsing_reports_first_error_by_path() {
+        let settings = many_files(PARALLEL_MIN_FILES + 4, "unknown_field: true", &[13, 7]);
+        for result in read_pages_both_ways(&settings).iter() {
+            match result {
+                Err(Error::ParseError { path, .. }) => assert!(path.ends_with("file07.yaml")),
+                Err(err) => panic!("expected a parse error, got: {}", err),
+                Ok(_) => panic!("expected a parse error"),
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_parsing_reports_every_duplicate_page_id() {
+        let settings = many_files(
+            PARALLEL_MIN_FILES + 4,
+            "---\nid: page0a\ncontent: \"Again.\"",
+            &[3, 9],
+        );
+        for result in read_pages_both_ways(&settings).iter() {
+            match result {
+                Err(Error::Errors(errors)) => {
+                    let paths: Vec<_> = errors
+                        .iter()
+                        .map(|err| match err.as_ref() {
+                            Error::DuplicatePageID { path, .. } => path.clone(),
+                            err => panic!("expected a duplicate page ID, got: {}", err),
+                        })
+                        .collect();
+                    assert_eq!(paths.len(), 2);
+                    assert!(paths[0].ends_with("file03.yaml"));
+                    assert!(paths[1].ends_with("file09.yaml"));
+                }
+                Err(err) => panic!("expected duplicate page IDs, got: {}", err),
+                Ok(_) => panic!("expected duplicate page IDs"),
+            }
+        }
+    }
 }
@@ -0,0 +1,79 @@
+use std::fmt;
+
+use crate::errors::Error;
+use crate::types::PageID;
+
+/// The result of checking a storygame with [`analyze`](super::analyze).
+///
+/// Errors prevent the story from being played, while warnings point out likely mistakes that
+/// don't.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<Error>,
+    pub warnings: Vec<Warning>,
+}
+
+impl ValidationReport {
+    /// Returns true if the report has neither errors nor warnings.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty() && self.warnings.is_empty()
+    }
+
+    /// Returns true if the story can be played, i.e. if there are no errors.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// A likely mistake in a storygame that doesn't prevent it from being played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// The page where the mistake was found, if it's in a page.
+    pub page_id: Option<PageID>,
+    /// The index of the link on `page_id` where the mistake was found, if it's in a link.
+    pub link_idx: Option<usize>,
+}
+
+/// The kind of mistake that a [`Warning`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// A page with blank content.
+    EmptyContent,
+    /// A link back to the same page that has no actions or triggers.
+    NoOpSelfLink,
+    /// A page that no chain of links from the entrypoint leads to.
+    Unreachable,
+}
+
+impl Warning {
+    /// Creates a warning about the page `page_id`.
+    pub fn in_page<S: Into<String>>(kind: WarningKind, page_id: &str, message: S) -> Self {
+        Warning {
+            kind,
+            message: message.into(),
+            page_id: Some(page_id.to_owned()),
+            link_idx: None,
+        }
+    }
+
+    /// Creates a warning about the link at index `link_idx` on the page `page_id`.
+    pub fn in_link<S: Into<String>>(
+        kind: WarningKind,
+        page_id: &str,
+        link_idx: usize,
+        message: S,
+    ) -> Self {
+        Warning {
+            link_idx: Some(link_idx),
+            ..Warning::in_page(kind, page_id, message)
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
@@ -1,26 +1,70 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs;
+use std::num::NonZeroU64;
 use std::path::{Path, PathBuf};
 
+use cursive::theme::Color;
 use log::LevelFilter;
 use serde::de;
 use serde::Deserialize;
 
 use crate::errors::{Doctype, Error};
-use crate::types::{item, ItemDef, Variable};
+use crate::types::{
+    item, CreationStep, Expr, InventoryOverflow, ItemDef, LimitMode, Link, TimerDef, VariableDef,
+    Version,
+};
 use crate::utils::shorten_path;
 
 use super::PageID;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct LoggingSettings {
+    /// Whether to write logs to a file.
+    #[serde(default = "default_logging_enabled")]
+    pub enabled: bool,
+    /// The directory to write log files to.
+    #[serde(default)]
+    pub dest_dir: Option<PathBuf>,
     #[serde(default)]
     pub base_file_name: Option<String>,
     #[serde(default)]
     pub file_ext: Option<String>,
     #[serde(default)]
     pub level: Option<LevelFilter>,
+    /// The size in bytes at which the log file is archived and a new one is started.
+    #[serde(default)]
+    pub max_file_size: Option<NonZeroU64>,
+    /// The number of archived log files to keep.
+    #[serde(default)]
+    pub max_archived_files: Option<u32>,
+    /// Whether to start a new log file (archiving the previous one) each time the story is
+    /// loaded.
+    #[serde(default)]
+    pub new_file_per_session: bool,
+}
+
+/// How choices whose `requires` condition isn't met are shown to the player.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LockedChoiceDisplay {
+    /// Locked choices aren't shown.
+    #[default]
+    #[serde(rename = "hide")]
+    Hide,
+    /// Locked choices are shown dimmed, and can't be chosen.
+    #[serde(rename = "disable")]
+    Disable,
+    /// Like `Disable`, but each locked choice also says what it requires.
+    #[serde(rename = "disable-with-reason")]
+    DisableWithReason,
+}
+
+/// Descriptive information about a storygame, for display to the player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: String,
+    pub author: Option<String>,
+    pub version: Option<Version>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -29,13 +73,57 @@ pub struct Settings {
     #[serde(skip)]
     source: Option<PathBuf>,
     title: String,
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    version: Option<Version>,
     base_dir: PathBuf,
     entrypoint: PathBuf,
     pages: HashSet<PageID>,
     #[serde(default)]
-    variables: HashMap<String, Variable>,
+    variables: HashMap<String, VariableDef>,
+    #[serde(default)]
+    timers: HashMap<String, TimerDef>,
     #[serde(default, deserialize_with = "deserialize_item_defs")]
     items: HashMap<String, ItemDef>,
+    #[serde(default, deserialize_with = "deserialize_limit")]
+    inventory_limit: Option<usize>,
+    #[serde(default)]
+    limit_mode: LimitMode,
+    #[serde(default)]
+    inventory_overflow: InventoryOverflow,
+    #[serde(default)]
+    helpers: HashMap<String, Expr>,
+    #[serde(default)]
+    templates: HashMap<String, Vec<Link>>,
+    #[serde(default)]
+    character_creation: Vec<CreationStep>,
+    #[serde(default, deserialize_with = "deserialize_tag_colors")]
+    tag_colors: HashMap<String, String>,
+    #[serde(default = "default_autoscroll")]
+    autoscroll: bool,
+    #[serde(default = "default_show_link_dest")]
+    show_link_dest: bool,
+    #[serde(default)]
+    batch_prompts: bool,
+    #[serde(default)]
+    locked_choice_display: LockedChoiceDisplay,
+    #[serde(default = "default_author_mode")]
+    author_mode: bool,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_limit")]
+    history_limit: Option<usize>,
+    #[serde(default)]
+    lazy: bool,
+    #[serde(default)]
+    cache: bool,
+    #[serde(default)]
+    page_ids_from_paths: bool,
+    #[serde(default)]
+    one_page_per_file: bool,
+    #[serde(default)]
+    strict_reachability: bool,
     logger: LoggingSettings,
 }
 
@@ -85,6 +173,54 @@ where
     deserializer.deserialize_map(ItemDefsVisitor)
 }
 
+fn deserialize_limit<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let limit = Option::<usize>::deserialize(deserializer)?;
+    // A limit of zero would leave no room at all, e.g. for items or history.
+    if limit == Some(0) {
+        return Err(de::Error::invalid_value(
+            de::Unexpected::Unsigned(0),
+            &"a positive integer",
+        ));
+    }
+    Ok(limit)
+}
+
+fn deserialize_tag_colors<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let colors = HashMap::<String, String>::deserialize(deserializer)?;
+    // Ensure each color is one that can be displayed.
+    for color in colors.values() {
+        if Color::parse(color).is_none() {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(color),
+                &"a color name (e.g. \"red\", \"light blue\") or hex code (e.g. \"#ff0000\")",
+            ));
+        }
+    }
+    Ok(colors)
+}
+
+fn default_autoscroll() -> bool {
+    true
+}
+
+fn default_show_link_dest() -> bool {
+    cfg!(debug_assertions)
+}
+
+fn default_author_mode() -> bool {
+    cfg!(debug_assertions)
+}
+
+fn default_logging_enabled() -> bool {
+    true
+}
+
 const DEFAULT_SETTINGS_FILE_STEM: &str = "storygame";
 
 impl Settings {
@@ -148,6 +284,34 @@ impl Settings {
     pub fn title(&self) -> &str {
         self.title.as_str()
     }
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+    pub fn version(&self) -> Option<Version> {
+        self.version
+    }
+    /// Returns the path of the file that the player's progress is saved to, which is named after
+    /// the story's title (e.g. `My Story.save.yaml`) and kept next to the settings file.
+    pub fn save_path(&self) -> Option<PathBuf> {
+        let file_name: String = self
+            .title
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' => '_',
+                c => c,
+            })
+            .collect();
+        let dir = self.source()?.parent()?;
+        Some(dir.join(format!("{}.save.yaml", file_name)))
+    }
+    /// Returns the story's [`Metadata`].
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.title.clone(),
+            author: self.author.clone(),
+            version: self.version,
+        }
+    }
     pub fn base_dir(&self) -> &Path {
         self.base_dir.as_path()
     }
@@ -157,13 +321,179 @@ impl Settings {
     pub fn pages(&self) -> &HashSet<PageID> {
         &self.pages
     }
-    pub fn variables(&self) -> &HashMap<String, Variable> {
+    pub fn variables(&self) -> &HashMap<String, VariableDef> {
         &self.variables
     }
+    /// Returns the map of number variable names to the timers that count them down.
+    pub fn timers(&self) -> &HashMap<String, TimerDef> {
+        &self.timers
+    }
     pub fn items(&self) -> &HashMap<String, ItemDef> {
         &self.items
     }
+    /// Returns the most items the player can carry, if there's a limit.
+    pub fn inventory_limit(&self) -> Option<usize> {
+        self.inventory_limit
+    }
+    /// Returns whether the [`inventory_limit`](Self::inventory_limit) counts every item held or
+    /// only distinct kinds of items.
+    pub fn limit_mode(&self) -> LimitMode {
+        self.limit_mode
+    }
+    /// Returns what happens when acquiring an item would exceed the
+    /// [`inventory_limit`](Self::inventory_limit).
+    pub fn inventory_overflow(&self) -> InventoryOverflow {
+        self.inventory_overflow
+    }
+    /// Returns the map of template helper names to the expressions they compute.
+    pub fn helpers(&self) -> &HashMap<String, Expr> {
+        &self.helpers
+    }
+    /// Returns the map of link template names to the links they add to the pages that include
+    /// them with `include_links`.
+    pub fn templates(&self) -> &HashMap<String, Vec<Link>> {
+        &self.templates
+    }
+    pub fn character_creation(&self) -> &[CreationStep] {
+        &self.character_creation
+    }
+    /// Returns the map of page tags to the colors used for the content of pages with that tag.
+    pub fn tag_colors(&self) -> &HashMap<String, String> {
+        &self.tag_colors
+    }
+    /// Returns whether content appended to the current page should be scrolled into view.
+    pub fn autoscroll(&self) -> bool {
+        self.autoscroll
+    }
+    /// Returns whether each link in the list of choices shows where it leads (e.g. `↪ (#intro)`),
+    /// which is useful to authors testing the story's flow but exposes page IDs to players.
+    /// Defaults to true in debug builds only.
+    pub fn show_link_dest(&self) -> bool {
+        self.show_link_dest
+    }
+    /// Returns whether consecutive prompts that ask for variables are shown together, as a single
+    /// form, rather than one at a time.
+    pub fn batch_prompts(&self) -> bool {
+        self.batch_prompts
+    }
+    /// Returns how choices whose `requires` condition isn't met are shown, e.g. to tease the
+    /// player with choices they can't take yet.
+    pub fn locked_choice_display(&self) -> LockedChoiceDisplay {
+        self.locked_choice_display
+    }
+    /// Returns whether the story is being played by its author, in which case any warnings about
+    /// likely mistakes in the story are shown after it loads, with shortcuts to the pages they're
+    /// about. Defaults to true in debug builds only, so players of a finished story aren't shown
+    /// warnings they can't act on.
+    pub fn author_mode(&self) -> bool {
+        self.author_mode
+    }
+    /// Returns the seed for random numbers, if any. With a seed, the same choices always lead to
+    /// the same random outcomes (e.g. `roll` actions and random initial values), which is useful
+    /// for testing or for stories where every player should face the same luck.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+    /// Returns the most pages to remember for going back, if there's a limit, to bound the memory
+    /// used by very long stories. Links can't go back past the oldest page remembered.
+    pub fn history_limit(&self) -> Option<usize> {
+        self.history_limit
+    }
+    /// Returns whether pages should be parsed on demand rather than up front.
+    ///
+    /// See [`LazyPages`](super::LazyPages) for the tradeoffs.
+    pub fn lazy(&self) -> bool {
+        self.lazy
+    }
+    /// Returns whether parsed pages should be cached on disk, to speed up loading the story again
+    /// if it hasn't changed. The cache is kept in the app's cache directory (e.g.
+    /// `$XDG_CACHE_HOME/storygamer` on Linux) rather than next to the settings file, so that it
+    /// doesn't clutter the story's directory.
+    pub fn cache(&self) -> bool {
+        self.cache
+    }
+    /// Returns whether pages that don't declare an `id` get one derived from the path of their story
+    /// file relative to `base_dir` (e.g. `chapter1/intro` for `chapter1/intro.yaml`), in which case
+    /// story files in subdirectories of `base_dir` are read as well. Links can refer to these
+    /// pages by ID, or relative to the linking page's directory (e.g. `../chapter2/start`).
+    pub fn page_ids_from_paths(&self) -> bool {
+        self.page_ids_from_paths
+    }
+    /// Returns whether each story file is exactly one page, in which case files aren't split into
+    /// YAML documents on `---` lines and can't have front-matter.
+    pub fn one_page_per_file(&self) -> bool {
+        self.one_page_per_file
+    }
+    /// Returns whether pages that can't be reached from the entrypoint are an error, rather than
+    /// only a warning.
+    ///
+    /// Pages are only an error if no `page-from-var` destination could lead to them; otherwise
+    /// they're still only a warning, since the pages a variable names aren't known until the story
+    /// is played.
+    pub fn strict_reachability(&self) -> bool {
+        self.strict_reachability
+    }
     pub fn logger(&self) -> &LoggingSettings {
         &self.logger
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn read_error(content: &str) -> Error {
+        let dir = testing::temp_dir(&[("Storygame.yaml", content)]);
+        Settings::read(dir.join("Storygame.yaml")).unwrap_err()
+    }
+
+    fn assert_located_parse_error(err: &Error, line: usize) {
+        let err = match err {
+            Error::Errors(errors) => errors.head.as_ref(),
+            err => err,
+        };
+        match err {
+            Error::ParseError {
+                doctype: Doctype::Settings,
+                path,
+                error,
+            } => {
+                assert!(path.ends_with("Storygame.yaml"), "{}", path.display());
+                assert_eq!(error.location().map(|loc| loc.line()), Some(line));
+            }
+            err => panic!("expected a settings parse error, got: {}", err),
+        }
+    }
+
+    #[test]
+    fn malformed_settings_file_is_a_located_parse_error() {
+        let err = read_error("title: Test\npages: [start\n");
+        assert_located_parse_error(&err, 3);
+        assert!(!err.to_string().contains("TOML"), "{}", err);
+    }
+
+    #[test]
+    fn mistyped_setting_is_a_located_parse_error() {
+        let err = read_error("title: Test\nbase_dir: .\nentrypoint: start\npages: 5\n");
+        assert_located_parse_error(&err, 4);
+    }
+
+    fn settings(extra: &str) -> Settings {
+        testing::read_settings(
+            &format!("entrypoint: start\npages: [start]\n{}", extra),
+            &[],
+        )
+    }
+
+    #[test]
+    fn link_dest_is_shown_by_default_in_debug_builds_only() {
+        assert_eq!(settings("").show_link_dest(), cfg!(debug_assertions));
+    }
+
+    #[test]
+    fn link_dest_can_be_shown_or_hidden() {
+        assert!(settings("show_link_dest: true").show_link_dest());
+        assert!(!settings("show_link_dest: false").show_link_dest());
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use regex::Regex;
+
+use crate::types::{Page, PageID};
+
+/// The number of words per minute assumed by [`StoryStats::reading_minutes`].
+pub const WORDS_PER_MINUTE: usize = 200;
+
+lazy_static! {
+    static ref RE_TEMPLATE_TAG: Regex = Regex::new(r"\{\{\{?[^}]*\}?\}\}").unwrap();
+    static ref RE_MARKDOWN_LINK: Regex = Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").unwrap();
+    static ref RE_MARKDOWN_SYNTAX: Regex = Regex::new(r"[*_`#>~|]").unwrap();
+}
+
+/// Statistics about the size of a storygame, from [`story_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoryStats {
+    /// The number of words in the content of every page, not counting Handlebars tags or Markdown
+    /// syntax.
+    pub words: usize,
+    pub pages: usize,
+    /// The number of links across every page.
+    pub choices: usize,
+}
+
+impl StoryStats {
+    /// Returns the estimated time in minutes to read every page, rounded up, at
+    /// [`WORDS_PER_MINUTE`].
+    pub fn reading_minutes(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE)
+    }
+}
+
+/// Returns the word, page, and choice counts of `pages`.
+pub fn story_stats(pages: &HashMap<PageID, Rc<RefCell<Page>>>) -> StoryStats {
+    let mut stats = StoryStats {
+        pages: pages.len(),
+        ..StoryStats::default()
+    };
+    for page in pages.values() {
+        let page = page.borrow();
+        stats.words += count_words(&page.content);
+        stats.choices += page.links.len();
+    }
+    stats
+}
+
+/// Counts the words in `content` after stripping Handlebars tags and Markdown syntax. Links and
+/// images count the words in their text.
+fn count_words(content: &str) -> usize {
+    let content = RE_TEMPLATE_TAG.replace_all(content, " ");
+    let content = RE_MARKDOWN_LINK.replace_all(&content, "$1");
+    let content = RE_MARKDOWN_SYNTAX.replace_all(&content, " ");
+    content
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::testing;
+
+    #[test]
+    fn word_count_skips_template_tags_and_markdown() {
+        assert_eq!(count_words("Hello, {{name}}!"), 1);
+        assert_eq!(count_words("{{#if brave}}Onward{{/if}} {{{title}}}"), 1);
+        assert_eq!(
+            count_words("# The *dark* `cave`\n\n> ---\n> _quiet_ - very"),
+            5
+        );
+        assert_eq!(
+            count_words("See [the map](map.png) or ![a ghost](ghost.png)."),
+            6
+        );
+        assert_eq!(count_words(""), 0);
+    }
+
+    #[test]
+    fn story_stats_counts_words_pages_and_choices() {
+        let settings = testing::read_settings(
+            "entrypoint: story.yaml\npages: [start, end]",
+            &[(
+                "story.yaml",
+                r#"
+id: start
+content: "**Welcome**, {{name}}. Where will you go today?"
+links:
+  - text: "Onward"
+    dest: { page: end }
+  - text: "Stay"
+    dest: { page: start }
+---
+id: end
+content: "The end."
+links:
+  - text: "Restart"
+    dest: start
+"#,
+            )],
+        );
+        let pages = parser::parse_pages(&settings).unwrap();
+        let stats = story_stats(&pages);
+        assert_eq!(
+            stats,
+            StoryStats {
+                words: 8,
+                pages: 2,
+                choices: 3,
+            }
+        );
+        assert_eq!(stats.reading_minutes(), 1);
+    }
+
+    #[test]
+    fn reading_minutes_rounds_up() {
+        let minutes = |words| {
+            StoryStats {
+                words,
+                ..StoryStats::default()
+            }
+            .reading_minutes()
+        };
+        assert_eq!(minutes(0), 0);
+        assert_eq!(minutes(1), 1);
+        assert_eq!(minutes(WORDS_PER_MINUTE), 1);
+        assert_eq!(minutes(WORDS_PER_MINUTE + 1), 2);
+    }
+}
@@ -0,0 +1,84 @@
+//! Helpers for tests that play or parse small stories written inline.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+use crate::app::Game;
+use crate::errors::Result;
+use crate::parser::{self, Settings};
+
+/// Writes `files`, given as paths relative to the directory and their content, to a new directory
+/// in the system's temporary directory, returning the directory's path.
+pub fn temp_dir(files: &[(&str, &str)]) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "{}-test-{}-{}",
+        progname!(),
+        process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// Points the app's cache directory at a temporary directory shared by every test, so that tests
+/// don't write to the user's cache, and returns the app's cache directory.
+pub fn temp_cache_dir() -> PathBuf {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| env::set_var("XDG_CACHE_HOME", temp_dir(&[])));
+    crate::utils::cache_dir()
+}
+
+/// Writes a story with the settings in `settings` and the story files in `files`, returning the
+/// path of its settings file, `Storygame.yaml`.
+///
+/// The `title`, `base_dir`, and `logger` settings are filled in, so `settings` should declare the
+/// `entrypoint`, the `pages`, and anything else the test needs.
+pub fn write_story_files(settings: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = temp_dir(files);
+    let settings = format!(
+        "title: Test\nbase_dir: {:?}\nlogger: {{ enabled: false }}\n{}",
+        dir, settings
+    );
+    let path = dir.join("Storygame.yaml");
+    fs::write(&path, settings).unwrap();
+    path
+}
+
+/// Writes a story like [`write_story_files`] whose only story file, and entrypoint, is
+/// `story.yaml` with the pages in `story`. The first page in `story` is the entrypoint.
+pub fn write_story(settings: &str, story: &str) -> PathBuf {
+    write_story_files(
+        &format!("entrypoint: story.yaml\n{}", settings),
+        &[("story.yaml", story)],
+    )
+}
+
+/// Writes a story like [`write_story_files`] and reads its settings.
+pub fn read_settings(settings: &str, files: &[(&str, &str)]) -> Settings {
+    Settings::read(write_story_files(settings, files)).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Writes a story like [`write_story`], then parses it and starts a game.
+pub fn load_game(settings: &str, story: &str) -> Result<Game> {
+    let settings = Settings::read(write_story(settings, story))?;
+    let (page_id, pages, _) = parser::parse_story(&settings)?;
+    let mut game = Game::from_settings(&settings, &pages[&page_id]);
+    game.pages = pages;
+    Ok(game)
+}
+
+/// Like [`load_game`], but panics if the story can't be loaded.
+pub fn game(settings: &str, story: &str) -> Game {
+    load_game(settings, story).unwrap_or_else(|e| panic!("{}", e))
+}
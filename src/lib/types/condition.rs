@@ -3,25 +3,214 @@ use std::str::FromStr;
 
 use regex::Regex;
 use serde::de::{self, IntoDeserializer, Visitor};
-use serde::Deserialize;
+use serde::ser;
+use serde::{Deserialize, Serialize};
 
 use super::variable::Variable;
+use super::PageID;
 
-#[derive(Deserialize, Debug, Clone)]
-#[serde(rename_all = "kebab-case")]
+/// A condition on the state of the game.
+///
+/// Besides the variants below, a condition can be written as just the name of a boolean
+/// variable, which is shorthand for `<name> == true`, or as an [`Operation`] in its string form
+/// (e.g. `gold >= price`), which is shorthand for `op: <operation>`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case", remote = "Self")]
 pub enum Condition {
     And(Vec<Condition>),
     Or(Vec<Condition>),
     Not(Box<Condition>),
     Op(Operation),
+    /// True if the named item is in the player's inventory. Items are removed from the
+    /// inventory as soon as they are depleted, so a held item always has uses left.
     HasItem(String),
+    /// True if the named item is not in the player's inventory. Shorthand for
+    /// `not: { has-item: <name> }`.
+    LacksItem(String),
+    /// Compares the uses left on the named item to a number. Items with unlimited uses are
+    /// treated as having `i32::MAX` uses left, and items not in the inventory as having `0`.
+    ItemUses(Operation),
+    /// True if the current page has the given tag.
+    CurrentPageTag(String),
+    /// True if the player has been to the page with the given ID, including if it's the current
+    /// page.
+    Visited(PageID),
+    /// Compares the number of times the player has entered the page `page` to `value`.
+    VisitCount {
+        page: PageID,
+        op: ComparisonOp,
+        value: i32,
+    },
 }
 
-#[derive(Debug, Clone)]
+impl Condition {
+    /// Returns true if the condition combines several others, and so needs parentheses when
+    /// nested in another condition.
+    fn is_compound(&self) -> bool {
+        matches!(self, Condition::And(conds) | Condition::Or(conds) if conds.len() > 1)
+    }
+}
+
+/// Describes the condition in words for the player, e.g. `has key and strength >= 10`. Nested
+/// conditions are parenthesized, e.g. `has key and (strength >= 10 or has crowbar)`.
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_joined(f: &mut fmt::Formatter, conds: &[Condition], sep: &str) -> fmt::Result {
+            for (i, cond) in conds.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(sep)?;
+                }
+                if cond.is_compound() {
+                    write!(f, "({})", cond)?;
+                } else {
+                    write!(f, "{}", cond)?;
+                }
+            }
+            Ok(())
+        }
+
+        match self {
+            Condition::And(conds) => fmt_joined(f, conds, " and "),
+            Condition::Or(conds) => fmt_joined(f, conds, " or "),
+            Condition::Not(cond) => match cond.as_ref() {
+                Condition::HasItem(name) => write!(f, "doesn't have {}", name),
+                Condition::LacksItem(name) => write!(f, "has {}", name),
+                cond => write!(f, "not ({})", cond),
+            },
+            // Write the shorthand for checking a boolean variable, e.g. `is_drunk`.
+            Condition::Op(Operation {
+                name,
+                op: ComparisonOp::EQ,
+                value: Variable::Bool(true),
+                value_ref: None,
+                modulo: None,
+            }) => f.write_str(name),
+            Condition::Op(operation) => write!(f, "{}", operation),
+            Condition::HasItem(name) => write!(f, "has {}", name),
+            Condition::LacksItem(name) => write!(f, "doesn't have {}", name),
+            Condition::ItemUses(operation) => write!(f, "uses of {}", operation),
+            Condition::CurrentPageTag(tag) => write!(f, "on a page tagged {}", tag),
+            Condition::Visited(page_id) => write!(f, "has visited {}", page_id),
+            Condition::VisitCount { page, op, value } => {
+                write!(f, "visits to {} {} {}", page, op, value)
+            }
+        }
+    }
+}
+
+impl Serialize for Condition {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        Condition::serialize(self, serializer)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Condition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct ConditionVisitor;
+
+        impl<'de> Visitor<'de> for ConditionVisitor {
+            type Value = Condition;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a condition, a comparison, or the name of a boolean variable")
+            }
+
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                if s.trim().contains(char::is_whitespace) {
+                    return Operation::deserialize(s.into_deserializer()).map(Condition::Op);
+                }
+                if s.is_empty() {
+                    return Err(de::Error::invalid_value(
+                        de::Unexpected::Str(s),
+                        &"the name of a boolean variable",
+                    ));
+                }
+                Ok(Condition::Op(Operation {
+                    name: s.to_owned(),
+                    op: ComparisonOp::EQ,
+                    value: Variable::Bool(true),
+                    value_ref: None,
+                    modulo: None,
+                }))
+            }
+
+            fn visit_map<V>(self, map: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::MapAccess<'de>,
+            {
+                Condition::deserialize(de::value::MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(ConditionVisitor)
+    }
+}
+
+/// A comparison of the variable `name` to a value.
+///
+/// In the string form (`<name> <op> <value>`), a bare `value` that isn't a number or boolean and
+/// names a declared variable (e.g. `gold >= price`) compares against that variable's value
+/// instead. Quote the value (e.g. `name == "price"`) to compare against the literal string.
+///
+/// A number variable can also be taken modulo a positive number before comparing, e.g.
+/// `hour % 2 == 0`.
+///
+/// Unless the story declares a variable with the same name, the name [`TURN_COUNT`] refers to the
+/// number of pages the player has entered, e.g. `turn % 2 == 0` is true on every other page.
+#[derive(Serialize, Debug, Clone)]
 pub struct Operation {
     pub name: String,
     pub op: ComparisonOp,
     pub value: Variable,
+    /// The name of the variable that `value` may refer to. Cleared during parsing if there's no
+    /// such variable, in which case `value` is compared as a literal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_ref: Option<String>,
+    /// If given, the variable's value is taken modulo this number before it's compared. Must be
+    /// positive, which is checked during parsing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modulo: Option<i32>,
+}
+
+/// The name of the built-in turn count in an [`Operation`]: the number of pages the player has
+/// entered, starting at 1 on the starting page.
+pub const TURN_COUNT: &str = "turn";
+
+impl Operation {
+    /// Returns the left-hand side of the comparison for the variable value `lhs`, i.e. `lhs`
+    /// modulo [`modulo`](Self::modulo) if this operation has one and `lhs` is a number.
+    ///
+    /// Returns `None` if [`modulo`](Self::modulo) isn't positive.
+    pub fn lhs(&self, lhs: &Variable) -> Option<Variable> {
+        match (self.modulo, lhs) {
+            (Some(modulo), _) if modulo <= 0 => None,
+            (Some(modulo), Variable::Num(n)) => Some(Variable::Num(n.rem_euclid(modulo))),
+            _ => Some(lhs.clone()),
+        }
+    }
+}
+
+/// Writes the comparison in its string form, e.g. `gold >= price` or `hour % 2 == 0`.
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if let Some(modulo) = self.modulo {
+            write!(f, " % {}", modulo)?;
+        }
+        match &self.value_ref {
+            Some(value_ref) => write!(f, " {} {}", self.op, value_ref),
+            None => write!(f, " {} {}", self.op, self.value),
+        }
+    }
 }
 
 impl<'de> de::Deserialize<'de> for Operation {
@@ -29,14 +218,19 @@ impl<'de> de::Deserialize<'de> for Operation {
     where
         D: de::Deserializer<'de>,
     {
-        const FIELDS: &[&str] = &["name", "op", "value"];
+        const FIELDS: &[&str] = &["name", "op", "value", "value_ref", "modulo"];
         lazy_static! {
             static ref RE_CONDITION: Regex = Regex::new(
                 r#"(?x)
                     ^ \s*
-                    (?P<name> \S+)
+                    (?P<name> [^\s%]+)
+                    # An optional modulus, e.g. `hour % 2 == 0`.
+                    (?: \s* % \s* (?P<modulo> -?\d+) )?
                     \s+ (?P<op> \S+)
-                    \s+ (?P<value> \S+)
+                    # The value may be quoted, to compare against a string with spaces.
+                    \s+ (?: "(?P<quoted> [^"]*)" | (?P<value> \S+) )
+                    # An optional trailing comment, e.g. `gold >= 10  # can afford the inn`.
+                    (?: \s+ \# .* )?
                     \s* $
                 "#
             )
@@ -63,6 +257,8 @@ impl<'de> de::Deserialize<'de> for Operation {
                 let mut name: Option<String> = None;
                 let mut op: Option<ComparisonOp> = None;
                 let mut value: Option<Variable> = None;
+                let mut value_ref: Option<String> = None;
+                let mut modulo: Option<i32> = None;
                 while let Some(key) = map.next_key::<String>()? {
                     match key.as_str() {
                         "name" => match name {
@@ -77,6 +273,14 @@ impl<'de> de::Deserialize<'de> for Operation {
                             Some(_) => return Err(de::Error::duplicate_field("value")),
                             None => value = Some(map.next_value()?),
                         },
+                        "value_ref" => match value_ref {
+                            Some(_) => return Err(de::Error::duplicate_field("value_ref")),
+                            None => value_ref = Some(map.next_value()?),
+                        },
+                        "modulo" => match modulo {
+                            Some(_) => return Err(de::Error::duplicate_field("modulo")),
+                            None => modulo = Some(map.next_value()?),
+                        },
                         other => return Err(de::Error::unknown_field(other, FIELDS)),
                     }
                 }
@@ -85,6 +289,8 @@ impl<'de> de::Deserialize<'de> for Operation {
                     name: name.ok_or_else(|| de::Error::missing_field("name"))?,
                     op: op.ok_or_else(|| de::Error::missing_field("op"))?,
                     value: value.ok_or_else(|| de::Error::missing_field("value"))?,
+                    value_ref,
+                    modulo,
                 })
             }
 
@@ -102,6 +308,8 @@ impl<'de> de::Deserialize<'de> for Operation {
                     value: seq
                         .next_element()?
                         .ok_or_else(|| de::Error::invalid_length(2, &self))?,
+                    value_ref: None,
+                    modulo: None,
                 })
             }
 
@@ -109,37 +317,66 @@ impl<'de> de::Deserialize<'de> for Operation {
             where
                 E: de::Error,
             {
-                match RE_CONDITION.captures(s) {
-                    Some(caps) => Ok(Operation {
-                        name: caps["name"].to_owned(),
-                        op: caps["op"].parse().map_err(|_| {
-                            de::Error::invalid_value(
-                                de::Unexpected::Str(&caps["op"]),
-                                &"a valid operator",
-                            )
-                        })?,
-                        value: {
-                            let value = &caps["value"];
-                            value
-                                .parse::<i32>()
-                                .map(Variable::Num)
-                                .or_else(|_| value.parse::<bool>().map(Variable::Bool))
-                                .or_else(|_| value.parse::<String>().map(Variable::Str))
-                                .map_err(|_| {
-                                    de::Error::invalid_value(
-                                        de::Unexpected::Str(value),
-                                        &"a number, boolean, or string",
-                                    )
-                                })?
-                        },
-                    }),
+                let caps = RE_CONDITION.captures(s).ok_or_else(|| {
+                    de::Error::invalid_value(
+                        de::Unexpected::Str(s),
+                        &"a string with the format \"<var> <op> <value>\"",
+                    )
+                })?;
+                let (value, value_ref) = match caps.name("quoted") {
+                    Some(quoted) => (Variable::Str(quoted.as_str().to_owned()), None),
                     None => {
-                        return Err(de::Error::invalid_value(
-                            de::Unexpected::Str(s),
-                            &"a string with the format \"<var> <op> <value>\"",
-                        ))
+                        let value = &caps["value"];
+                        let value = value
+                            .parse::<i32>()
+                            .map(Variable::Num)
+                            .or_else(|_| {
+                                // Only read decimal numbers with a decimal point, so that words
+                                // like `inf` can still refer to variables.
+                                value
+                                    .parse::<f64>()
+                                    .ok()
+                                    .filter(|x| x.is_finite() && value.contains('.'))
+                                    .map(Variable::Float)
+                                    .ok_or(())
+                            })
+                            .or_else(|_| value.parse::<bool>().map(Variable::Bool))
+                            .or_else(|_| value.parse::<String>().map(Variable::Str))
+                            .map_err(|_| {
+                                de::Error::invalid_value(
+                                    de::Unexpected::Str(value),
+                                    &"a number, boolean, or string",
+                                )
+                            })?;
+                        // A bare word may be the name of a variable, which is only known once
+                        // the operation is checked against the declared variables.
+                        let value_ref = match &value {
+                            Variable::Str(name) => Some(name.clone()),
+                            _ => None,
+                        };
+                        (value, value_ref)
                     }
-                }
+                };
+                Ok(Operation {
+                    name: caps["name"].to_owned(),
+                    op: caps["op"].parse().map_err(|_| {
+                        de::Error::invalid_value(
+                            de::Unexpected::Str(&caps["op"]),
+                            &"a valid operator",
+                        )
+                    })?,
+                    value,
+                    value_ref,
+                    modulo: match caps.name("modulo") {
+                        Some(modulo) => Some(modulo.as_str().parse().map_err(|_| {
+                            de::Error::invalid_value(
+                                de::Unexpected::Str(modulo.as_str()),
+                                &"a 32-bit integer",
+                            )
+                        })?),
+                        None => None,
+                    },
+                })
             }
         }
 
@@ -147,7 +384,7 @@ impl<'de> de::Deserialize<'de> for Operation {
     }
 }
 
-#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ComparisonOp {
     #[serde(rename = "==")]
     EQ,
@@ -182,3 +419,95 @@ impl FromStr for ComparisonOp {
         Self::deserialize(s.into_deserializer())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Operation, de::value::Error> {
+        Operation::deserialize(s.into_deserializer())
+    }
+
+    #[test]
+    fn quoted_value_with_spaces() {
+        let operation = parse(r#"name == "King Arthur""#).unwrap();
+        assert_eq!(operation.name, "name");
+        assert_eq!(operation.op, ComparisonOp::EQ);
+        assert_eq!(operation.value, Variable::Str("King Arthur".to_owned()));
+        assert_eq!(operation.value_ref, None);
+    }
+
+    #[test]
+    fn quoted_value_with_comment() {
+        let operation = parse(r#"name != "King Arthur"  # not the king"#).unwrap();
+        assert_eq!(operation.op, ComparisonOp::NEQ);
+        assert_eq!(operation.value, Variable::Str("King Arthur".to_owned()));
+    }
+
+    #[test]
+    fn quoted_value_is_always_a_string() {
+        let operation = parse(r#"name == "10""#).unwrap();
+        assert_eq!(operation.value, Variable::Str("10".to_owned()));
+        assert_eq!(operation.value_ref, None);
+    }
+
+    #[test]
+    fn bare_values_are_still_parsed() {
+        let operation = parse("name == Arthur").unwrap();
+        assert_eq!(operation.value, Variable::Str("Arthur".to_owned()));
+        assert_eq!(operation.value_ref, Some("Arthur".to_owned()));
+
+        let operation = parse("gold >= 10").unwrap();
+        assert_eq!(operation.op, ComparisonOp::GTE);
+        assert_eq!(operation.value, Variable::Num(10));
+
+        let operation = parse("brave == true").unwrap();
+        assert_eq!(operation.value, Variable::Bool(true));
+    }
+
+    #[test]
+    fn modulo() {
+        for s in [
+            "hour % 2 == 0",
+            "hour%2 == 0",
+            "  hour % 2  ==  0  # even hours",
+        ]
+        .iter()
+        {
+            let operation = parse(s).unwrap();
+            assert_eq!(operation.name, "hour", "{}", s);
+            assert_eq!(operation.modulo, Some(2), "{}", s);
+            assert_eq!(operation.op, ComparisonOp::EQ, "{}", s);
+            assert_eq!(operation.value, Variable::Num(0), "{}", s);
+        }
+        assert_eq!(parse("hour == 0").unwrap().modulo, None);
+        assert!(parse("hour % two == 0").is_err());
+    }
+
+    #[test]
+    fn modulo_lhs_is_never_negative() {
+        let operation = parse("hour % 3 == 0").unwrap();
+        assert_eq!(operation.lhs(&Variable::Num(7)), Some(Variable::Num(1)));
+        assert_eq!(operation.lhs(&Variable::Num(-7)), Some(Variable::Num(2)));
+        assert_eq!(
+            operation.lhs(&Variable::Num(i32::MIN)),
+            Some(Variable::Num(1))
+        );
+    }
+
+    #[test]
+    fn modulo_lhs_with_non_positive_modulo_is_none() {
+        // Parsing rejects these, but operations can also be built directly.
+        for modulo in [0, -1, -3].iter() {
+            let mut operation = parse("hour % 2 == 0").unwrap();
+            operation.modulo = Some(*modulo);
+            assert_eq!(operation.lhs(&Variable::Num(i32::MIN)), None, "{}", modulo);
+        }
+    }
+
+    #[test]
+    fn unquoted_value_with_spaces_is_an_error() {
+        assert!(parse("name == King Arthur").is_err());
+        assert!(parse(r#"name == "King Arthur"#).is_err());
+    }
+}
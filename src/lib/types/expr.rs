@@ -0,0 +1,246 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use serde::de;
+
+/// An arithmetic expression over number variables, e.g. `gold + silver * 0.1`.
+///
+/// Supports number literals, variable names, parentheses, unary `-`, and the binary operators
+/// `+`, `-`, `*`, `/`, and `%`, with the usual precedence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    BinOp(Box<Expr>, BinOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Expr {
+    /// Evaluates the expression, using `lookup` to get the values of variables.
+    ///
+    /// Returns [`None`](Option::None) if a variable can't be found or the result is not a finite
+    /// number (e.g. after dividing by zero).
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        let value = match self {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => lookup(name)?,
+            Expr::Neg(expr) => -expr.eval(lookup)?,
+            Expr::BinOp(lhs, op, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup)?, rhs.eval(lookup)?);
+                match op {
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div => lhs / rhs,
+                    BinOp::Rem => lhs % rhs,
+                }
+            }
+        };
+        if value.is_finite() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the names of all variables referenced in the expression, in order of appearance.
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Expr::Num(_) => Vec::new(),
+            Expr::Var(name) => vec![name.as_str()],
+            Expr::Neg(expr) => expr.variables(),
+            Expr::BinOp(lhs, _, rhs) => {
+                let mut names = lhs.variables();
+                names.extend(rhs.variables());
+                names
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Var(name) => f.write_str(name),
+            Expr::Neg(expr) => write!(f, "-{}", expr),
+            Expr::BinOp(lhs, op, rhs) => {
+                let op = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                    BinOp::Rem => "%",
+                };
+                write!(f, "({} {} {})", lhs, op, rhs)
+            }
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = ExprParser {
+            src: s,
+            chars: s.char_indices().peekable(),
+        };
+        let expr = parser.parse_sum()?;
+        match parser.next_token() {
+            None => Ok(expr),
+            Some((i, _)) => Err(format!("unexpected '{}' in expression '{}'", &s[i..], s)),
+        }
+    }
+}
+
+/// Recursive descent parser for [`Expr`].
+struct ExprParser<'a> {
+    src: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> ExprParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some((_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn next_token(&mut self) -> Option<(usize, char)> {
+        self.skip_whitespace();
+        self.chars.next()
+    }
+
+    fn err_unexpected_end(&self) -> String {
+        format!("unexpected end of expression '{}'", self.src)
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn parse_sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_product()?;
+        loop {
+            let op = match self.peek_char() {
+                Some('+') => BinOp::Add,
+                Some('-') => BinOp::Sub,
+                _ => return Ok(expr),
+            };
+            self.chars.next();
+            expr = Expr::BinOp(Box::new(expr), op, Box::new(self.parse_product()?));
+        }
+    }
+
+    // product := unary (('*' | '/' | '%') unary)*
+    fn parse_product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op = match self.peek_char() {
+                Some('*') => BinOp::Mul,
+                Some('/') => BinOp::Div,
+                Some('%') => BinOp::Rem,
+                _ => return Ok(expr),
+            };
+            self.chars.next();
+            expr = Expr::BinOp(Box::new(expr), op, Box::new(self.parse_unary()?));
+        }
+    }
+
+    // unary := '-' unary | atom
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.peek_char() == Some('-') {
+            self.chars.next();
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        self.parse_atom()
+    }
+
+    // atom := number | name | '(' sum ')'
+    fn parse_atom(&mut self) -> Result<Expr, String> {
+        let (start, c) = self.next_token().ok_or_else(|| self.err_unexpected_end())?;
+        if c == '(' {
+            let expr = self.parse_sum()?;
+            return match self.next_token() {
+                Some((_, ')')) => Ok(expr),
+                Some((i, _)) => Err(format!(
+                    "expected ')' but found '{}' in expression '{}'",
+                    &self.src[i..],
+                    self.src
+                )),
+                None => Err(self.err_unexpected_end()),
+            };
+        }
+
+        let is_num = c.is_ascii_digit() || c == '.';
+        if !(is_num || c.is_alphabetic() || c == '_') {
+            return Err(format!(
+                "unexpected '{}' in expression '{}'",
+                &self.src[start..],
+                self.src
+            ));
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = self.chars.peek() {
+            let continues = if is_num {
+                c.is_ascii_digit() || c == '.'
+            } else {
+                c.is_alphanumeric() || c == '_'
+            };
+            if !continues {
+                break;
+            }
+            end = i + c.len_utf8();
+            self.chars.next();
+        }
+
+        let token = &self.src[start..end];
+        if is_num {
+            token
+                .parse()
+                .map(Expr::Num)
+                .map_err(|_| format!("invalid number '{}' in expression '{}'", token, self.src))
+        } else {
+            Ok(Expr::Var(token.to_string()))
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Expr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct ExprVisitor;
+
+        impl<'de> de::Visitor<'de> for ExprVisitor {
+            type Value = Expr;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an arithmetic expression (e.g. \"gold + silver * 0.1\")")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                s.parse().map_err(de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_str(ExprVisitor)
+    }
+}
@@ -27,10 +27,45 @@ impl PartialEq for ItemDef {
 
 impl Eq for ItemDef {}
 
+/// A limit on how much the player can carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InventoryLimit {
+    pub max: usize,
+    pub mode: LimitMode,
+    pub overflow: InventoryOverflow,
+}
+
+/// What an [`InventoryLimit`] counts.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LimitMode {
+    /// Every item held, counting each copy of an item separately.
+    #[default]
+    #[serde(rename = "items")]
+    Items,
+    /// Distinct kinds of items held, however many copies there are of each.
+    #[serde(rename = "stacks")]
+    Stacks,
+}
+
+/// What happens when acquiring an item would exceed an [`InventoryLimit`].
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryOverflow {
+    /// The item isn't acquired, and the player is told why.
+    #[default]
+    #[serde(rename = "reject")]
+    Reject,
+    /// The oldest item is dropped to make room: the oldest copy of any item when counting items,
+    /// or every copy of the oldest item when counting stacks.
+    #[serde(rename = "drop-oldest")]
+    DropOldest,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Item {
     def: Rc<ItemDef>,
     used: i32,
+    /// When the item was acquired, relative to the other items in the game.
+    acquired: u64,
 }
 
 impl Item {
@@ -38,6 +73,7 @@ impl Item {
         Item {
             def: Rc::clone(def),
             used: 0,
+            acquired: 0,
         }
     }
 
@@ -54,12 +90,23 @@ impl Item {
         &self.def.effect
     }
 
+    pub fn acquired(&self) -> u64 {
+        self.acquired
+    }
+    pub fn set_acquired(&mut self, acquired: u64) {
+        self.acquired = acquired;
+    }
+
     pub fn used(&self) -> i32 {
         self.used
     }
     pub fn uses_left(&self) -> Option<i32> {
         self.def.max_uses.map(|uses| uses - self.used)
     }
+    /// Returns `true` if the item has a `max_uses` and all of them have been used.
+    pub fn is_depleted(&self) -> bool {
+        self.uses_left() == Some(0)
+    }
     pub fn mod_uses(&mut self, n: i32) -> i32 {
         let max = self.def.max_uses.unwrap_or(i32::MAX);
         let used = clamp(self.used + n, 0, max);
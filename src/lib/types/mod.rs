@@ -1,32 +1,71 @@
 mod condition;
+mod expr;
 pub mod item;
 mod variable;
+mod version;
 
 use std::cell::RefCell;
 use std::fmt;
+use std::path::PathBuf;
 use std::rc::{Rc, Weak};
 
 use either::{Either, Either::*};
 use serde::de;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::utils;
 
 pub use self::condition::*;
-pub use self::item::{Item, ItemDef};
+pub use self::expr::{BinOp, Expr};
+pub use self::item::{InventoryLimit, InventoryOverflow, Item, ItemDef, LimitMode};
 pub use self::variable::*;
+pub use self::version::Version;
 
 pub type PageID = String;
 
 /// A page in a story.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Page {
+    /// The page's ID. If omitted in the story file, it may be derived from the file's path (see
+    /// [`page_id_from_path`](crate::parser::page_id_from_path)).
+    #[serde(default)]
     pub id: PageID,
     pub title: Option<String>,
     pub content: String,
     pub prompt: Option<String>,
+    #[serde(default)]
     pub links: Vec<Link>,
+    /// Names of link templates from the settings whose links are added after the page's own
+    /// `links`.
+    #[serde(default)]
+    pub include_links: Vec<String>,
+    /// The author of the page, if it differs from the author of the story.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Optional descriptive text that the player can view without leaving the page.
+    #[serde(default)]
+    pub examine: Option<String>,
+    /// Free-form labels, used for tooling, theming, and conditions.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether entering this page saves a checkpoint that the player can return to.
+    #[serde(default)]
+    pub checkpoint: bool,
     #[serde(skip)]
     pub parents: Vec<Weak<RefCell<Page>>>,
+    /// Where the page was defined, if it was read from a file.
+    #[serde(skip)]
+    pub source: Option<PageSource>,
+}
+
+/// The location of a [`Page`] in the story files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSource {
+    /// The path of the file containing the page.
+    pub path: PathBuf,
+    /// The position of the page among the pages in the file, starting from 0.
+    pub index: usize,
 }
 
 impl Page {
@@ -43,13 +82,19 @@ impl Page {
             content: content.into(),
             prompt,
             links,
+            include_links: Vec::new(),
+            author: None,
+            examine: None,
+            tags: Vec::new(),
+            checkpoint: false,
             parents: Vec::new(),
+            source: None,
         }
     }
 }
 
 /// A link to somewhere else in the story, plus any associated actions.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Link {
     pub text: String,
@@ -61,18 +106,89 @@ pub struct Link {
     pub triggers: Vec<LinkTrigger>,
     #[serde(default)]
     pub actions: Vec<LinkAction>,
+    /// The name of a style to show the link's text in, either a color or a text effect (see
+    /// [`parse_style`](crate::utils::parse_style)).
+    #[serde(default, deserialize_with = "deserialize_link_style")]
+    pub style: Option<String>,
+    /// Text to show before the link's text, e.g. `"+"` for links that grant an item.
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+fn deserialize_link_style<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let style = Option::<String>::deserialize(deserializer)?;
+    // Ensure the style is one that can be displayed.
+    if let Some(style) = &style {
+        if utils::parse_style(style).is_none() {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(style),
+                &"a text effect (e.g. \"bold\", \"italic\"), color name (e.g. \"red\"), \
+                or hex code (e.g. \"#ff0000\")",
+            ));
+        }
+    }
+    Ok(style)
+}
+
+impl Link {
+    /// Creates a link with no requirements, triggers, or actions.
+    pub fn new<S: Into<String>>(text: S, dest: LinkDest) -> Self {
+        Link {
+            text: text.into(),
+            dest,
+            requires: None,
+            triggers: Vec::new(),
+            actions: Vec::new(),
+            style: None,
+            icon: None,
+        }
+    }
 }
 
 /// The destination of a link.
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub enum LinkDest {
-    #[serde(rename = "page", deserialize_with = "deserialize_link_dest_page")]
+    #[serde(
+        rename = "page",
+        deserialize_with = "deserialize_link_dest_page",
+        serialize_with = "serialize_link_dest_page"
+    )]
     Page(Either<PageID, Rc<RefCell<Page>>>),
     #[serde(rename = "current")]
     CurrentPage,
     #[serde(rename = "previous")]
     PrevPage,
+    /// Go back the given number of pages in the history, or as far as possible if the history is
+    /// shorter than that.
+    #[serde(rename = "back")]
+    PrevPages(usize),
+    /// Go to the story's starting page, recording the current page in the history so the player
+    /// can go back.
+    #[serde(rename = "start")]
+    StartPage,
+    /// Go to the story's starting page and clear the history.
+    #[serde(rename = "start-over")]
+    StartOver,
+    /// Go to one of the given pages, chosen at random.
+    #[serde(
+        rename = "random",
+        deserialize_with = "deserialize_link_dest_pages",
+        serialize_with = "serialize_link_dest_pages"
+    )]
+    Random(Vec<Either<PageID, Rc<RefCell<Page>>>>),
+    /// Go to the page whose ID is the current value of the given string variable. If the value
+    /// isn't the ID of a page, the player stays on the current page.
+    #[serde(rename = "page-from-var")]
+    PageFromVar(String),
+    /// Return to the last checkpoint, restoring the page, variables, and items as they were then
+    /// (see [`Game::restore_checkpoint`](crate::app::Game::restore_checkpoint)). If no checkpoint
+    /// has been saved, the player stays on the current page.
+    #[serde(rename = "checkpoint")]
+    Checkpoint,
     #[serde(rename = "end")]
     EndGame(String),
 }
@@ -100,6 +216,43 @@ where
     deserializer.deserialize_string(LinkDestPageVisitor)
 }
 
+/// A page that a [`LinkDest`] refers to, either by ID or, once resolved, directly.
+type ToPage = Either<PageID, Rc<RefCell<Page>>>;
+
+fn deserialize_link_dest_pages<'de, D>(deserializer: D) -> Result<Vec<ToPage>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let page_ids = Vec::<PageID>::deserialize(deserializer)?;
+    if page_ids.is_empty() {
+        return Err(de::Error::invalid_length(0, &"at least 1 page ID"));
+    }
+    Ok(page_ids.into_iter().map(Left).collect())
+}
+
+fn serialize_link_dest_pages<S>(to_pages: &[ToPage], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(to_pages.iter().map(|to_page| match to_page {
+        Left(page_id) => page_id.clone(),
+        Right(page) => page.borrow().id.clone(),
+    }))
+}
+
+fn serialize_link_dest_page<S>(
+    to_page: &Either<PageID, Rc<RefCell<Page>>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match to_page {
+        Left(page_id) => serializer.serialize_str(page_id),
+        Right(page) => serializer.serialize_str(&page.borrow().id),
+    }
+}
+
 impl Default for LinkDest {
     fn default() -> Self {
         LinkDest::CurrentPage
@@ -115,12 +268,31 @@ impl fmt::Display for LinkDest {
             },
             LinkDest::CurrentPage => f.write_str("<current page>"),
             LinkDest::PrevPage => f.write_str("<previous page>"),
+            LinkDest::PrevPages(n) => write!(f, "<back {} pages>", n),
+            LinkDest::StartPage | LinkDest::StartOver => f.write_str("<start>"),
+            LinkDest::Random(to_pages) => {
+                let page_ids: Vec<_> = to_pages
+                    .iter()
+                    .map(|to_page| match to_page {
+                        Left(page_id) => page_id.clone(),
+                        Right(page) => page.borrow().id.clone(),
+                    })
+                    .collect();
+                write!(f, "<random: {}>", page_ids.join(", "))
+            }
+            LinkDest::PageFromVar(name) => write!(f, "<page from {}>", name),
+            LinkDest::Checkpoint => f.write_str("<checkpoint>"),
             LinkDest::EndGame(_) => f.write_str("<end game>"),
         }
     }
 }
 
 impl LinkDest {
+    /// Creates a destination that links directly to `page`, without needing to resolve its ID.
+    pub fn to_page(page: &Rc<RefCell<Page>>) -> Self {
+        LinkDest::Page(Right(Rc::clone(page)))
+    }
+
     pub fn get_page(&self) -> Option<Rc<RefCell<Page>>> {
         if let LinkDest::Page(maybe_page) = self {
             return Some(Rc::clone(
@@ -133,38 +305,192 @@ impl LinkDest {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct LinkTrigger {
     pub condition: Condition,
     pub actions: Vec<LinkAction>,
+    /// Actions to run instead of `actions` if the condition is not met.
+    #[serde(default)]
+    pub else_actions: Vec<LinkAction>,
+    /// If true and this trigger's condition is met, skip any remaining triggers on the link.
+    #[serde(default)]
+    pub stop: bool,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub enum LinkAction {
     #[serde(rename = "set-var")]
     SetVar { name: String, value: Variable },
+    /// Set a variable to the current value of the variable `from`, which must have the same type.
+    #[serde(rename = "set-var-from")]
+    SetVarFrom { name: String, from: String },
+    /// Append `value` to the end of a string variable.
+    #[serde(rename = "append-str")]
+    AppendStr { name: String, value: String },
     #[serde(rename = "mod-num")]
     ModNum { name: String, value: i32 },
+    /// Add the current value of the number variable `by` to the number variable `name`, or
+    /// subtract it if `negate` is true, e.g. to spend `price` gold.
+    #[serde(rename = "mod-num-by-var")]
+    ModNumByVar {
+        name: String,
+        by: String,
+        #[serde(default)]
+        negate: bool,
+    },
+    /// Add `value` to a decimal number variable.
+    #[serde(rename = "mod-float")]
+    ModFloat { name: String, value: f64 },
     #[serde(rename = "toggle-bool")]
     ToggleBool(String),
+    /// Set a number variable to a random number in the inclusive range `[min, max]`, e.g. to roll
+    /// a die.
+    #[serde(rename = "roll")]
+    Roll { name: String, min: i32, max: i32 },
     #[serde(rename = "set-dest")]
     SetDest(LinkDest),
     #[serde(rename = "prompt")]
     Prompt(Prompt),
+    #[serde(rename = "add-note")]
+    AddNote(Note),
     #[serde(rename = "acquire-item")]
     AcquireItem(String),
     #[serde(rename = "drop-item")]
     DropItem(String),
     #[serde(rename = "use-item")]
     UseItem(String),
+    /// Save a checkpoint of the game as it is when the action runs, on the page the link is on,
+    /// replacing any earlier checkpoint. A `checkpoint` destination returns to it.
+    #[serde(rename = "set-checkpoint")]
+    SetCheckpoint,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// A note to add to the player's journal.
+///
+/// A note may be given as just its text (e.g. `add-note: "The butler lied."`), or with a map of
+/// options (e.g. `add-note: { text: "The butler lied.", once: true }`).
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub text: String,
+    /// If true, the note is not added if the journal already has a note with the same text, after
+    /// interpolation.
+    pub once: bool,
+}
+
+impl<'de> de::Deserialize<'de> for Note {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Options {
+            text: String,
+            #[serde(default)]
+            once: bool,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(String),
+            Options(Options),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => Note { text, once: false },
+            Repr::Options(Options { text, once }) => Note { text, once },
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Prompt {
     pub text: String,
     #[serde(default)]
     pub variable: Option<String>,
+    /// Buttons to show instead of the default "Ok" button, for prompts without a `variable`.
+    #[serde(default)]
+    pub buttons: Vec<PromptButton>,
+    /// A condition that must hold when the prompt is about to be shown, or else it's skipped.
+    #[serde(default)]
+    pub requires: Option<Condition>,
+    /// The smallest number the player may enter, for prompts with a number `variable`.
+    #[serde(default)]
+    pub min: Option<f64>,
+    /// The largest number the player may enter, for prompts with a number `variable`.
+    #[serde(default)]
+    pub max: Option<f64>,
+    /// Values for the player to choose from instead of typing one in, for prompts with a
+    /// `variable`.
+    #[serde(default)]
+    pub choices: Vec<String>,
+}
+
+impl Prompt {
+    /// Creates a prompt that just tells the player something, e.g. why an action had no effect.
+    pub fn info<S: Into<String>>(text: S) -> Self {
+        Prompt {
+            text: text.into(),
+            variable: None,
+            buttons: Vec::new(),
+            requires: None,
+            min: None,
+            max: None,
+            choices: Vec::new(),
+        }
+    }
+
+    /// Checks that `value` is one of the prompt's `choices` and within its `min` and `max`, if it
+    /// has them, returning a message for the player if it isn't.
+    pub fn check_value(&self, value: &Variable) -> Result<(), String> {
+        let is_choice = |choice: &String| value.type_().parse(choice).as_ref() == Some(value);
+        if !self.choices.is_empty() && !self.choices.iter().any(is_choice) {
+            return Err(format!(
+                "Please choose one of: {}.",
+                self.choices.join(", ")
+            ));
+        }
+        if let Some(n) = value.as_f64() {
+            match (self.min, self.max) {
+                (Some(min), Some(max)) if n < min || n > max => {
+                    return Err(format!("Please enter a number from {} to {}.", min, max))
+                }
+                (Some(min), None) if n < min => {
+                    return Err(format!("Please enter a number of at least {}.", min))
+                }
+                (None, Some(max)) if n > max => {
+                    return Err(format!("Please enter a number of at most {}.", max))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A button in a [`Prompt`], which either dismisses the prompt or follows a link.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PromptButton {
+    pub label: String,
+    /// The index of the link on the current page to follow when the button is pressed. If not
+    /// given, the button just dismisses the prompt.
+    #[serde(default)]
+    pub link: Option<usize>,
+}
+
+/// A step in the character creation sequence, which runs before the story starts.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub enum CreationStep {
+    /// Show a prompt, optionally asking the player for the value of a variable.
+    #[serde(rename = "prompt")]
+    Prompt(Prompt),
+    /// Ask the player to choose one of several items to start with.
+    #[serde(rename = "choose-item")]
+    ChooseItem { text: String, items: Vec<String> },
 }
@@ -4,10 +4,15 @@ use std::str::FromStr;
 use serde::de::{self, IntoDeserializer};
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+use super::LinkAction;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Variable {
     Num(i32),
+    /// A decimal number, written with a decimal point (e.g. `0.5`, `10.0`) to tell it apart from
+    /// a whole number.
+    Float(f64),
     Bool(bool),
     Str(String),
 }
@@ -16,6 +21,8 @@ impl fmt::Display for Variable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match self {
             Variable::Num(value) => value.to_string(),
+            // Always show the decimal point, e.g. `10.0` rather than `10`.
+            Variable::Float(value) => format!("{:?}", value),
             Variable::Bool(value) => value.to_string(),
             Variable::Str(value) => format!("\"{}\"", value.escape_default()),
         };
@@ -34,6 +41,7 @@ impl Variable {
     pub fn type_(&self) -> VarType {
         match self {
             Variable::Num(_) => VarType::Num,
+            Variable::Float(_) => VarType::Float,
             Variable::Bool(_) => VarType::Bool,
             Variable::Str(_) => VarType::Str,
         }
@@ -42,21 +50,251 @@ impl Variable {
     pub fn type_eq(&self, other: &Variable) -> bool {
         self.type_() == other.type_()
     }
+
+    /// Returns the value of a whole or decimal number as an `f64`, or `None` for other types.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Variable::Num(value) => Some(*value as f64),
+            Variable::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VarType {
     Num,
+    Float,
     Bool,
     Str,
 }
 
+impl VarType {
+    /// Returns true for whole and decimal numbers.
+    pub fn is_numeric(self) -> bool {
+        matches!(self, VarType::Num | VarType::Float)
+    }
+
+    /// Parses `input` from the player as a value of this type, or returns `None` if it isn't one.
+    ///
+    /// Strings are taken as-is, so e.g. `true` is a valid string rather than a boolean.
+    pub fn parse(self, input: &str) -> Option<Variable> {
+        match self {
+            VarType::Str => Some(Variable::Str(input.to_owned())),
+            VarType::Num => input.trim().parse().ok().map(Variable::Num),
+            VarType::Float => input
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|x| x.is_finite())
+                .map(Variable::Float),
+            VarType::Bool => input.trim().parse().ok().map(Variable::Bool),
+        }
+    }
+
+    /// Returns whether values of this type can be compared with values of type `other`, i.e. if
+    /// the types are the same or both numeric.
+    pub fn is_comparable_to(self, other: VarType) -> bool {
+        self == other || (self.is_numeric() && other.is_numeric())
+    }
+}
+
 impl fmt::Display for VarType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str(match self {
             VarType::Num => "number",
+            VarType::Float => "decimal number",
             VarType::Bool => "boolean",
             VarType::Str => "string",
         })
     }
 }
+
+/// The declaration of a variable in the settings file.
+///
+/// A variable may be declared with just its initial value (e.g. `gold: 10`), or with a map of
+/// options (e.g. `strength: { initial: { random: [1, 6] } }`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDef {
+    pub initial: InitialValue,
+    /// An inclusive range `[min, max]` that a number variable wraps around when modified, instead
+    /// of being clamped.
+    pub wrap: Option<(i32, i32)>,
+    /// Text to render in templates in place of a string variable while its value is empty, e.g.
+    /// for optional state such as a nickname that the player may not have been given yet.
+    pub render_default: Option<String>,
+}
+
+/// A number variable that counts down by one each time a link takes the player to another page,
+/// e.g. the turns left before a bomb explodes. Timers stop counting down at zero.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TimerDef {
+    /// Actions to run when the timer reaches zero, e.g. a `set-dest` to a game-over page.
+    #[serde(default)]
+    pub on_zero: Vec<LinkAction>,
+    /// Whether links that go back wind the timer back by one for each page gone back, rather than
+    /// counting down as for any other page change.
+    #[serde(default)]
+    pub rewind_on_back: bool,
+}
+
+/// The initial value of a variable.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum InitialValue {
+    /// A literal value.
+    Value(Variable),
+    /// A random number in the inclusive range `[min, max]`, rolled when the game starts.
+    Random { random: (i32, i32) },
+}
+
+impl VariableDef {
+    pub fn type_(&self) -> VarType {
+        match &self.initial {
+            InitialValue::Value(value) => value.type_(),
+            InitialValue::Random { .. } => VarType::Num,
+        }
+    }
+
+    /// Returns `value`, wrapped around into this variable's `wrap` range if it has one.
+    pub fn wrapped(&self, value: i64) -> i64 {
+        match self.wrap {
+            Some((min, max)) => {
+                let (min, max) = (min as i64, max as i64);
+                min + (value - min).rem_euclid(max - min + 1)
+            }
+            None => value,
+        }
+    }
+}
+
+impl From<Variable> for VariableDef {
+    fn from(value: Variable) -> Self {
+        VariableDef {
+            initial: InitialValue::Value(value),
+            wrap: None,
+            render_default: None,
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for VariableDef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Options {
+            initial: InitialValue,
+            #[serde(default)]
+            wrap: Option<(i32, i32)>,
+            #[serde(default)]
+            render_default: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Initial(InitialValue),
+            Options(Options),
+        }
+
+        let def = match Repr::deserialize(deserializer)? {
+            Repr::Initial(initial) => VariableDef {
+                initial,
+                wrap: None,
+                render_default: None,
+            },
+            Repr::Options(Options {
+                initial,
+                wrap,
+                render_default,
+            }) => VariableDef {
+                initial,
+                wrap,
+                render_default,
+            },
+        };
+
+        // Ensure random ranges are non-empty.
+        if let InitialValue::Random { random: (min, max) } = def.initial {
+            if min > max {
+                return Err(de::Error::invalid_value(
+                    de::Unexpected::Signed(min as i64),
+                    &format!("a minimum no greater than the maximum ({})", max).as_str(),
+                ));
+            }
+        }
+
+        // Ensure wrap ranges are non-empty and only given for numbers.
+        if let Some((min, max)) = def.wrap {
+            if def.type_() != VarType::Num {
+                return Err(de::Error::custom(format!(
+                    "wrap is only allowed for numbers, but the variable is a {}",
+                    def.type_()
+                )));
+            }
+            if min > max {
+                return Err(de::Error::invalid_value(
+                    de::Unexpected::Signed(min as i64),
+                    &format!("a minimum no greater than the maximum ({})", max).as_str(),
+                ));
+            }
+        }
+
+        // Ensure render defaults are only given for strings, the only type that can be empty.
+        if def.render_default.is_some() && def.type_() != VarType::Str {
+            return Err(de::Error::custom(format!(
+                "render_default is only allowed for strings, but the variable is a {}",
+                def.type_()
+            )));
+        }
+
+        Ok(def)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn str_takes_input_as_is() {
+        for input in ["true", "5", "1.0", " 5 ", "King Arthur"].iter() {
+            assert_eq!(
+                VarType::Str.parse(input),
+                Some(Variable::Str(input.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn num_takes_whole_numbers_only() {
+        assert_eq!(VarType::Num.parse("5"), Some(Variable::Num(5)));
+        assert_eq!(VarType::Num.parse(" 5 "), Some(Variable::Num(5)));
+        assert_eq!(VarType::Num.parse("-3"), Some(Variable::Num(-3)));
+        assert_eq!(VarType::Num.parse("1.0"), None);
+        assert_eq!(VarType::Num.parse("true"), None);
+        assert_eq!(VarType::Num.parse("five"), None);
+    }
+
+    #[test]
+    fn float_takes_finite_numbers() {
+        assert_eq!(VarType::Float.parse("1.5"), Some(Variable::Float(1.5)));
+        assert_eq!(VarType::Float.parse(" 5 "), Some(Variable::Float(5.0)));
+        assert_eq!(VarType::Float.parse("inf"), None);
+        assert_eq!(VarType::Float.parse("NaN"), None);
+        assert_eq!(VarType::Float.parse("true"), None);
+    }
+
+    #[test]
+    fn bool_takes_true_or_false() {
+        assert_eq!(VarType::Bool.parse("true"), Some(Variable::Bool(true)));
+        assert_eq!(VarType::Bool.parse(" false "), Some(Variable::Bool(false)));
+        assert_eq!(VarType::Bool.parse("True"), None);
+        assert_eq!(VarType::Bool.parse("1"), None);
+        assert_eq!(VarType::Bool.parse("yes"), None);
+    }
+}
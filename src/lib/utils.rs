@@ -1,5 +1,11 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use cursive::theme::{Color, Effect, Style};
 use num_traits::{clamp, AsPrimitive};
 
 macro_rules! item_for_each {
@@ -25,6 +31,38 @@ pub fn is_parent_path(l: &PathBuf, r: &PathBuf) -> bool {
     false
 }
 
+/// Returns the directory for files the app keeps between runs (e.g. logs), following platform
+/// conventions (e.g. `$XDG_DATA_HOME/storygamer` on Linux), or in the temp directory if there is
+/// no such directory.
+pub fn data_dir() -> PathBuf {
+    app_dir(dirs::data_local_dir())
+}
+
+/// Returns the directory for files the app can regenerate (e.g. parsed story caches), following
+/// platform conventions (e.g. `$XDG_CACHE_HOME/storygamer` on Linux), or in the temp directory if
+/// there is no such directory.
+pub fn cache_dir() -> PathBuf {
+    app_dir(dirs::cache_dir())
+}
+
+fn app_dir(base_dir: Option<PathBuf>) -> PathBuf {
+    base_dir.unwrap_or_else(env::temp_dir).join(progname!())
+}
+
+/// Parses a style name, which is either a text effect (`bold`, `italic`, `underline`, `reverse`,
+/// or `strikethrough`) or a color name or hex code (e.g. `red`, `light blue`, `#ff0000`).
+pub fn parse_style(name: &str) -> Option<Style> {
+    let effect = match name {
+        "bold" => Effect::Bold,
+        "italic" => Effect::Italic,
+        "underline" => Effect::Underline,
+        "reverse" => Effect::Reverse,
+        "strikethrough" => Effect::Strikethrough,
+        _ => return Color::parse(name).map(Style::from),
+    };
+    Some(Style::from(effect))
+}
+
 pub fn shorten_path<P: AsRef<Path>>(path: P) -> PathBuf {
     let path = path.as_ref();
     dirs::home_dir()
@@ -69,3 +107,91 @@ item_for_each! {
         };
     }
 }
+
+/// A small, seedable pseudo-random number generator (xorshift64*).
+///
+/// Not suitable for cryptographic use, but more than random enough for dice rolls.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a new `Rng` from the given seed. The same seed always yields the same sequence.
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed with a round of splitmix64, since xorshift needs a nonzero state
+        // and performs poorly on seeds with few bits set.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng { state: z.max(1) }
+    }
+
+    /// Create a new `Rng` seeded from the system clock and the process's hash randomization.
+    pub fn from_entropy() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        if let Ok(elapsed) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            hasher.write_u128(elapsed.as_nanos());
+        }
+        Rng::new(hasher.finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Generate a uniformly distributed number in the given range.
+    ///
+    /// Panics if the range is empty, i.e. if its end is less than its start.
+    pub fn gen_range(&mut self, range: RangeInclusive<i32>) -> i32 {
+        let (start, end) = range.into_inner();
+        assert!(start <= end, "empty range: {}..={}", start, end);
+        let span = (end as i64 - start as i64) as u64 + 1;
+        // Reject values from the incomplete final "bucket" to avoid modulo bias.
+        let zone = u64::MAX - (u64::MAX % span);
+        loop {
+            let n = self.next_u64();
+            if n < zone {
+                return (start as i64 + (n % span) as i64) as i32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_with_same_seed_repeats() {
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        for _ in 0..100 {
+            assert_eq!(a.gen_range(1..=6), b.gen_range(1..=6));
+        }
+    }
+
+    #[test]
+    fn gen_range_stays_in_range() {
+        let mut rng = Rng::new(0);
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            let n = rng.gen_range(1..=6);
+            assert!((1..=6).contains(&n), "{}", n);
+            seen[n as usize - 1] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+        assert_eq!(rng.gen_range(3..=3), 3);
+        let n = rng.gen_range(i32::MIN..=i32::MAX);
+        assert!((i32::MIN..=i32::MAX).contains(&n));
+    }
+
+    #[test]
+    #[should_panic]
+    fn gen_range_panics_on_empty_range() {
+        Rng::new(0).gen_range(1..=0);
+    }
+}
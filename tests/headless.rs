@@ -0,0 +1,94 @@
+use storygamer::app::Headless;
+use storygamer::errors::Error;
+use storygamer::types::Variable;
+
+const STORY: &str = "tests/fixtures/crossroads/Storygame.yaml";
+
+fn load() -> Headless {
+    Headless::load(STORY).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[test]
+fn plays_to_an_ending() {
+    let mut headless = load();
+    assert_eq!(headless.current_title().as_deref(), Some("The Crossroads"));
+    assert_eq!(headless.choices(), vec!["Enter the cave", "Go home"]);
+
+    assert_eq!(headless.choose(0).unwrap(), None);
+    assert_eq!(headless.current_title().as_deref(), Some("The Cave"));
+    assert_eq!(
+        headless.prompt().map(|prompt| prompt.text.as_str()),
+        Some("Who goes there?")
+    );
+    headless.submit_prompt("Arthur").unwrap();
+    assert!(headless.prompt().is_none());
+    assert_eq!(
+        headless.current_text().source(),
+        "It's dark in here, Arthur.\n"
+    );
+
+    assert_eq!(
+        headless.choose(0).unwrap().as_deref(),
+        Some("Arthur found the treasure.")
+    );
+}
+
+#[test]
+fn goes_back_and_takes_another_path() {
+    let mut headless = load();
+    headless.choose(0).unwrap();
+    headless.submit_prompt("Arthur").unwrap();
+    assert_eq!(headless.choose(1).unwrap(), None);
+    assert_eq!(headless.current_title().as_deref(), Some("The Crossroads"));
+
+    assert_eq!(headless.choose(1).unwrap(), None);
+    assert_eq!(
+        headless.choose(0).unwrap().as_deref(),
+        Some("You stayed home.")
+    );
+}
+
+#[test]
+fn choose_fails_while_a_prompt_is_waiting() {
+    let mut headless = load();
+    headless.choose(0).unwrap();
+    assert!(headless.choose(0).is_err());
+    assert_eq!(headless.current_title().as_deref(), Some("The Cave"));
+
+    headless.submit_prompt("Arthur").unwrap();
+    assert!(headless.choose(0).unwrap().is_some());
+}
+
+#[test]
+fn locked_links_are_not_choices() {
+    let mut headless = load();
+    assert_eq!(headless.choices(), vec!["Enter the cave", "Go home"]);
+    assert!(headless.choose(2).is_err());
+
+    headless
+        .game
+        .variables
+        .insert("brave".to_owned(), Variable::Bool(true));
+    assert_eq!(
+        headless.choices(),
+        vec!["Enter the cave", "Go home", "Climb the cliff"]
+    );
+    assert_eq!(
+        headless.choose(2).unwrap().as_deref(),
+        Some("You reached the summit.")
+    );
+}
+
+#[test]
+fn following_a_locked_link_is_an_error() {
+    let mut headless = load();
+    let err = headless.game.follow_link(2).unwrap_err();
+    assert!(matches!(err, Error::LinkLocked { .. }), "{}", err);
+    assert_eq!(headless.current_title().as_deref(), Some("The Crossroads"));
+}
+
+#[test]
+fn choose_fails_for_a_missing_choice() {
+    let mut headless = load();
+    assert!(headless.choose(2).is_err());
+}